@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::utils::parse::default_last_month_timestamp;
+
+/// Upper edges of the game time windows in seconds, the last window is open-ended
+const GAME_TIME_WINDOW_EDGES: [u32; 5] = [600, 900, 1200, 1500, 1800];
+/// Upper edges of the net worth brackets, the last bracket is open-ended
+const NET_WORTH_BRACKET_EDGES: [u32; 3] = [5000, 10000, 20000];
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_min_window_matches() -> Option<u64> {
+    1000.into()
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_min_item_matches() -> Option<u64> {
+    500.into()
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams, Eq, PartialEq, Hash, Default)]
+pub(super) struct HeroPowerspikeQuery {
+    /// Hero ID to detect the power spike for. See more: <https://assets.deadlock-api.com/v2/heroes>
+    hero_id: u32,
+    /// Filter matches based on their start time (Unix timestamp). **Default:** 30 days ago.
+    #[serde(default = "default_last_month_timestamp")]
+    #[param(default = default_last_month_timestamp)]
+    min_unix_timestamp: Option<i64>,
+    /// Filter matches based on their start time (Unix timestamp).
+    max_unix_timestamp: Option<i64>,
+    /// Filter matches based on their duration in seconds (up to 7000s).
+    #[param(maximum = 7000)]
+    min_duration_s: Option<u64>,
+    /// Filter matches based on their duration in seconds (up to 7000s).
+    #[param(maximum = 7000)]
+    max_duration_s: Option<u64>,
+    /// Filter matches based on the average badge level (tier = first digits, subtier = last digit) of *both* teams involved. See more: <https://assets.deadlock-api.com/v2/ranks>
+    #[param(minimum = 0, maximum = 116)]
+    min_average_badge: Option<u8>,
+    /// Filter matches based on the average badge level (tier = first digits, subtier = last digit) of *both* teams involved. See more: <https://assets.deadlock-api.com/v2/ranks>
+    #[param(minimum = 0, maximum = 116)]
+    max_average_badge: Option<u8>,
+    /// Filter matches based on their ID.
+    min_match_id: Option<u64>,
+    /// Filter matches based on their ID.
+    max_match_id: Option<u64>,
+    /// The minimum number of matches for a game time window or net worth bracket to be considered as the power spike.
+    #[serde(default = "default_min_window_matches")]
+    #[param(minimum = 1, default = 1000)]
+    min_window_matches: Option<u64>,
+    /// The minimum number of matches for an item to be included in the power spike window.
+    #[serde(default = "default_min_item_matches")]
+    #[param(minimum = 1, default = 500)]
+    min_item_matches: Option<u64>,
+}
+
+/// Counts of player matches per grouping set, the columns not part of the set are `None`.
+#[derive(Debug, Clone, Row, Deserialize)]
+struct PowerspikeRow {
+    item_id: Option<u32>,
+    time_window: Option<u32>,
+    net_worth_bracket: Option<u32>,
+    wins: u64,
+    matches: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct PowerspikeWindow {
+    /// Start of the game time window in seconds.
+    pub start_s: u32,
+    /// End of the game time window in seconds, `None` for the last window.
+    pub end_s: Option<u32>,
+    /// The number of matches with an item purchase in this window that ended in a win.
+    pub wins: u64,
+    /// The number of matches with an item purchase in this window.
+    pub matches: u64,
+    pub winrate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct PowerspikeNetWorthBracket {
+    /// Lower bound of the net worth bracket.
+    pub min_net_worth: u32,
+    /// Upper bound of the net worth bracket, `None` for the last bracket.
+    pub max_net_worth: Option<u32>,
+    /// The number of matches with an item purchase in this bracket that ended in a win.
+    pub wins: u64,
+    /// The number of matches with an item purchase in this bracket.
+    pub matches: u64,
+    pub winrate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct PowerspikeItem {
+    pub item_id: u32,
+    /// The number of matches the item was bought in the power spike window and won.
+    pub wins: u64,
+    /// The number of matches the item was bought in the power spike window.
+    pub matches: u64,
+    pub winrate: f64,
+    /// Lower bound of the net worth bracket the item is bought in most often in the power spike window.
+    pub min_net_worth: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct HeroPowerspike {
+    pub hero_id: u32,
+    /// The game time window with the highest winrate, if any window has enough matches.
+    pub peak_window: Option<PowerspikeWindow>,
+    /// The net worth bracket with the highest winrate, if any bracket has enough matches.
+    pub peak_net_worth_bracket: Option<PowerspikeNetWorthBracket>,
+    /// All game time windows.
+    pub windows: Vec<PowerspikeWindow>,
+    /// All net worth brackets.
+    pub net_worth_brackets: Vec<PowerspikeNetWorthBracket>,
+    /// Items bought in the peak window, sorted by winrate (descending).
+    pub items: Vec<PowerspikeItem>,
+}
+
+/// Maps a value to the index of the bucket it falls into, given the upper edges of the buckets.
+fn bucket_index_expr(value: &str, edges: &[u32]) -> String {
+    let conditions = edges
+        .iter()
+        .zip(0..)
+        .map(|(edge, index)| format!("{value} < {edge}, {index}"))
+        .join(", ");
+    format!("toUInt32(multiIf({conditions}, {}))", edges.len())
+}
+
+/// Returns the lower and upper bound of a bucket by its index.
+fn bucket_bounds(edges: &[u32], index: u32) -> (u32, Option<u32>) {
+    let index = index as usize;
+    let lower = index
+        .checked_sub(1)
+        .and_then(|i| edges.get(i))
+        .copied()
+        .unwrap_or_default();
+    (lower, edges.get(index).copied())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn winrate(wins: u64, matches: u64) -> f64 {
+    if matches > 0 {
+        wins as f64 / matches as f64
+    } else {
+        0.0
+    }
+}
+
+fn build_query(query: &HeroPowerspikeQuery) -> String {
+    let mut info_filters = vec![];
+    if let Some(min_unix_timestamp) = query.min_unix_timestamp {
+        info_filters.push(format!("start_time >= {min_unix_timestamp}"));
+    }
+    if let Some(max_unix_timestamp) = query.max_unix_timestamp {
+        info_filters.push(format!("start_time <= {max_unix_timestamp}"));
+    }
+    if let Some(min_match_id) = query.min_match_id {
+        info_filters.push(format!("match_id >= {min_match_id}"));
+    }
+    if let Some(max_match_id) = query.max_match_id {
+        info_filters.push(format!("match_id <= {max_match_id}"));
+    }
+    if let Some(min_badge_level) = query.min_average_badge
+        && min_badge_level > 11
+    {
+        info_filters.push(format!(
+            "average_badge_team0 >= {min_badge_level} AND average_badge_team1 >= {min_badge_level}"
+        ));
+    }
+    if let Some(max_badge_level) = query.max_average_badge
+        && max_badge_level < 116
+    {
+        info_filters.push(format!(
+            "average_badge_team0 <= {max_badge_level} AND average_badge_team1 <= {max_badge_level}"
+        ));
+    }
+    if let Some(min_duration_s) = query.min_duration_s {
+        info_filters.push(format!("duration_s >= {min_duration_s}"));
+    }
+    if let Some(max_duration_s) = query.max_duration_s {
+        info_filters.push(format!("duration_s <= {max_duration_s}"));
+    }
+    let info_filters = if info_filters.is_empty() {
+        String::new()
+    } else {
+        format!(" AND {}", info_filters.join(" AND "))
+    };
+    let hero_id = query.hero_id;
+    let time_window_expr = bucket_index_expr("buy_time", &GAME_TIME_WINDOW_EDGES);
+    let net_worth_bracket_expr = bucket_index_expr("net_worth_at_buy", &NET_WORTH_BRACKET_EDGES);
+    format!(
+        "
+WITH
+    t_upgrades AS (SELECT id FROM items WHERE type = 'upgrade'),
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    ),
+    exploded_players AS (
+        SELECT
+            match_id,
+            account_id,
+            it.item_id AS item_id,
+            won,
+            it.game_time_s AS buy_time,
+            coalesce(
+                arrayElementOrNull(
+                    stats.net_worth,
+                    arrayFirstIndex(ts -> ts >= it.game_time_s, stats.time_stamp_s) - 1
+                ), net_worth
+            ) AS net_worth_at_buy
+        FROM match_player
+            ARRAY JOIN items AS it
+        WHERE match_id IN (SELECT match_id FROM t_matches)
+            AND it.item_id IN t_upgrades
+            AND it.game_time_s > 0
+            AND hero_id = {hero_id}
+    )
+SELECT
+    item_id,
+    {time_window_expr} AS time_window,
+    {net_worth_bracket_expr} AS net_worth_bracket,
+    uniqExactIf((match_id, account_id), won) AS wins,
+    uniqExact((match_id, account_id)) AS matches
+FROM exploded_players
+GROUP BY GROUPING SETS (
+    (time_window),
+    (net_worth_bracket),
+    (item_id, time_window),
+    (item_id, time_window, net_worth_bracket)
+)
+ORDER BY item_id, time_window, net_worth_bracket
+SETTINGS group_by_use_nulls = 1
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<PowerspikeRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<PowerspikeRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+/// Aggregates the per grouping set rows into the power spike of the hero.
+///
+/// Windows, brackets and items count every player match once, no matter how many items were
+/// bought in it.
+fn detect_powerspike(query: &HeroPowerspikeQuery, rows: &[PowerspikeRow]) -> HeroPowerspike {
+    let min_window_matches = query.min_window_matches.unwrap_or(1000);
+    let min_item_matches = query.min_item_matches.unwrap_or(500);
+
+    let mut window_totals: HashMap<u32, (u64, u64)> = HashMap::new();
+    let mut bracket_totals: HashMap<u32, (u64, u64)> = HashMap::new();
+    for row in rows {
+        match (row.item_id, row.time_window, row.net_worth_bracket) {
+            (None, Some(window), None) => {
+                window_totals.insert(window, (row.wins, row.matches));
+            }
+            (None, None, Some(bracket)) => {
+                bracket_totals.insert(bracket, (row.wins, row.matches));
+            }
+            _ => {}
+        }
+    }
+
+    let windows = window_totals
+        .into_iter()
+        .sorted_by_key(|(index, _)| *index)
+        .map(|(index, (wins, matches))| {
+            let (start_s, end_s) = bucket_bounds(&GAME_TIME_WINDOW_EDGES, index);
+            PowerspikeWindow {
+                start_s,
+                end_s,
+                wins,
+                matches,
+                winrate: winrate(wins, matches),
+            }
+        })
+        .collect::<Vec<_>>();
+    let net_worth_brackets = bracket_totals
+        .into_iter()
+        .sorted_by_key(|(index, _)| *index)
+        .map(|(index, (wins, matches))| {
+            let (min_net_worth, max_net_worth) = bucket_bounds(&NET_WORTH_BRACKET_EDGES, index);
+            PowerspikeNetWorthBracket {
+                min_net_worth,
+                max_net_worth,
+                wins,
+                matches,
+                winrate: winrate(wins, matches),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let peak_window = windows
+        .iter()
+        .filter(|w| w.matches >= min_window_matches)
+        .max_by(|a, b| a.winrate.total_cmp(&b.winrate))
+        .cloned();
+    let peak_net_worth_bracket = net_worth_brackets
+        .iter()
+        .filter(|b| b.matches >= min_window_matches)
+        .max_by(|a, b| a.winrate.total_cmp(&b.winrate))
+        .cloned();
+
+    let items = peak_window
+        .as_ref()
+        .map(|peak| {
+            let mut items: HashMap<u32, (u64, u64)> = HashMap::new();
+            let mut item_brackets: HashMap<u32, HashMap<u32, u64>> = HashMap::new();
+            for row in rows {
+                let (Some(item_id), Some(window)) = (row.item_id, row.time_window) else {
+                    continue;
+                };
+                if bucket_bounds(&GAME_TIME_WINDOW_EDGES, window).0 != peak.start_s {
+                    continue;
+                }
+                match row.net_worth_bracket {
+                    Some(bracket) => {
+                        item_brackets
+                            .entry(item_id)
+                            .or_default()
+                            .insert(bracket, row.matches);
+                    }
+                    None => {
+                        items.insert(item_id, (row.wins, row.matches));
+                    }
+                }
+            }
+            items
+                .into_iter()
+                .filter(|(_, (_, matches))| *matches >= min_item_matches)
+                .map(|(item_id, (wins, matches))| {
+                    let bracket = item_brackets
+                        .get(&item_id)
+                        .and_then(|b| b.iter().max_by_key(|(index, m)| (**m, *index)))
+                        .map(|(index, _)| *index)
+                        .unwrap_or_default();
+                    PowerspikeItem {
+                        item_id,
+                        wins,
+                        matches,
+                        winrate: winrate(wins, matches),
+                        min_net_worth: bucket_bounds(&NET_WORTH_BRACKET_EDGES, bracket).0,
+                    }
+                })
+                .sorted_by(|a, b| {
+                    b.winrate
+                        .total_cmp(&a.winrate)
+                        .then_with(|| a.item_id.cmp(&b.item_id))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    HeroPowerspike {
+        hero_id: query.hero_id,
+        peak_window,
+        peak_net_worth_bracket,
+        windows,
+        net_worth_brackets,
+        items,
+    }
+}
+
+async fn get_hero_powerspike(
+    ch_client: &clickhouse::Client,
+    mut query: HeroPowerspikeQuery,
+) -> APIResult<HeroPowerspike> {
+    query.min_unix_timestamp = query.min_unix_timestamp.map(|v| v - v % 3600);
+    query.max_unix_timestamp = query.max_unix_timestamp.map(|v| v + 3600 - v % 3600);
+    let query_str = build_query(&query);
+    debug!(?query_str);
+    let rows = run_query(ch_client, &query_str).await?;
+    Ok(detect_powerspike(&query, &rows))
+}
+
+#[utoipa::path(
+    get,
+    path = "/hero-powerspike",
+    params(HeroPowerspikeQuery),
+    responses(
+        (status = OK, description = "Hero Power Spike", body = HeroPowerspike),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to fetch hero power spike")
+    ),
+    tags = ["Analytics"],
+    summary = "Hero Power Spike",
+    description = "
+Detects the game time window and net worth bracket in which a hero's winrate peaks.
+
+The winrates are based on the item purchases of the hero: a match counts towards every game time window and net worth bracket the hero bought an item in, once per window and bracket no matter how many items were bought.
+Windows and brackets with fewer than `min_window_matches` matches are not considered as the power spike.
+
+The response also includes the winrate of every item bought in the peak window that has at least `min_item_matches` matches, together with the net worth bracket the item is bought in most often within the peak window.
+
+Results are cached for **1 hour** based on the combination of query parameters provided. Subsequent identical requests within this timeframe will receive the cached response.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(super) async fn hero_powerspike(
+    Query(query): Query<HeroPowerspikeQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    if !state.assets_client.validate_hero_id(query.hero_id).await {
+        return Err(APIError::status_msg(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid hero_id: {}", query.hero_id),
+        ));
+    }
+    get_hero_powerspike(&state.ch_client_ro, query)
+        .await
+        .map(Json)
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::warn;
+
+    use super::*;
+
+    fn row(
+        item_id: Option<u32>,
+        time_window: Option<u32>,
+        net_worth_bracket: Option<u32>,
+        wins: u64,
+        matches: u64,
+    ) -> PowerspikeRow {
+        PowerspikeRow {
+            item_id,
+            time_window,
+            net_worth_bracket,
+            wins,
+            matches,
+        }
+    }
+
+    #[test]
+    fn test_build_query_filters() {
+        let query = HeroPowerspikeQuery {
+            hero_id: 15,
+            min_unix_timestamp: Some(1672531200),
+            max_unix_timestamp: Some(1675209599),
+            min_duration_s: Some(600),
+            min_average_badge: Some(61),
+            max_average_badge: Some(112),
+            ..Default::default()
+        };
+        let sql = build_query(&query);
+        if let Err(e) =
+            sqlparser::parser::Parser::parse_sql(&sqlparser::dialect::ClickHouseDialect {}, &sql)
+        {
+            warn!("Failed to parse SQL: {sql}: {e}");
+        }
+        assert!(sql.contains("hero_id = 15"));
+        assert!(sql.contains("start_time >= 1672531200"));
+        assert!(sql.contains("start_time <= 1675209599"));
+        assert!(sql.contains("duration_s >= 600"));
+        assert!(sql.contains("average_badge_team0 >= 61 AND average_badge_team1 >= 61"));
+        assert!(sql.contains("average_badge_team0 <= 112 AND average_badge_team1 <= 112"));
+        assert!(sql.contains("uniqExact((match_id, account_id)) AS matches"));
+        assert!(sql.contains("SETTINGS group_by_use_nulls = 1"));
+    }
+
+    #[test]
+    fn test_bucket_index_expr() {
+        assert_eq!(
+            bucket_index_expr("net_worth_at_buy", &NET_WORTH_BRACKET_EDGES),
+            "toUInt32(multiIf(net_worth_at_buy < 5000, 0, net_worth_at_buy < 10000, 1, \
+             net_worth_at_buy < 20000, 2, 3))"
+        );
+    }
+
+    #[test]
+    fn test_bucket_bounds() {
+        assert_eq!(bucket_bounds(&NET_WORTH_BRACKET_EDGES, 0), (0, Some(5000)));
+        assert_eq!(
+            bucket_bounds(&NET_WORTH_BRACKET_EDGES, 2),
+            (10000, Some(20000))
+        );
+        assert_eq!(bucket_bounds(&NET_WORTH_BRACKET_EDGES, 3), (20000, None));
+    }
+
+    #[test]
+    fn test_detect_powerspike() {
+        let query = HeroPowerspikeQuery {
+            hero_id: 1,
+            min_window_matches: Some(100),
+            min_item_matches: Some(50),
+            ..Default::default()
+        };
+        let rows = [
+            row(None, Some(0), None, 50, 100),
+            row(None, Some(2), None, 70, 120),
+            row(None, Some(5), None, 20, 20),
+            row(None, None, Some(0), 50, 100),
+            row(None, None, Some(1), 40, 60),
+            row(None, None, Some(2), 40, 60),
+            row(None, None, Some(3), 20, 20),
+            row(Some(1), Some(0), None, 50, 100),
+            row(Some(1), Some(0), Some(0), 50, 100),
+            row(Some(1), Some(2), None, 40, 60),
+            row(Some(1), Some(2), Some(1), 40, 60),
+            row(Some(2), Some(2), None, 35, 50),
+            row(Some(2), Some(2), Some(2), 35, 50),
+            row(Some(3), Some(2), None, 10, 20),
+            row(Some(3), Some(2), Some(2), 10, 20),
+            row(Some(3), Some(5), None, 20, 20),
+            row(Some(3), Some(5), Some(3), 20, 20),
+        ];
+        let powerspike = detect_powerspike(&query, &rows);
+
+        // Window 5 has the highest winrate, but not enough matches
+        let peak = powerspike.peak_window.unwrap();
+        assert_eq!((peak.start_s, peak.end_s), (900, Some(1200)));
+        // Matches with several items bought in the window are only counted once
+        assert_eq!(peak.matches, 120);
+        assert_eq!(powerspike.windows.len(), 3);
+
+        let bracket = powerspike.peak_net_worth_bracket.unwrap();
+        assert_eq!(bracket.min_net_worth, 0);
+
+        // Item 3 does not have enough matches in the peak window, item 1 is bought in the lowest
+        // bracket most often overall, but not in the peak window
+        assert_eq!(
+            powerspike
+                .items
+                .iter()
+                .map(|i| (i.item_id, i.min_net_worth))
+                .collect::<Vec<_>>(),
+            vec![(2, 10000), (1, 5000)]
+        );
+    }
+
+    #[test]
+    fn test_detect_powerspike_no_peak() {
+        let query = HeroPowerspikeQuery {
+            hero_id: 1,
+            ..Default::default()
+        };
+        let powerspike = detect_powerspike(&query, &[row(None, Some(0), None, 5, 10)]);
+        assert!(powerspike.peak_window.is_none());
+        assert!(powerspike.peak_net_worth_bracket.is_none());
+        assert!(powerspike.items.is_empty());
+    }
+}
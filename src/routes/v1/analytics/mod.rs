@@ -3,6 +3,7 @@ pub mod badge_distribution;
 pub mod build_item_stats;
 pub mod hero_comb_stats;
 pub mod hero_counters_stats;
+pub mod hero_powerspike;
 pub mod hero_scoreboard;
 pub mod hero_stats;
 pub mod hero_synergies_stats;
@@ -43,6 +44,7 @@ pub(super) fn router() -> OpenApiRouter<AppState> {
             .routes(routes!(hero_counters_stats::hero_counters_stats))
             .routes(routes!(hero_synergies_stats::hero_synergies_stats))
             .routes(routes!(hero_comb_stats::hero_comb_stats))
+            .routes(routes!(hero_powerspike::hero_powerspike))
             .routes(routes!(build_item_stats::build_item_stats))
             .routes(routes!(badge_distribution::badge_distribution))
             .routes(routes!(player_performance_curve::player_performance_curve))
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::context::AppState;
+use crate::error::APIResult;
+use crate::routes::v1::build_creator::confidence::wilson_lower_bound;
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, build_enemy_filter, build_info_filters,
+};
+use crate::routes::v1::build_creator::structs::{AbilityUpgrade, BuildCreatorAbilityOrder};
+
+/// Number of ability orders returned in the build creator response
+const MAX_ABILITY_ORDERS: usize = 5;
+
+#[derive(Debug, Clone, Row, Deserialize)]
+struct AbilityOrderRow {
+    abilities: Vec<u32>,
+    avg_times_s: Vec<f64>,
+    wins: u64,
+    matches: u64,
+}
+
+fn build_query(query: &BuildCreatorQuery) -> String {
+    let info_filters = build_info_filters(query);
+    let enemy_filter = build_enemy_filter(query);
+    let hero_id = query.hero_id;
+    let min_matches = query.min_matches.unwrap_or(50);
+    format!(
+        "
+WITH
+    (SELECT groupArray(id) FROM items WHERE type = 'ability') AS ability_ids_array,
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    )
+SELECT
+    arrayFilter(x -> has(ability_ids_array, x), items.item_id) AS abilities,
+    avgForEach(
+        arrayMap(
+            t -> toFloat64(t),
+            arrayFilter((t, x) -> has(ability_ids_array, x), items.game_time_s, items.item_id)
+        )
+    ) AS avg_times_s,
+    sum(won) AS wins,
+    count() AS matches
+FROM match_player
+WHERE match_id IN (SELECT match_id FROM t_matches)
+    AND hero_id = {hero_id}{enemy_filter}
+GROUP BY abilities
+HAVING matches >= {min_matches} AND notEmpty(abilities)
+ORDER BY matches DESC
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<AbilityOrderRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<AbilityOrderRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+/// Picks the best performing ability orders, ranked by the Wilson lower bound of their winrate.
+fn best_ability_orders(
+    rows: Vec<AbilityOrderRow>,
+    ability_names: &HashMap<u32, String>,
+) -> Vec<BuildCreatorAbilityOrder> {
+    let mut orders = rows
+        .into_iter()
+        .map(|row| {
+            #[allow(clippy::cast_precision_loss)]
+            let winrate = row.wins as f64 / row.matches as f64;
+            BuildCreatorAbilityOrder {
+                upgrades: row
+                    .abilities
+                    .into_iter()
+                    .zip(row.avg_times_s)
+                    .map(|(ability_id, avg_time_s)| AbilityUpgrade {
+                        ability_id,
+                        name: ability_names.get(&ability_id).cloned(),
+                        avg_time_s,
+                    })
+                    .collect(),
+                matches: row.matches,
+                winrate,
+                wilson_lower_bound: wilson_lower_bound(row.wins, row.matches),
+            }
+        })
+        .collect::<Vec<_>>();
+    orders.sort_by(|a, b| {
+        b.wilson_lower_bound
+            .total_cmp(&a.wilson_lower_bound)
+            .then_with(|| b.matches.cmp(&a.matches))
+    });
+    orders.truncate(MAX_ABILITY_ORDERS);
+    orders
+}
+
+/// Fetches the best performing ability orders of the hero under the build creator filters.
+pub(super) async fn fetch_ability_orders(
+    state: &AppState,
+    query: &BuildCreatorQuery,
+    ability_names: &HashMap<u32, String>,
+) -> APIResult<Vec<BuildCreatorAbilityOrder>> {
+    let query_str = build_query(query);
+    debug!(?query_str);
+    let rows = run_query(&state.ch_client_ro, &query_str).await?;
+    Ok(best_ability_orders(rows, ability_names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(abilities: Vec<u32>, wins: u64, matches: u64) -> AbilityOrderRow {
+        AbilityOrderRow {
+            avg_times_s: abilities.iter().map(|&a| f64::from(a) * 60.0).collect(),
+            abilities,
+            wins,
+            matches,
+        }
+    }
+
+    #[test]
+    fn test_build_query() {
+        let query = BuildCreatorQuery {
+            hero_id: 7,
+            min_matches: Some(100),
+            enemy_hero_ids: Some(vec![1, 2]),
+            ..Default::default()
+        };
+        let sql = build_query(&query);
+        assert!(sql.contains("hero_id = 7"));
+        assert!(sql.contains("hero_id IN (1, 2)"));
+        assert!(sql.contains("HAVING matches >= 100"));
+    }
+
+    #[test]
+    fn test_best_ability_orders() {
+        let names = HashMap::from([(1, "Ability 1".to_owned())]);
+        let orders = best_ability_orders(
+            vec![
+                row(vec![1, 2], 60, 100),
+                row(vec![2, 1], 550, 1000),
+                row(vec![1, 1], 5, 10),
+            ],
+            &names,
+        );
+        assert_eq!(
+            orders
+                .iter()
+                .map(|o| o.upgrades.iter().map(|u| u.ability_id).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            vec![vec![2, 1], vec![1, 2], vec![1, 1]]
+        );
+        assert_eq!(orders[1].upgrades[0].name.as_deref(), Some("Ability 1"));
+        assert!((orders[1].upgrades[1].avg_time_s - 120.0).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use futures::try_join;
+use serde::Deserialize;
+use tracing::debug;
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, build_enemy_filter, build_info_filters, normalize_match_filters,
+};
+use crate::routes::v1::build_creator::structs::{
+    BadgeRange, BuildCreatorComparison, ItemComparison, ItemRangeStats,
+};
+use crate::services::assets::types::AssetsItem;
+
+#[derive(Debug, Clone, Deserialize, IntoParams, Eq, PartialEq, Hash, Default)]
+pub(crate) struct BuildCreatorCompareQuery {
+    #[serde(flatten)]
+    #[param(ignore)]
+    pub filters: BuildCreatorQuery,
+    /// Badge range to compare against, e.g. the top ranks.
+    #[param(minimum = 0, maximum = 116)]
+    pub compare_min_average_badge: Option<u8>,
+    /// Badge range to compare against, e.g. the top ranks.
+    #[param(minimum = 0, maximum = 116)]
+    pub compare_max_average_badge: Option<u8>,
+}
+
+impl BuildCreatorCompareQuery {
+    /// Build creator query of the badge range to compare against.
+    fn compare_query(&self) -> BuildCreatorQuery {
+        BuildCreatorQuery {
+            min_average_badge: self.compare_min_average_badge,
+            max_average_badge: self.compare_max_average_badge,
+            ..self.filters.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Row, Deserialize)]
+pub(super) struct RangeItemRow {
+    item_id: u32,
+    wins: u64,
+    matches: u64,
+    avg_buy_time_s: f64,
+    /// Number of the hero's players in the badge range, the same for every row
+    hero_matches: u64,
+}
+
+pub(super) fn build_query(query: &BuildCreatorQuery) -> String {
+    let info_filters = build_info_filters(query);
+    let enemy_filter = build_enemy_filter(query);
+    let hero_id = query.hero_id;
+    format!(
+        "
+WITH
+    t_upgrades AS (SELECT id FROM items WHERE type = 'upgrade'),
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    ),
+    t_players AS (
+        SELECT won, items
+        FROM match_player
+        WHERE match_id IN (SELECT match_id FROM t_matches)
+            AND hero_id = {hero_id}{enemy_filter}
+    )
+SELECT
+    it.item_id AS item_id,
+    sum(won) AS wins,
+    count() AS matches,
+    avg(it.game_time_s) AS avg_buy_time_s,
+    (SELECT count() FROM t_players) AS hero_matches
+FROM t_players
+    ARRAY JOIN items AS it
+WHERE it.item_id IN t_upgrades AND it.game_time_s > 0
+GROUP BY item_id
+ORDER BY item_id
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<RangeItemRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+pub(super) async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<RangeItemRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+/// Stats of every item within one badge range, and the number of the hero's players in it.
+#[allow(clippy::cast_precision_loss)]
+pub(super) fn range_stats(rows: &[RangeItemRow]) -> (HashMap<u32, ItemRangeStats>, u64) {
+    let hero_matches = rows.first().map_or(0, |row| row.hero_matches);
+    let stats = rows
+        .iter()
+        .filter(|row| row.matches > 0)
+        .map(|row| {
+            (
+                row.item_id,
+                ItemRangeStats {
+                    matches: row.matches,
+                    pick_rate: row.matches as f64 / hero_matches.max(1) as f64,
+                    winrate: row.wins as f64 / row.matches as f64,
+                    avg_buy_time_s: row.avg_buy_time_s,
+                },
+            )
+        })
+        .collect();
+    (stats, hero_matches)
+}
+
+fn compare_item(
+    base: Option<&ItemRangeStats>,
+    compare: Option<&ItemRangeStats>,
+) -> (f64, Option<f64>, Option<f64>) {
+    let pick_rate = |s: Option<&ItemRangeStats>| s.map_or(0.0, |s| s.pick_rate);
+    let both = base.zip(compare);
+    (
+        pick_rate(compare) - pick_rate(base),
+        both.map(|(b, c)| c.winrate - b.winrate),
+        both.map(|(b, c)| c.avg_buy_time_s - b.avg_buy_time_s),
+    )
+}
+
+/// Compares the stats of all items that have at least `min_matches` matches in either range.
+pub(super) fn compare_items<'a>(
+    items: impl Iterator<Item = &'a AssetsItem>,
+    base_stats: &HashMap<u32, ItemRangeStats>,
+    compare_stats: &HashMap<u32, ItemRangeStats>,
+    min_matches: u64,
+) -> Vec<ItemComparison> {
+    let has_min_matches = |s: Option<&ItemRangeStats>| s.is_some_and(|s| s.matches >= min_matches);
+    items
+        .filter_map(|item_meta| {
+            let base = base_stats.get(&item_meta.id);
+            let compare = compare_stats.get(&item_meta.id);
+            if !has_min_matches(base) && !has_min_matches(compare) {
+                return None;
+            }
+            let (pick_rate_delta, winrate_delta, avg_buy_time_delta_s) =
+                compare_item(base, compare);
+            Some(ItemComparison {
+                item_id: item_meta.id,
+                name: item_meta.name.clone(),
+                tier: item_meta.tier.unwrap_or_default(),
+                slot: item_meta.slot.clone(),
+                base: base.cloned(),
+                compare: compare.cloned(),
+                pick_rate_delta,
+                winrate_delta,
+                avg_buy_time_delta_s,
+            })
+        })
+        .collect()
+}
+
+pub(super) async fn build_creator_compare(
+    Query(mut query): Query<BuildCreatorCompareQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    if query.compare_min_average_badge.is_none() && query.compare_max_average_badge.is_none() {
+        return Err(APIError::bad_request(
+            "compare_min_average_badge or compare_max_average_badge is required",
+        ));
+    }
+    normalize_match_filters(&state, &mut query.filters).await?;
+
+    let hero_name = state
+        .assets_client
+        .fetch_hero_name_from_id(query.filters.hero_id)
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch hero: {e}")))?
+        .ok_or_else(|| {
+            APIError::bad_request(format!("Hero {} not found", query.filters.hero_id))
+        })?;
+    let items_map: HashMap<u32, _> = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?
+        .into_iter()
+        .filter(|item| item.item_type.as_deref() == Some("upgrade"))
+        .map(|item| (item.id, item))
+        .collect();
+
+    // Both badge ranges are cached separately, so either can be reused by other comparisons
+    let base_query_str = build_query(&query.filters);
+    let compare_query_str = build_query(&query.compare_query());
+    debug!(?base_query_str, ?compare_query_str);
+    let (base_rows, compare_rows) = try_join!(
+        run_query(&state.ch_client_ro, &base_query_str),
+        run_query(&state.ch_client_ro, &compare_query_str),
+    )?;
+    let (base_stats, base_matches) = range_stats(&base_rows);
+    let (compare_stats, compare_matches) = range_stats(&compare_rows);
+
+    let min_matches = u64::from(query.filters.min_matches.unwrap_or(50));
+    let mut items = compare_items(items_map.values(), &base_stats, &compare_stats, min_matches);
+    items.sort_by(|a, b| {
+        b.pick_rate_delta
+            .abs()
+            .total_cmp(&a.pick_rate_delta.abs())
+            .then_with(|| a.item_id.cmp(&b.item_id))
+    });
+
+    Ok(Json(BuildCreatorComparison {
+        hero_id: query.filters.hero_id,
+        hero_name,
+        base: BadgeRange {
+            min_average_badge: query.filters.min_average_badge,
+            max_average_badge: query.filters.max_average_badge,
+            matches: base_matches,
+        },
+        compare: BadgeRange {
+            min_average_badge: query.compare_min_average_badge,
+            max_average_badge: query.compare_max_average_badge,
+            matches: compare_matches,
+        },
+        items,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(item_id: u32, wins: u64, matches: u64, avg_buy_time_s: f64) -> RangeItemRow {
+        RangeItemRow {
+            item_id,
+            wins,
+            matches,
+            avg_buy_time_s,
+            hero_matches: 1000,
+        }
+    }
+
+    #[test]
+    fn test_range_queries() {
+        let query = BuildCreatorCompareQuery {
+            filters: BuildCreatorQuery {
+                hero_id: 7,
+                min_average_badge: Some(50),
+                max_average_badge: Some(70),
+                ..Default::default()
+            },
+            compare_min_average_badge: Some(100),
+            ..Default::default()
+        };
+        let base_sql = build_query(&query.filters);
+        let compare_sql = build_query(&query.compare_query());
+        assert!(base_sql.contains("average_badge_team0 >= 50"));
+        assert!(base_sql.contains("average_badge_team0 <= 70"));
+        assert!(compare_sql.contains("average_badge_team0 >= 100"));
+        assert!(!compare_sql.contains("average_badge_team0 <="));
+        assert!(compare_sql.contains("hero_id = 7"));
+    }
+
+    #[test]
+    fn test_compare_item() {
+        let (base, base_matches) = range_stats(&[row(1, 50, 100, 600.0), row(2, 10, 20, 900.0)]);
+        let (compare, _) = range_stats(&[row(1, 120, 200, 540.0)]);
+        assert_eq!(base_matches, 1000);
+
+        let (pick_rate_delta, winrate_delta, avg_buy_time_delta_s) =
+            compare_item(base.get(&1), compare.get(&1));
+        assert!((pick_rate_delta - 0.1).abs() < 1e-9);
+        assert!(winrate_delta.is_some_and(|d| (d - 0.1).abs() < 1e-9));
+        assert!(avg_buy_time_delta_s.is_some_and(|d| (d + 60.0).abs() < 1e-9));
+
+        let (pick_rate_delta, winrate_delta, _) = compare_item(base.get(&2), compare.get(&2));
+        assert!((pick_rate_delta + 0.02).abs() < 1e-9);
+        assert!(winrate_delta.is_none());
+    }
+}
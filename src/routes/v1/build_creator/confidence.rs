@@ -0,0 +1,50 @@
+/// z-score of the 95% confidence level
+const Z_95: f64 = 1.96;
+/// Weight of the prior in the smoothed winrate, in matches
+const PRIOR_MATCHES: f64 = 100.0;
+
+/// Lower bound of the 95% Wilson score interval of a winrate.
+///
+/// Small samples get a wide interval and therefore a low bound, so lucky streaks over a few
+/// matches do not outrank items with a large sample.
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn wilson_lower_bound(wins: u64, matches: u64) -> f64 {
+    if matches == 0 {
+        return 0.0;
+    }
+    let n = matches as f64;
+    let p = wins as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let center = p + z2 / (2.0 * n);
+    let margin = Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    ((center - margin) / (1.0 + z2 / n)).max(0.0)
+}
+
+/// Mean of the Beta posterior of a winrate, using a prior centered at `prior_winrate`.
+///
+/// The winrate is pulled towards the prior by the weight of [`PRIOR_MATCHES`] matches, which
+/// matters for small samples and vanishes for large ones.
+#[allow(clippy::cast_precision_loss)]
+pub(super) fn smoothed_winrate(wins: u64, matches: u64, prior_winrate: f64) -> f64 {
+    (wins as f64 + PRIOR_MATCHES * prior_winrate) / (matches as f64 + PRIOR_MATCHES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wilson_lower_bound() {
+        assert!(wilson_lower_bound(0, 0).abs() < f64::EPSILON);
+        assert!((wilson_lower_bound(50, 100) - 0.4038).abs() < 1e-4);
+        // A lucky streak over few matches ranks below a slightly worse large sample
+        assert!(wilson_lower_bound(40, 60) < wilson_lower_bound(11_000, 20_000));
+    }
+
+    #[test]
+    fn test_smoothed_winrate() {
+        assert!((smoothed_winrate(0, 0, 0.5) - 0.5).abs() < f64::EPSILON);
+        assert!((smoothed_winrate(100, 100, 0.5) - 0.75).abs() < f64::EPSILON);
+        assert!((smoothed_winrate(6000, 10_000, 0.5) - 0.599).abs() < 1e-3);
+    }
+}
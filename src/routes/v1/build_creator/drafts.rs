@@ -0,0 +1,376 @@
+use core::time::Duration;
+use std::collections::HashSet;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use rand::distr::Alphanumeric;
+use uuid::Uuid;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::handlers::{MAX_BUCKETS, validate_enemy_hero_ids};
+use crate::routes::v1::build_creator::structs::{
+    BuildDraft, BuildDraftFilters, BuildDraftRequest, CreatedBuildDraft,
+};
+use crate::services::rate_limiter::Quota;
+use crate::services::rate_limiter::extractor::RateLimitKey;
+
+/// Length of the share code of a draft, 62^8 possible codes
+const SHARE_CODE_LENGTH: usize = 8;
+/// Number of attempts to find an unused share code
+const SHARE_CODE_ATTEMPTS: usize = 3;
+/// Header carrying the edit token of anonymous drafts
+const EDIT_TOKEN_HEADER: &str = "X-Edit-Token";
+const MAX_ITEMS: usize = 64;
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_NOTES_LENGTH: usize = 10_000;
+
+#[derive(Debug, sqlx::FromRow)]
+struct BuildDraftRow {
+    share_code: String,
+    hero_id: i32,
+    name: Option<String>,
+    item_ids: Vec<i32>,
+    notes: Option<String>,
+    filters: sqlx::types::Json<BuildDraftFilters>,
+    owner_api_key: Option<Uuid>,
+    edit_token: Option<Uuid>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl BuildDraftRow {
+    /// A draft can be modified with the API key that created it, or with its edit token.
+    fn is_editable_by(&self, api_key: Option<Uuid>, edit_token: Option<Uuid>) -> bool {
+        (self.owner_api_key.is_some() && self.owner_api_key == api_key)
+            || (self.edit_token.is_some() && self.edit_token == edit_token)
+    }
+}
+
+impl From<BuildDraftRow> for BuildDraft {
+    fn from(row: BuildDraftRow) -> Self {
+        Self {
+            share_code: row.share_code,
+            hero_id: row.hero_id.cast_unsigned(),
+            name: row.name,
+            item_ids: row.item_ids.into_iter().map(i32::cast_unsigned).collect(),
+            notes: row.notes,
+            filters: row.filters.0,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+fn generate_share_code() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(SHARE_CODE_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+fn is_valid_share_code(share_code: &str) -> bool {
+    share_code.len() == SHARE_CODE_LENGTH && share_code.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn edit_token(headers: &HeaderMap) -> Option<Uuid> {
+    headers
+        .get(EDIT_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| Uuid::parse_str(s).ok())
+}
+
+fn validate_request(request: &mut BuildDraftRequest) -> APIResult<()> {
+    if request.item_ids.len() > MAX_ITEMS {
+        return Err(APIError::bad_request(format!(
+            "A draft can contain at most {MAX_ITEMS} items"
+        )));
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = request.item_ids.iter().find(|id| !seen.insert(**id)) {
+        return Err(APIError::bad_request(format!(
+            "Item {duplicate} is in the draft more than once"
+        )));
+    }
+    if request
+        .name
+        .as_ref()
+        .is_some_and(|n| n.chars().count() > MAX_NAME_LENGTH)
+    {
+        return Err(APIError::bad_request(format!(
+            "The name can be at most {MAX_NAME_LENGTH} characters long"
+        )));
+    }
+    if request
+        .notes
+        .as_ref()
+        .is_some_and(|n| n.chars().count() > MAX_NOTES_LENGTH)
+    {
+        return Err(APIError::bad_request(format!(
+            "The notes can be at most {MAX_NOTES_LENGTH} characters long"
+        )));
+    }
+    validate_enemy_hero_ids(request.hero_id, &mut request.filters.enemy_hero_ids)?;
+    if request
+        .filters
+        .buckets
+        .as_ref()
+        .is_some_and(|b| b.len() > MAX_BUCKETS)
+    {
+        return Err(APIError::bad_request(format!(
+            "buckets can contain at most {MAX_BUCKETS} edges"
+        )));
+    }
+    Ok(())
+}
+
+/// Checks the request body against the assets, so that only valid drafts are stored.
+async fn validate_assets(state: &AppState, request: &BuildDraftRequest) -> APIResult<()> {
+    state
+        .assets_client
+        .fetch_hero_name_from_id(request.hero_id)
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch hero: {e}")))?
+        .ok_or_else(|| APIError::bad_request(format!("Hero {} not found", request.hero_id)))?;
+    let upgrade_ids: HashSet<u32> = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?
+        .into_iter()
+        .filter(|item| item.item_type.as_deref() == Some("upgrade"))
+        .map(|item| item.id)
+        .collect();
+    if let Some(item_id) = request
+        .item_ids
+        .iter()
+        .find(|id| !upgrade_ids.contains(*id))
+    {
+        return Err(APIError::bad_request(format!(
+            "Item {item_id} is not an upgrade"
+        )));
+    }
+    Ok(())
+}
+
+async fn apply_write_limits(state: &AppState, rate_limit_key: &RateLimitKey) -> APIResult<()> {
+    state
+        .rate_limit_client
+        .apply_limits(
+            rate_limit_key,
+            "build_creator_drafts",
+            &[
+                Quota::ip_limit(100, Duration::from_secs(60 * 60)),
+                Quota::key_limit(1000, Duration::from_secs(60 * 60)),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+async fn fetch_draft(pg_client: &sqlx::PgPool, share_code: &str) -> APIResult<BuildDraftRow> {
+    if !is_valid_share_code(share_code) {
+        return Err(APIError::bad_request(format!(
+            "Invalid share code: {share_code}"
+        )));
+    }
+    sqlx::query_as("SELECT * FROM build_drafts WHERE share_code = $1")
+        .bind(share_code)
+        .fetch_optional(pg_client)
+        .await?
+        .ok_or_else(|| {
+            APIError::status_msg(
+                StatusCode::NOT_FOUND,
+                format!("Draft {share_code} not found"),
+            )
+        })
+}
+
+/// Fetches a draft and makes sure the requester is allowed to modify it.
+async fn fetch_editable_draft(
+    pg_client: &sqlx::PgPool,
+    share_code: &str,
+    rate_limit_key: &RateLimitKey,
+    headers: &HeaderMap,
+) -> APIResult<BuildDraftRow> {
+    let draft = fetch_draft(pg_client, share_code).await?;
+    if !draft.is_editable_by(rate_limit_key.api_key, edit_token(headers)) {
+        return Err(APIError::status_msg(
+            StatusCode::FORBIDDEN,
+            format!("You are not allowed to modify draft {share_code}"),
+        ));
+    }
+    Ok(draft)
+}
+
+pub(super) async fn create_draft(
+    rate_limit_key: RateLimitKey,
+    State(state): State<AppState>,
+    Json(mut request): Json<BuildDraftRequest>,
+) -> APIResult<impl IntoResponse> {
+    validate_request(&mut request)?;
+    apply_write_limits(&state, &rate_limit_key).await?;
+    validate_assets(&state, &request).await?;
+
+    // Drafts created with an API key are owned by it, all others by a fresh edit token
+    let edit_token = rate_limit_key.api_key.is_none().then(Uuid::new_v4);
+    let item_ids: Vec<i32> = request
+        .item_ids
+        .iter()
+        .copied()
+        .map(u32::cast_signed)
+        .collect();
+    for _ in 0..SHARE_CODE_ATTEMPTS {
+        let row: Option<BuildDraftRow> = sqlx::query_as(
+            "
+            INSERT INTO build_drafts (share_code, hero_id, name, item_ids, notes, filters, owner_api_key, edit_token)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (share_code) DO NOTHING
+            RETURNING *
+            ",
+        )
+        .bind(generate_share_code())
+        .bind(request.hero_id.cast_signed())
+        .bind(&request.name)
+        .bind(&item_ids)
+        .bind(&request.notes)
+        .bind(sqlx::types::Json(&request.filters))
+        .bind(rate_limit_key.api_key)
+        .bind(edit_token)
+        .fetch_optional(&state.pg_client)
+        .await?;
+        if let Some(row) = row {
+            return Ok((
+                StatusCode::CREATED,
+                Json(CreatedBuildDraft {
+                    draft: row.into(),
+                    edit_token,
+                }),
+            ));
+        }
+    }
+    Err(APIError::internal("Failed to generate a unique share code"))
+}
+
+pub(super) async fn get_draft(
+    Path(share_code): Path<String>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    let draft: BuildDraft = fetch_draft(&state.pg_client, &share_code).await?.into();
+    Ok(Json(draft))
+}
+
+pub(super) async fn update_draft(
+    rate_limit_key: RateLimitKey,
+    headers: HeaderMap,
+    Path(share_code): Path<String>,
+    State(state): State<AppState>,
+    Json(mut request): Json<BuildDraftRequest>,
+) -> APIResult<impl IntoResponse> {
+    validate_request(&mut request)?;
+    apply_write_limits(&state, &rate_limit_key).await?;
+    fetch_editable_draft(&state.pg_client, &share_code, &rate_limit_key, &headers).await?;
+    validate_assets(&state, &request).await?;
+
+    let item_ids: Vec<i32> = request
+        .item_ids
+        .iter()
+        .copied()
+        .map(u32::cast_signed)
+        .collect();
+    let row: BuildDraftRow = sqlx::query_as(
+        "
+        UPDATE build_drafts
+        SET hero_id = $2, name = $3, item_ids = $4, notes = $5, filters = $6, updated_at = now()
+        WHERE share_code = $1
+        RETURNING *
+        ",
+    )
+    .bind(&share_code)
+    .bind(request.hero_id.cast_signed())
+    .bind(&request.name)
+    .bind(&item_ids)
+    .bind(&request.notes)
+    .bind(sqlx::types::Json(&request.filters))
+    .fetch_one(&state.pg_client)
+    .await?;
+    Ok(Json(BuildDraft::from(row)))
+}
+
+pub(super) async fn delete_draft(
+    rate_limit_key: RateLimitKey,
+    headers: HeaderMap,
+    Path(share_code): Path<String>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    apply_write_limits(&state, &rate_limit_key).await?;
+    fetch_editable_draft(&state.pg_client, &share_code, &rate_limit_key, &headers).await?;
+
+    sqlx::query("DELETE FROM build_drafts WHERE share_code = $1")
+        .bind(&share_code)
+        .execute(&state.pg_client)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(owner_api_key: Option<Uuid>, edit_token: Option<Uuid>) -> BuildDraftRow {
+        BuildDraftRow {
+            share_code: "aB3dE5gH".to_owned(),
+            hero_id: 7,
+            name: None,
+            item_ids: vec![1, 2],
+            notes: None,
+            filters: sqlx::types::Json(BuildDraftFilters::default()),
+            owner_api_key,
+            edit_token,
+            created_at: DateTime::default(),
+            updated_at: DateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_share_code() {
+        let share_code = generate_share_code();
+        assert!(is_valid_share_code(&share_code));
+        assert!(!is_valid_share_code("abc"));
+        assert!(!is_valid_share_code("abc-efgh"));
+        assert!(!is_valid_share_code("abcdefghi"));
+    }
+
+    #[test]
+    fn test_is_editable_by() {
+        let key = Uuid::new_v4();
+        let token = Uuid::new_v4();
+        assert!(row(Some(key), None).is_editable_by(Some(key), None));
+        assert!(row(None, Some(token)).is_editable_by(None, Some(token)));
+        assert!(!row(Some(key), None).is_editable_by(Some(token), Some(key)));
+        assert!(!row(None, Some(token)).is_editable_by(None, None));
+        assert!(!row(None, None).is_editable_by(None, None));
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let mut request = BuildDraftRequest {
+            hero_id: 7,
+            name: Some("Draft".to_owned()),
+            item_ids: vec![1, 2, 3],
+            notes: None,
+            filters: BuildDraftFilters::default(),
+        };
+        assert!(validate_request(&mut request).is_ok());
+        request.item_ids = vec![1, 2, 1];
+        assert!(validate_request(&mut request).is_err());
+        request.item_ids = vec![];
+        request.notes = Some("a".repeat(MAX_NOTES_LENGTH + 1));
+        assert!(validate_request(&mut request).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use itertools::Itertools;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::confidence::wilson_lower_bound;
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, build_info_filters, fetch_hero, validate_ability_order,
+};
+use crate::routes::v1::build_creator::recommend::{BUILD_SLOTS, MAX_ITEMS_PER_SLOT};
+use crate::routes::v1::build_creator::structs::{
+    AbilityOrderEvaluation, BuildEvaluation, BuildEvaluationRequest, ItemContribution,
+};
+use crate::services::assets::types::AssetsItem;
+
+/// Share of the build items a match has to contain to count as a close match
+const MIN_OVERLAP_RATIO: f64 = 0.75;
+/// Number of close matches below which the expected winrate is considered unreliable
+const MIN_RELIABLE_MATCHES: u64 = 100;
+/// Shop slots every build should cover
+const SHOP_SLOTS: [&str; 3] = ["weapon", "vitality", "spirit"];
+
+#[derive(Debug, Clone, Row, Deserialize)]
+struct ItemContributionRow {
+    item_id: u32,
+    wins_with: u64,
+    matches_with: u64,
+    wins_without: u64,
+    matches_without: u64,
+    ability_order_wins: u64,
+    ability_order_matches: u64,
+}
+
+/// Default number of build items a match has to contain, 75% of the build rounded up
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn default_min_matching_items(build_size: usize) -> u32 {
+    (build_size as f64 * MIN_OVERLAP_RATIO).ceil() as u32
+}
+
+fn build_query(request: &BuildEvaluationRequest) -> String {
+    let info_filters = build_info_filters(&BuildCreatorQuery {
+        hero_id: request.hero_id,
+        min_unix_timestamp: request.min_unix_timestamp,
+        max_unix_timestamp: request.max_unix_timestamp,
+        min_average_badge: request.min_average_badge,
+        max_average_badge: request.max_average_badge,
+        ..Default::default()
+    });
+    let hero_id = request.hero_id;
+    let item_ids = request.item_ids.iter().join(", ");
+    let min_matching_items = request
+        .min_matching_items
+        .unwrap_or_else(|| default_min_matching_items(request.item_ids.len()));
+    let ability_order_expr = if request.ability_order.is_empty() {
+        "false".to_owned()
+    } else {
+        format!(
+            "arraySlice(arrayFilter(x -> has(ability_ids_array, x), items.item_id), 1, {}) = [{}]",
+            request.ability_order.len(),
+            request.ability_order.iter().join(", ")
+        )
+    };
+    format!(
+        "
+WITH
+    (SELECT groupArray(id) FROM items WHERE type = 'ability') AS ability_ids_array,
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    ),
+    t_players AS (
+        SELECT
+            won,
+            items.item_id AS purchased_item_ids,
+            {ability_order_expr} AS ability_order_match
+        FROM match_player
+        WHERE match_id IN (SELECT match_id FROM t_matches)
+            AND hero_id = {hero_id}
+            AND length(arrayIntersect(items.item_id, [{item_ids}])) >= {min_matching_items}
+    )
+SELECT
+    toUInt32(build_item_id) AS item_id,
+    sumIf(won, has(purchased_item_ids, build_item_id)) AS wins_with,
+    countIf(has(purchased_item_ids, build_item_id)) AS matches_with,
+    sumIf(won, NOT has(purchased_item_ids, build_item_id)) AS wins_without,
+    countIf(NOT has(purchased_item_ids, build_item_id)) AS matches_without,
+    sumIf(won, ability_order_match) AS ability_order_wins,
+    countIf(ability_order_match) AS ability_order_matches
+FROM t_players
+    ARRAY JOIN [{item_ids}] AS build_item_id
+GROUP BY item_id
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<ItemContributionRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<ItemContributionRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn winrate(wins: u64, matches: u64) -> Option<f64> {
+    (matches > 0).then(|| wins as f64 / matches as f64)
+}
+
+/// Checks the build for common mistakes, independent of the match data.
+fn build_warnings(items: &[&AssetsItem]) -> Vec<String> {
+    let mut warnings = vec![];
+    if items.len() > BUILD_SLOTS {
+        warnings.push(format!(
+            "The build has {} items, but only {BUILD_SLOTS} fit into the item slots",
+            items.len()
+        ));
+    }
+    if !items.iter().any(|item| item.tier == Some(4)) {
+        warnings.push("The build has no tier 4 item".to_owned());
+    }
+    let slot_counts = items
+        .iter()
+        .filter_map(|item| item.slot.as_deref())
+        .counts();
+    for slot in SHOP_SLOTS {
+        match slot_counts.get(slot).copied().unwrap_or_default() {
+            0 => warnings.push(format!("The build has no {slot} item")),
+            count if count > MAX_ITEMS_PER_SLOT => warnings.push(format!(
+                "The build has {count} {slot} items, more than {MAX_ITEMS_PER_SLOT} makes it \
+                 unbalanced"
+            )),
+            _ => {}
+        }
+    }
+    warnings
+}
+
+pub(super) async fn build_creator_evaluate(
+    State(state): State<AppState>,
+    Json(mut request): Json<BuildEvaluationRequest>,
+) -> APIResult<impl IntoResponse> {
+    if request.item_ids.is_empty() {
+        return Err(APIError::bad_request("At least one item is required"));
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = request.item_ids.iter().find(|id| !seen.insert(**id)) {
+        return Err(APIError::bad_request(format!(
+            "Item {duplicate} is in the build more than once"
+        )));
+    }
+    if let Some(min_matching_items) = request.min_matching_items
+        && (min_matching_items == 0 || min_matching_items as usize > request.item_ids.len())
+    {
+        return Err(APIError::bad_request(
+            "min_matching_items must be between 1 and the number of items in the build",
+        ));
+    }
+
+    // Normalize timestamps to hour boundaries for better caching
+    request.min_unix_timestamp = request.min_unix_timestamp.map(|v| v - v % 3600);
+    request.max_unix_timestamp = request.max_unix_timestamp.map(|v| v + 3600 - v % 3600);
+
+    let hero = fetch_hero(&state, request.hero_id).await?;
+    let items_metadata = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?;
+    let upgrades: HashMap<u32, &AssetsItem> = items_metadata
+        .iter()
+        .filter(|item| item.item_type.as_deref() == Some("upgrade"))
+        .map(|item| (item.id, item))
+        .collect();
+
+    let build_items = request
+        .item_ids
+        .iter()
+        .map(|item_id| {
+            upgrades
+                .get(item_id)
+                .copied()
+                .ok_or_else(|| APIError::bad_request(format!("Item {item_id} is not an upgrade")))
+        })
+        .collect::<APIResult<Vec<_>>>()?;
+    validate_ability_order(&hero, &items_metadata, &request.ability_order)?;
+
+    let query_str = build_query(&request);
+    debug!(?query_str);
+    let rows = run_query(&state.ch_client_ro, &query_str).await?;
+    let rows: HashMap<u32, ItemContributionRow> =
+        rows.into_iter().map(|row| (row.item_id, row)).collect();
+
+    // Every row covers all close matches, so the totals can be taken from any of them
+    let (wins, matches) = rows
+        .values()
+        .next()
+        .map(|row| {
+            (
+                row.wins_with + row.wins_without,
+                row.matches_with + row.matches_without,
+            )
+        })
+        .unwrap_or_default();
+    let expected_winrate = winrate(wins, matches);
+    let lower_bound = wilson_lower_bound(wins, matches);
+
+    let items = build_items
+        .iter()
+        .map(|item| {
+            let row = rows.get(&item.id);
+            let winrate_with = row.and_then(|r| winrate(r.wins_with, r.matches_with));
+            let winrate_without = row.and_then(|r| winrate(r.wins_without, r.matches_without));
+            ItemContribution {
+                item_id: item.id,
+                name: item.name.clone(),
+                tier: item.tier.unwrap_or_default(),
+                slot: item.slot.clone(),
+                matches_with: row.map(|r| r.matches_with).unwrap_or_default(),
+                winrate_with,
+                matches_without: row.map(|r| r.matches_without).unwrap_or_default(),
+                winrate_without,
+                marginal_contribution: winrate_with.zip(winrate_without).map(|(w, wo)| w - wo),
+            }
+        })
+        .collect();
+
+    let ability_order = (!request.ability_order.is_empty()).then(|| {
+        let (wins, matches) = rows
+            .values()
+            .next()
+            .map(|row| (row.ability_order_wins, row.ability_order_matches))
+            .unwrap_or_default();
+        AbilityOrderEvaluation {
+            matches,
+            winrate: winrate(wins, matches),
+        }
+    });
+
+    let mut warnings = build_warnings(&build_items);
+    if matches < MIN_RELIABLE_MATCHES {
+        warnings.push(format!(
+            "Only {matches} matches are similar to the build, the expected winrate is unreliable"
+        ));
+    }
+
+    Ok(Json(BuildEvaluation {
+        hero_id: request.hero_id,
+        hero_name: hero.name,
+        matches,
+        expected_winrate,
+        wilson_lower_bound: lower_bound,
+        score: (lower_bound * 100.0).round(),
+        items,
+        ability_order,
+        warnings,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: u32, tier: u32, slot: &str) -> AssetsItem {
+        AssetsItem {
+            id,
+            name: format!("Item {id}"),
+            tier: Some(tier),
+            cost: None,
+            item_type: Some("upgrade".to_owned()),
+            slot: Some(slot.to_owned()),
+            class_name: None,
+            component_items: None,
+        }
+    }
+
+    #[test]
+    fn test_default_min_matching_items() {
+        assert_eq!(default_min_matching_items(1), 1);
+        assert_eq!(default_min_matching_items(4), 3);
+        assert_eq!(default_min_matching_items(12), 9);
+    }
+
+    #[test]
+    fn test_build_query() {
+        let request = BuildEvaluationRequest {
+            hero_id: 7,
+            item_ids: vec![1, 2, 3, 4],
+            ability_order: vec![10, 11],
+            min_matching_items: None,
+            min_unix_timestamp: Some(1_672_531_200),
+            max_unix_timestamp: None,
+            min_average_badge: None,
+            max_average_badge: None,
+        };
+        let sql = build_query(&request);
+        assert!(sql.contains("hero_id = 7"));
+        assert!(sql.contains("length(arrayIntersect(items.item_id, [1, 2, 3, 4])) >= 3"));
+        assert!(sql.contains("ARRAY JOIN [1, 2, 3, 4] AS build_item_id"));
+        assert!(sql.contains(
+            "arraySlice(arrayFilter(x -> has(ability_ids_array, x), items.item_id), 1, 2) = [10, \
+             11]"
+        ));
+        assert!(sql.contains("start_time >= 1672531200"));
+    }
+
+    #[test]
+    fn test_build_warnings() {
+        let items = [
+            item(1, 1, "weapon"),
+            item(2, 2, "weapon"),
+            item(3, 3, "vitality"),
+        ];
+        let warnings = build_warnings(&items.iter().collect::<Vec<_>>());
+        assert_eq!(
+            warnings,
+            vec![
+                "The build has no tier 4 item".to_owned(),
+                "The build has no spirit item".to_owned(),
+            ]
+        );
+
+        let items = (0..6).map(|i| item(i, 4, "spirit")).collect::<Vec<_>>();
+        let warnings = build_warnings(&items.iter().collect::<Vec<_>>());
+        assert!(
+            warnings.contains(
+                &"The build has 6 spirit items, more than 5 makes it unbalanced".to_owned()
+            )
+        );
+    }
+}
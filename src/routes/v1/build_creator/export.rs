@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::handlers::{fetch_hero, validate_ability_order};
+use crate::routes::v1::build_creator::structs::BuildExportRequest;
+use crate::routes::v1::builds::structs::{
+    BuildHero, BuildHeroDetails, BuildHeroDetailsAbilityOrder,
+    BuildHeroDetailsAbilityOrderCurrencyChange, BuildHeroDetailsCategory,
+    BuildHeroDetailsCategoryAbility,
+};
+
+/// Currency spent to unlock an ability
+const ABILITY_UNLOCK_CURRENCY: i32 = 2;
+/// Currency spent to upgrade an ability
+const ABILITY_POINT_CURRENCY: i32 = 1;
+/// Ability point costs of the three upgrade levels
+const ABILITY_UPGRADE_COSTS: [i32; 3] = [1, 2, 5];
+
+/// Mod categories of the exported build, items are assigned by tier
+const MOD_CATEGORIES: [&str; 3] = ["Early Game", "Mid Game", "Late Game"];
+
+fn mod_category_index(tier: u32) -> usize {
+    match tier {
+        0..=2 => 0,
+        3 => 1,
+        _ => 2,
+    }
+}
+
+/// Converts an ability order into the currency changes of an in-game build.
+///
+/// The first occurrence of an ability unlocks it, every further occurrence upgrades it.
+fn currency_changes(
+    ability_order: &[u32],
+) -> APIResult<Vec<BuildHeroDetailsAbilityOrderCurrencyChange>> {
+    let mut levels: HashMap<u32, usize> = HashMap::new();
+    ability_order
+        .iter()
+        .map(|&ability_id| {
+            let level = levels.entry(ability_id).or_default();
+            let (currency_type, delta) = if *level == 0 {
+                (ABILITY_UNLOCK_CURRENCY, -1)
+            } else {
+                let cost = ABILITY_UPGRADE_COSTS.get(*level - 1).ok_or_else(|| {
+                    APIError::bad_request(format!(
+                        "Ability {ability_id} can be upgraded at most {} times",
+                        ABILITY_UPGRADE_COSTS.len()
+                    ))
+                })?;
+                (ABILITY_POINT_CURRENCY, -cost)
+            };
+            *level += 1;
+            Ok(BuildHeroDetailsAbilityOrderCurrencyChange {
+                ability_id,
+                currency_type,
+                delta,
+                annotation: None,
+            })
+        })
+        .collect()
+}
+
+pub(super) async fn build_creator_export(
+    State(state): State<AppState>,
+    Json(request): Json<BuildExportRequest>,
+) -> APIResult<impl IntoResponse> {
+    if request.item_ids.is_empty() {
+        return Err(APIError::bad_request("At least one item is required"));
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = request.item_ids.iter().find(|id| !seen.insert(**id)) {
+        return Err(APIError::bad_request(format!(
+            "Item {duplicate} is in the build more than once"
+        )));
+    }
+
+    if let Some(item_id) = request
+        .annotations
+        .keys()
+        .find(|id| !request.item_ids.contains(*id))
+    {
+        return Err(APIError::bad_request(format!(
+            "Item {item_id} is annotated but not in the build"
+        )));
+    }
+
+    let hero = fetch_hero(&state, request.hero_id).await?;
+
+    let items_metadata = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?;
+    let upgrade_tiers: HashMap<u32, u32> = items_metadata
+        .iter()
+        .filter(|item| item.item_type.as_deref() == Some("upgrade"))
+        .map(|item| (item.id, item.tier.unwrap_or_default()))
+        .collect();
+
+    let mut mods_by_category: [Vec<BuildHeroDetailsCategoryAbility>; 3] = Default::default();
+    for &item_id in &request.item_ids {
+        let tier = upgrade_tiers
+            .get(&item_id)
+            .ok_or_else(|| APIError::bad_request(format!("Item {item_id} is not an upgrade")))?;
+        if let Some(mods) = mods_by_category.get_mut(mod_category_index(*tier)) {
+            mods.push(BuildHeroDetailsCategoryAbility {
+                ability_id: item_id,
+                annotation: request.annotations.get(&item_id).cloned(),
+                required_flex_slots: None,
+                sell_priority: None,
+                imbue_target_ability_id: None,
+            });
+        }
+    }
+    let mod_categories = MOD_CATEGORIES
+        .into_iter()
+        .zip(mods_by_category)
+        .filter(|(_, mods)| !mods.is_empty())
+        .map(|(name, mods)| BuildHeroDetailsCategory {
+            name: name.to_owned(),
+            width: None,
+            height: None,
+            description: None,
+            mods: Some(mods),
+            optional: None,
+        })
+        .collect();
+
+    validate_ability_order(&hero, &items_metadata, &request.ability_order)?;
+    let ability_order = if request.ability_order.is_empty() {
+        None
+    } else {
+        Some(BuildHeroDetailsAbilityOrder {
+            currency_changes: Some(currency_changes(&request.ability_order)?),
+        })
+    };
+
+    Ok(Json(BuildHero {
+        hero_id: request.hero_id,
+        hero_build_id: 0,
+        author_account_id: request.author_account_id.unwrap_or_default(),
+        last_updated_timestamp: None,
+        publish_timestamp: None,
+        name: request
+            .name
+            .unwrap_or_else(|| format!("{} Build", hero.name)),
+        description: request.description,
+        language: request.language.unwrap_or_default() as u32,
+        version: 1,
+        origin_build_id: 0,
+        tags: vec![],
+        development_build: None,
+        details: BuildHeroDetails {
+            mod_categories,
+            ability_order,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_currency_changes_unlock_then_upgrade() {
+        let changes = currency_changes(&[1, 2, 1, 1, 1]).unwrap();
+        let changes = changes
+            .iter()
+            .map(|c| (c.ability_id, c.currency_type, c.delta))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (1, ABILITY_UNLOCK_CURRENCY, -1),
+                (2, ABILITY_UNLOCK_CURRENCY, -1),
+                (1, ABILITY_POINT_CURRENCY, -1),
+                (1, ABILITY_POINT_CURRENCY, -2),
+                (1, ABILITY_POINT_CURRENCY, -5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_currency_changes_too_many_upgrades() {
+        assert!(currency_changes(&[1, 1, 1, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_mod_category_index() {
+        assert_eq!(mod_category_index(1), 0);
+        assert_eq!(mod_category_index(2), 0);
+        assert_eq!(mod_category_index(3), 1);
+        assert_eq!(mod_category_index(4), 2);
+    }
+}
@@ -1,56 +1,101 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use axum::Json;
 use axum::extract::State;
-use axum::response::IntoResponse;
+use axum::http::header::CACHE_CONTROL;
+use axum::response::{IntoResponse, Response};
 use axum_extra::extract::Query;
 use cached::TimedCache;
 use cached::proc_macro::cached;
 use clickhouse::Row;
+use futures::try_join;
 use serde::Deserialize;
 use tracing::debug;
 use utoipa::IntoParams;
 
 use crate::context::AppState;
 use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::abilities::fetch_ability_orders;
+use crate::routes::v1::build_creator::confidence::{smoothed_winrate, wilson_lower_bound};
+use crate::routes::v1::build_creator::patch::patch_window;
+use crate::routes::v1::build_creator::personal::fetch_personal_stats;
 use crate::routes::v1::build_creator::structs::{
-    BuildCreatorItem, BuildCreatorResponse, BucketWinrate, SortBy, TimingMode,
+    BucketWinrate, BuildCreatorItem, BuildCreatorResponse, NETWORTH_BUCKET_WIDTH, SortBy,
+    TimingMode,
+};
+use crate::routes::v1::build_creator::upgrades::{UpgradeGraph, fetch_upgrade_chains};
+use crate::services::assets::types::{AssetsHero, AssetsItem};
+use crate::utils::parse::{
+    comma_separated_deserialize_option, default_last_month_timestamp, parse_number,
+    parse_number_option, parse_steam_id_option,
 };
-use crate::utils::parse::default_last_month_timestamp;
 
-#[allow(clippy::unnecessary_wraps)]
-fn default_min_matches() -> Option<u32> {
-    Some(50)
-}
+/// Maximum number of enemy heroes, the size of a team
+const MAX_ENEMY_HEROES: usize = 6;
+/// Maximum number of custom bucket edges
+pub(super) const MAX_BUCKETS: usize = 20;
+/// `Cache-Control` of responses including the personal stats of accounts
+const PRIVATE_CACHE_CONTROL: &str = "private, max-age=3600";
 
-#[derive(Debug, Clone, Deserialize, IntoParams, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Deserialize, IntoParams, Eq, PartialEq, Hash, Default)]
 pub(crate) struct BuildCreatorQuery {
     /// Hero ID to get item stats for. See more: <https://assets.deadlock-api.com/v2/heroes>
+    #[serde(deserialize_with = "parse_number")]
     pub hero_id: u32,
-    /// Minimum number of matches for statistical significance.
-    #[serde(default = "default_min_matches")]
-    #[param(minimum = 1, default = 50)]
+    /// Minimum number of matches for statistical significance. **Default:** 50, 1000 for the
+    /// items of recommended builds.
+    #[serde(default, deserialize_with = "parse_number_option")]
+    #[param(minimum = 1)]
     pub min_matches: Option<u32>,
     /// Filter matches based on their start time (Unix timestamp). **Default:** 30 days ago.
-    #[serde(default = "default_last_month_timestamp")]
+    #[serde(
+        default = "default_last_month_timestamp",
+        deserialize_with = "parse_number_option"
+    )]
     #[param(default = default_last_month_timestamp)]
     pub min_unix_timestamp: Option<i64>,
     /// Filter matches based on their start time (Unix timestamp).
+    #[serde(default, deserialize_with = "parse_number_option")]
     pub max_unix_timestamp: Option<i64>,
+    /// Filter matches based on the patch they were played on: `latest`, `previous` or a date
+    /// (`YYYY-MM-DD`) selecting the patch that was live at the end of that day. Overrides
+    /// `min_unix_timestamp` and `max_unix_timestamp`.
+    pub patch: Option<String>,
     /// Filter matches based on the average badge level. See more: <https://assets.deadlock-api.com/v2/ranks>
+    #[serde(default, deserialize_with = "parse_number_option")]
     #[param(minimum = 0, maximum = 116)]
     pub min_average_badge: Option<u8>,
     /// Filter matches based on the average badge level.
+    #[serde(default, deserialize_with = "parse_number_option")]
     #[param(minimum = 0, maximum = 116)]
     pub max_average_badge: Option<u8>,
-    /// Sort items by: win_rate (default), popularity, or avg_buy_order
+    /// Sort items by: win_rate (default), popularity, avg_buy_order or wilson_lower_bound
     #[serde(default)]
     #[param(inline)]
     pub sort_by: SortBy,
-    /// Timing mode for winrate buckets: networth (default) or game_time
+    /// Timing mode for winrate buckets: networth (default), game_time, relative_game_time or
+    /// purchase_order
     #[serde(default)]
     #[param(inline)]
     pub timing_mode: TimingMode,
+    /// Comma separated list of the lower bucket edges, in souls for networth, minutes for
+    /// game_time, percent of the match duration for relative_game_time and the purchase position
+    /// for purchase_order. The last bucket is open-ended. Defaults depend on the timing mode.
+    #[param(value_type = Option<String>)]
+    #[serde(default, deserialize_with = "comma_separated_deserialize_option")]
+    pub buckets: Option<Vec<u32>>,
+    /// Comma separated list of enemy hero IDs. Only matches where the hero faced all of these
+    /// heroes are considered. See more: <https://assets.deadlock-api.com/v2/heroes>
+    #[param(value_type = Option<String>)]
+    #[serde(default, deserialize_with = "comma_separated_deserialize_option")]
+    pub enemy_hero_ids: Option<Vec<u32>>,
+    /// Account ID to get the personal item usage of, next to the global stats.
+    #[serde(default, deserialize_with = "parse_steam_id_option")]
+    pub account_id: Option<u32>,
+    /// Comma separated list of account IDs to get the combined personal item usage of.
+    #[param(inline, min_items = 1, max_items = 1_000)]
+    #[serde(default, deserialize_with = "comma_separated_deserialize_option")]
+    pub account_ids: Option<Vec<u32>>,
 }
 
 #[derive(Debug, Clone, Row, Deserialize)]
@@ -66,7 +111,8 @@ struct ItemStatsRow {
     sell_count: u64,
 }
 
-fn build_query(query: &BuildCreatorQuery) -> String {
+/// Builds the `match_info` filters shared by all build creator queries.
+pub(super) fn build_info_filters(query: &BuildCreatorQuery) -> String {
     let mut info_filters = Vec::new();
 
     if let Some(min_unix_timestamp) = query.min_unix_timestamp {
@@ -90,36 +136,214 @@ fn build_query(query: &BuildCreatorQuery) -> String {
         }
     }
 
-    let info_filters = if info_filters.is_empty() {
+    if info_filters.is_empty() {
         String::new()
     } else {
         format!(" AND {}", info_filters.join(" AND "))
+    }
+}
+
+/// Normalizes the enemy heroes of a query, their order does not matter and an empty list is the
+/// same as no list.
+///
+/// Fails if the hero itself is one of the enemies or there are more enemies than fit in a team.
+pub(super) fn validate_enemy_hero_ids(
+    hero_id: u32,
+    enemy_hero_ids: &mut Option<Vec<u32>>,
+) -> APIResult<()> {
+    if let Some(ids) = enemy_hero_ids {
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.contains(&hero_id) {
+            return Err(APIError::bad_request(
+                "enemy_hero_ids must not contain the hero itself",
+            ));
+        }
+        if ids.len() > MAX_ENEMY_HEROES {
+            return Err(APIError::bad_request(format!(
+                "At most {MAX_ENEMY_HEROES} enemy heroes are allowed"
+            )));
+        }
+    }
+    if enemy_hero_ids.as_ref().is_some_and(Vec::is_empty) {
+        *enemy_hero_ids = None;
+    }
+    Ok(())
+}
+
+/// Fetches a hero from the assets, an unknown hero is a bad request.
+pub(super) async fn fetch_hero(state: &AppState, hero_id: u32) -> APIResult<AssetsHero> {
+    state
+        .assets_client
+        .fetch_heroes()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch hero: {e}")))?
+        .into_iter()
+        .find(|hero| hero.id == hero_id)
+        .ok_or_else(|| APIError::bad_request(format!("Hero {hero_id} not found")))
+}
+
+/// Checks that the ability order only contains abilities of the hero, the items in its signature
+/// slots.
+pub(super) fn validate_ability_order(
+    hero: &AssetsHero,
+    items: &[AssetsItem],
+    ability_order: &[u32],
+) -> APIResult<()> {
+    let ability_class_names: HashSet<&str> = hero
+        .items
+        .iter()
+        .filter(|(slot, _)| slot.starts_with("signature"))
+        .map(|(_, class_name)| class_name.as_str())
+        .collect();
+    let ability_ids: HashSet<u32> = items
+        .iter()
+        .filter(|item| item.item_type.as_deref() == Some("ability"))
+        .filter(|item| {
+            item.class_name
+                .as_deref()
+                .is_some_and(|c| ability_class_names.contains(c))
+        })
+        .map(|item| item.id)
+        .collect();
+    if let Some(ability_id) = ability_order.iter().find(|id| !ability_ids.contains(*id)) {
+        return Err(APIError::bad_request(format!(
+            "Ability {ability_id} is not an ability of hero {}",
+            hero.id
+        )));
+    }
+    Ok(())
+}
+
+/// Normalizes the match filters shared by all build creator endpoints.
+///
+/// Rounds the timestamps to hours, restricts the matches to the window of the patch and validates
+/// the enemy heroes.
+pub(super) async fn normalize_match_filters(
+    state: &AppState,
+    query: &mut BuildCreatorQuery,
+) -> APIResult<()> {
+    // Normalize timestamps to hour boundaries for better caching
+    query.min_unix_timestamp = query.min_unix_timestamp.map(|v| v - v % 3600);
+    query.max_unix_timestamp = query.max_unix_timestamp.map(|v| v + 3600 - v % 3600);
+
+    // Restrict matches to the window of the patch
+    if let Some(patch) = query.patch.take() {
+        let window = patch_window(state, &patch).await?;
+        query.min_unix_timestamp = Some(window.min_unix_timestamp);
+        query.max_unix_timestamp = window.max_unix_timestamp;
+    }
+
+    validate_enemy_hero_ids(query.hero_id, &mut query.enemy_hero_ids)
+}
+
+/// Sorts and deduplicates custom bucket edges, and fails if there are none or too many.
+pub(super) fn validate_buckets(buckets: &mut Option<Vec<u32>>) -> APIResult<()> {
+    if let Some(buckets) = buckets {
+        buckets.sort_unstable();
+        buckets.dedup();
+        if buckets.is_empty() || buckets.len() > MAX_BUCKETS {
+            return Err(APIError::bad_request(format!(
+                "buckets must contain between 1 and {MAX_BUCKETS} edges"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Builds the `match_player` filter for matches where the hero faced all enemy heroes.
+pub(super) fn build_enemy_filter(query: &BuildCreatorQuery) -> String {
+    let Some(enemy_hero_ids) = query.enemy_hero_ids.as_ref().filter(|ids| !ids.is_empty()) else {
+        return String::new();
     };
+    format!(
+        "
+            AND (match_id, team) IN (
+                SELECT match_id, if(team = 'Team0', 'Team1', 'Team0')
+                FROM match_player
+                WHERE match_id IN (SELECT match_id FROM t_matches)
+                    AND hero_id IN ({})
+                GROUP BY match_id, team
+                HAVING uniqExact(hero_id) = {}
+            )",
+        enemy_hero_ids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        enemy_hero_ids.len()
+    )
+}
 
+/// Lower bucket edges of the query, the custom ones or the defaults of the timing mode.
+///
+/// `None` for the default net worth buckets, which have no upper limit.
+fn bucket_edges(query: &BuildCreatorQuery) -> Option<&[u32]> {
+    query
+        .buckets
+        .as_deref()
+        .or_else(|| query.timing_mode.default_buckets())
+}
+
+/// Maps a value to the lower edge of the bucket it falls into.
+///
+/// Values below the first edge fall into the first bucket, the last bucket is open-ended.
+pub(super) fn bucket_expr(value: &str, edges: &[u32]) -> String {
+    let (Some(first), Some(last)) = (edges.first(), edges.last()) else {
+        return "toUInt32(0)".to_owned();
+    };
+    if edges.len() == 1 {
+        return format!("toUInt32({first})");
+    }
+    let conditions = edges
+        .windows(2)
+        .map(|w| format!("{value} < {}, {}", w[1], w[0]))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("toUInt32(multiIf({conditions}, {last}))")
+}
+
+fn build_query(query: &BuildCreatorQuery) -> String {
+    let info_filters = build_info_filters(query);
+    let enemy_filter = build_enemy_filter(query);
     let hero_id = query.hero_id;
     let min_matches = query.min_matches.unwrap_or(50);
 
-    // Choose bucket expression based on timing mode
-    let (bucket_expr, extra_select) = match query.timing_mode {
+    // Value that is bucketed, in the unit of the bucket edges
+    let (bucket_value, extra_select) = match query.timing_mode {
         TimingMode::Networth => (
-            "toUInt32(floor(net_worth_at_buy / 5000) * 5000)".to_string(),
+            "net_worth_at_buy",
             ",coalesce(
                 arrayElementOrNull(
                     stats.net_worth,
                     arrayFirstIndex(ts -> ts >= it.game_time_s, stats.time_stamp_s) - 1
                 ), net_worth
-            ) AS net_worth_at_buy".to_string(),
+            ) AS net_worth_at_buy",
         ),
-        TimingMode::GameTime => (
-            // Game phases: 0=0-5min, 1=5-10min, 2=10-20min, 3=20-30min, 4=30+min
-            "toUInt32(CASE WHEN buy_time < 300 THEN 0 WHEN buy_time < 600 THEN 1 WHEN buy_time < 1200 THEN 2 WHEN buy_time < 1800 THEN 3 ELSE 4 END)".to_string(),
-            String::new(),
+        TimingMode::GameTime => ("buy_time / 60", ""),
+        TimingMode::RelativeGameTime => ("buy_time / duration_s * 100", ""),
+        TimingMode::PurchaseOrder => (
+            "purchase_position",
+            ",length(
+                arrayFilter(
+                    (id, t) -> has(upgrade_ids_array, id) AND t > 0 AND t <= it.game_time_s,
+                    items.item_id,
+                    items.game_time_s
+                )
+            ) AS purchase_position",
+        ),
+    };
+    let bucket_expr = match bucket_edges(query) {
+        Some(edges) => bucket_expr(bucket_value, edges),
+        None => format!(
+            "toUInt32(floor({bucket_value} / {NETWORTH_BUCKET_WIDTH}) * {NETWORTH_BUCKET_WIDTH})"
         ),
     };
 
     format!(
         "
 WITH
+    (SELECT groupArray(id) FROM items WHERE type = 'upgrade') AS upgrade_ids_array,
     t_upgrades AS (SELECT id FROM items WHERE type = 'upgrade'),
     t_matches AS (
         SELECT match_id, start_time, duration_s
@@ -139,7 +363,7 @@ WITH
         WHERE match_id IN (SELECT match_id FROM t_matches)
             AND it.item_id IN t_upgrades
             AND it.game_time_s > 0
-            AND hero_id = {hero_id}
+            AND hero_id = {hero_id}{enemy_filter}
     )
 SELECT
     item_id,
@@ -176,12 +400,45 @@ async fn run_query(
 }
 
 pub(super) async fn build_creator_items(
-    Query(mut query): Query<BuildCreatorQuery>,
+    Query(query): Query<BuildCreatorQuery>,
     State(state): State<AppState>,
-) -> APIResult<impl IntoResponse> {
-    // Normalize timestamps to hour boundaries for better caching
-    query.min_unix_timestamp = query.min_unix_timestamp.map(|v| v - v % 3600);
-    query.max_unix_timestamp = query.max_unix_timestamp.map(|v| v + 3600 - v % 3600);
+) -> APIResult<Response> {
+    let account_scoped = query.account_id.is_some() || query.account_ids.is_some();
+    let response = Json(fetch_build_creator_response(&state, query, true).await?);
+    if account_scoped {
+        // Personal stats must not be stored by shared caches, e.g. after an account got protected
+        return Ok(([(CACHE_CONTROL, PRIVATE_CACHE_CONTROL)], response).into_response());
+    }
+    Ok(response.into_response())
+}
+
+/// Fetches the item stats for a hero and groups them by tier.
+///
+/// Shared by all build creator endpoints that work on top of the per-tier item stats. The upgrade
+/// chains and ability orders are only fetched `with_details`, otherwise they are left empty.
+pub(super) async fn fetch_build_creator_response(
+    state: &AppState,
+    mut query: BuildCreatorQuery,
+    with_details: bool,
+) -> APIResult<BuildCreatorResponse> {
+    normalize_match_filters(state, &mut query).await?;
+    validate_buckets(&mut query.buckets)?;
+
+    // Combine the accounts and remove protected ones
+    let mut account_ids = query.account_ids.take().unwrap_or_default();
+    account_ids.extend(query.account_id.take());
+    account_ids.sort_unstable();
+    account_ids.dedup();
+    if !account_ids.is_empty() {
+        let protected_users = state
+            .steam_client
+            .get_protected_users(&state.pg_client)
+            .await?;
+        account_ids.retain(|id| !protected_users.contains(id));
+        if account_ids.is_empty() {
+            return Err(APIError::protected_user());
+        }
+    }
 
     // Fetch hero name
     let hero_name = state
@@ -198,6 +455,13 @@ pub(super) async fn build_creator_items(
         .await
         .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?;
 
+    // Ability names for the ability orders
+    let ability_names: HashMap<u32, String> = items_metadata
+        .iter()
+        .filter(|item| item.item_type.as_deref() == Some("ability"))
+        .map(|item| (item.id, item.name.clone()))
+        .collect();
+
     // Create a lookup map for items
     let items_map: HashMap<u32, _> = items_metadata
         .into_iter()
@@ -205,10 +469,67 @@ pub(super) async fn build_creator_items(
         .map(|item| (item.id, item))
         .collect();
 
-    // Run the query
+    // Run the query, next to the winrates of upgrade chains, the best ability orders, the item
+    // winrates against any enemies and the item usage of the accounts under the same filters
     let query_str = build_query(&query);
     debug!(?query_str);
-    let stats = run_query(&state.ch_client_ro, &query_str).await?;
+    let upgrade_graph = UpgradeGraph::new(items_map.values());
+    let details = async {
+        if !with_details {
+            return Ok((HashMap::new(), Vec::new()));
+        }
+        try_join!(
+            fetch_upgrade_chains(state, &query, &upgrade_graph),
+            fetch_ability_orders(state, &query, &ability_names),
+        )
+    };
+    // Winrates of the items against any enemies, to compare the matchup against
+    let baseline_winrates = async {
+        if query.enemy_hero_ids.is_none() {
+            return Ok(None);
+        }
+        let baseline_query = BuildCreatorQuery {
+            enemy_hero_ids: None,
+            ..query.clone()
+        };
+        let query_str = build_query(&baseline_query);
+        debug!(?query_str);
+        let stats = run_query(&state.ch_client_ro, &query_str).await?;
+        Ok::<_, APIError>(Some(item_winrates(&stats)))
+    };
+    // Item usage of the accounts next to the one of successful players
+    let personal_stats = async {
+        if account_ids.is_empty() {
+            return Ok((HashMap::new(), None));
+        }
+        let (stats, matches) = fetch_personal_stats(state, &query, &account_ids).await?;
+        Ok((stats, Some(matches)))
+    };
+    let (
+        stats,
+        (mut upgrade_chains, ability_orders),
+        baseline_winrates,
+        (mut personal_stats, account_matches),
+    ) = try_join!(
+        async {
+            run_query(&state.ch_client_ro, &query_str)
+                .await
+                .map_err(APIError::from)
+        },
+        details,
+        baseline_winrates,
+        personal_stats,
+    )?;
+
+    // Average winrate of the hero over all item purchases, used as prior for smoothing
+    let (hero_wins, hero_purchases) = stats
+        .iter()
+        .fold((0, 0), |(w, m), row| (w + row.wins, m + row.matches));
+    let prior_winrate = if hero_purchases > 0 {
+        hero_wins as f64 / hero_purchases as f64
+    } else {
+        0.5
+    };
 
     // Group stats by item_id
     let mut item_stats: HashMap<u32, Vec<ItemStatsRow>> = HashMap::new();
@@ -217,6 +538,7 @@ pub(super) async fn build_creator_items(
     }
 
     // Build response grouped by tier
+    let bucket_edges = bucket_edges(&query);
     let mut tiers: HashMap<String, Vec<BuildCreatorItem>> = HashMap::new();
 
     for (item_id, stats_rows) in item_stats {
@@ -232,6 +554,7 @@ pub(super) async fn build_creator_items(
         // Build winrates by bucket
         let mut winrates_by_bucket: HashMap<String, BucketWinrate> = HashMap::new();
         let mut total_matches = 0u64;
+        let mut total_wins = 0u64;
         let mut total_buy_time = 0.0f64;
         let mut total_buy_time_count = 0u64;
         let mut total_sell_time = 0.0f64;
@@ -239,16 +562,13 @@ pub(super) async fn build_creator_items(
         let mut total_sell_count = 0u64;
 
         for row in &stats_rows {
-            // Convert bucket number to human-readable key based on timing mode
-            let bucket_key = match query.timing_mode {
-                TimingMode::Networth => format!("{}", row.bucket),
-                TimingMode::GameTime => match row.bucket {
-                    0 => "0-5".to_string(),
-                    1 => "5-10".to_string(),
-                    2 => "10-20".to_string(),
-                    3 => "20-30".to_string(),
-                    _ => "30+".to_string(),
-                },
+            // Convert the lower bucket edge to a human-readable key based on timing mode
+            let bucket_key = match bucket_edges {
+                Some(edges) => {
+                    let bucket_index = edges.binary_search(&row.bucket).unwrap_or_default();
+                    query.timing_mode.bucket_key(edges, bucket_index)
+                }
+                None => row.bucket.to_string(),
             };
 
             let winrate = if row.matches > 0 {
@@ -262,10 +582,13 @@ pub(super) async fn build_creator_items(
                 BucketWinrate {
                     winrate,
                     matches: row.matches,
+                    wilson_lower_bound: wilson_lower_bound(row.wins, row.matches),
+                    smoothed_winrate: smoothed_winrate(row.wins, row.matches, prior_winrate),
                 },
             );
 
             total_matches += row.matches;
+            total_wins += row.wins;
             total_buy_time += row.avg_buy_time_s * row.matches as f64;
             total_buy_time_count += row.matches;
 
@@ -292,6 +615,14 @@ pub(super) async fn build_creator_items(
             (None, None)
         };
 
+        let baseline_winrate = baseline_winrates
+            .as_ref()
+            .and_then(|winrates| winrates.get(&item_id))
+            .copied();
+        let winrate_delta = baseline_winrate
+            .filter(|_| total_matches > 0)
+            .map(|baseline| total_wins as f64 / total_matches as f64 - baseline);
+
         let sell_rate = if total_matches > 0 {
             total_sell_count as f64 / total_matches as f64
         } else {
@@ -307,43 +638,66 @@ pub(super) async fn build_creator_items(
             avg_sell_time_s,
             avg_sell_time_relative,
             sell_rate,
+            wilson_lower_bound: wilson_lower_bound(total_wins, total_matches),
+            smoothed_winrate: smoothed_winrate(total_wins, total_matches, prior_winrate),
+            baseline_winrate,
+            winrate_delta,
             winrates_by_bucket,
+            upgrades_from: upgrade_graph.upgrades_from(item_id),
+            upgrades_into: upgrade_graph.upgrades_into(item_id),
+            upgrade_chains: upgrade_chains.remove(&item_id).unwrap_or_default(),
+            personal: personal_stats.remove(&item_id),
         };
 
-        tiers
-            .entry(tier.to_string())
-            .or_default()
-            .push(item);
+        tiers.entry(tier.to_string()).or_default().push(item);
     }
 
     // Sort items within each tier based on sort_by parameter
     let sort_by = query.sort_by;
     for items in tiers.values_mut() {
-        items.sort_by(|a, b| {
-            match sort_by {
-                SortBy::WinRate => {
-                    let avg_wr_a = calculate_avg_winrate(&a.winrates_by_bucket);
-                    let avg_wr_b = calculate_avg_winrate(&b.winrates_by_bucket);
-                    avg_wr_b.partial_cmp(&avg_wr_a).unwrap_or(std::cmp::Ordering::Equal)
-                }
-                SortBy::Popularity => {
-                    b.matches_total.cmp(&a.matches_total)
-                }
-                SortBy::AvgBuyOrder => {
-                    a.avg_buy_time_s.partial_cmp(&b.avg_buy_time_s).unwrap_or(std::cmp::Ordering::Equal)
-                }
+        items.sort_by(|a, b| match sort_by {
+            SortBy::WinRate => {
+                let avg_wr_a = calculate_avg_winrate(&a.winrates_by_bucket);
+                let avg_wr_b = calculate_avg_winrate(&b.winrates_by_bucket);
+                avg_wr_b
+                    .partial_cmp(&avg_wr_a)
+                    .unwrap_or(std::cmp::Ordering::Equal)
             }
+            SortBy::Popularity => b.matches_total.cmp(&a.matches_total),
+            SortBy::AvgBuyOrder => a
+                .avg_buy_time_s
+                .partial_cmp(&b.avg_buy_time_s)
+                .unwrap_or(std::cmp::Ordering::Equal),
+            SortBy::WilsonLowerBound => b.wilson_lower_bound.total_cmp(&a.wilson_lower_bound),
         });
     }
 
-    Ok(Json(BuildCreatorResponse {
+    Ok(BuildCreatorResponse {
         hero_id: query.hero_id,
         hero_name,
         tiers,
-    }))
+        ability_orders,
+        account_matches,
+    })
 }
 
-fn calculate_avg_winrate(winrates: &HashMap<String, BucketWinrate>) -> f64 {
+/// Winrate of every item across all buckets.
+#[allow(clippy::cast_precision_loss)]
+fn item_winrates(stats: &[ItemStatsRow]) -> HashMap<u32, f64> {
+    let mut totals: HashMap<u32, (u64, u64)> = HashMap::new();
+    for row in stats {
+        let (wins, matches) = totals.entry(row.item_id).or_default();
+        *wins += row.wins;
+        *matches += row.matches;
+    }
+    totals
+        .into_iter()
+        .filter(|(_, (_, matches))| *matches > 0)
+        .map(|(item_id, (wins, matches))| (item_id, wins as f64 / matches as f64))
+        .collect()
+}
+
+pub(super) fn calculate_avg_winrate(winrates: &HashMap<String, BucketWinrate>) -> f64 {
     if winrates.is_empty() {
         return 0.0;
     }
@@ -360,3 +714,171 @@ fn calculate_avg_winrate(winrates: &HashMap<String, BucketWinrate>) -> f64 {
 
     weighted_sum / total_matches as f64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: u32, item_type: &str, class_name: &str) -> AssetsItem {
+        AssetsItem {
+            id,
+            name: class_name.to_owned(),
+            tier: None,
+            cost: None,
+            item_type: Some(item_type.to_owned()),
+            slot: None,
+            class_name: Some(class_name.to_owned()),
+            component_items: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_ability_order() {
+        let hero = AssetsHero {
+            id: 1,
+            name: "Hero".to_owned(),
+            items: HashMap::from([
+                ("signature1".to_owned(), "ability_a".to_owned()),
+                ("signature2".to_owned(), "ability_b".to_owned()),
+                ("weapon_primary".to_owned(), "weapon_a".to_owned()),
+            ]),
+        };
+        let items = [
+            item(10, "ability", "ability_a"),
+            item(11, "ability", "ability_b"),
+            item(12, "ability", "ability_of_another_hero"),
+            item(13, "weapon", "weapon_a"),
+        ];
+        assert!(validate_ability_order(&hero, &items, &[]).is_ok());
+        assert!(validate_ability_order(&hero, &items, &[10, 11, 10]).is_ok());
+        assert!(validate_ability_order(&hero, &items, &[10, 12]).is_err());
+        assert!(validate_ability_order(&hero, &items, &[13]).is_err());
+    }
+
+    #[test]
+    fn test_bucket_expr() {
+        assert_eq!(
+            bucket_expr("buy_time / 60", &[0, 5, 10]),
+            "toUInt32(multiIf(buy_time / 60 < 5, 0, buy_time / 60 < 10, 5, 10))"
+        );
+        assert_eq!(bucket_expr("net_worth_at_buy", &[3000]), "toUInt32(3000)");
+    }
+
+    fn stats_row(item_id: u32, bucket: u32, wins: u64, matches: u64) -> ItemStatsRow {
+        ItemStatsRow {
+            item_id,
+            bucket,
+            wins,
+            losses: matches - wins,
+            matches,
+            avg_buy_time_s: 600.0,
+            avg_sell_time_s: 0.0,
+            avg_sell_time_relative: 0.0,
+            sell_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_build_enemy_filter() {
+        let query = BuildCreatorQuery {
+            hero_id: 7,
+            enemy_hero_ids: Some(vec![2, 15]),
+            ..Default::default()
+        };
+        let filter = build_enemy_filter(&query);
+        assert!(filter.contains("AND (match_id, team) IN ("));
+        assert!(filter.contains("SELECT match_id, if(team = 'Team0', 'Team1', 'Team0')"));
+        assert!(filter.contains("AND hero_id IN (2, 15)"));
+        assert!(filter.contains("HAVING uniqExact(hero_id) = 2"));
+
+        let sql = build_query(&query);
+        assert!(sql.contains("AND hero_id = 7\n            AND (match_id, team) IN ("));
+    }
+
+    #[test]
+    fn test_build_enemy_filter_empty() {
+        let query = BuildCreatorQuery {
+            hero_id: 7,
+            enemy_hero_ids: Some(vec![]),
+            ..Default::default()
+        };
+        assert_eq!(build_enemy_filter(&query), "");
+        assert_eq!(build_enemy_filter(&BuildCreatorQuery::default()), "");
+    }
+
+    #[test]
+    fn test_validate_enemy_hero_ids() {
+        let mut enemy_hero_ids = Some(vec![15, 2, 15]);
+        assert!(validate_enemy_hero_ids(7, &mut enemy_hero_ids).is_ok());
+        assert_eq!(enemy_hero_ids, Some(vec![2, 15]));
+
+        let mut enemy_hero_ids = Some(vec![]);
+        assert!(validate_enemy_hero_ids(7, &mut enemy_hero_ids).is_ok());
+        assert_eq!(enemy_hero_ids, None);
+
+        assert!(validate_enemy_hero_ids(7, &mut Some(vec![2, 7])).is_err());
+        assert!(validate_enemy_hero_ids(7, &mut Some((1..=7).collect())).is_err());
+    }
+
+    #[test]
+    fn test_item_winrates() {
+        let winrates = item_winrates(&[
+            stats_row(1, 0, 30, 50),
+            stats_row(1, 5000, 20, 50),
+            stats_row(2, 0, 0, 0),
+        ]);
+        assert_eq!(winrates.len(), 1);
+        assert!((winrates[&1] - 0.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_bucket_key() {
+        let edges = TimingMode::GameTime.default_buckets().unwrap();
+        assert_eq!(
+            (0..edges.len())
+                .map(|i| TimingMode::GameTime.bucket_key(edges, i))
+                .collect::<Vec<_>>(),
+            vec!["0-5", "5-10", "10-20", "20-30", "30+"]
+        );
+        assert_eq!(TimingMode::Networth.bucket_key(&[0, 3000, 8000], 1), "3000");
+        assert_eq!(TimingMode::PurchaseOrder.bucket_key(&[1, 2, 4], 0), "1");
+        assert_eq!(TimingMode::PurchaseOrder.bucket_key(&[1, 2, 4], 1), "2-4");
+    }
+
+    #[test]
+    fn test_build_query_custom_buckets() {
+        let query = BuildCreatorQuery {
+            hero_id: 7,
+            timing_mode: TimingMode::Networth,
+            buckets: Some(vec![0, 3000, 8000, 15000]),
+            ..Default::default()
+        };
+        let sql = build_query(&query);
+        assert!(sql.contains(
+            "toUInt32(multiIf(net_worth_at_buy < 3000, 0, net_worth_at_buy < 8000, 3000, \
+             net_worth_at_buy < 15000, 8000, 15000)) AS bucket"
+        ));
+    }
+
+    #[test]
+    fn test_build_query_default_networth_buckets() {
+        let query = BuildCreatorQuery {
+            hero_id: 7,
+            ..Default::default()
+        };
+        let sql = build_query(&query);
+        assert!(sql.contains("toUInt32(floor(net_worth_at_buy / 5000) * 5000) AS bucket"));
+    }
+
+    #[test]
+    fn test_build_query_purchase_order() {
+        let query = BuildCreatorQuery {
+            hero_id: 7,
+            timing_mode: TimingMode::PurchaseOrder,
+            ..Default::default()
+        };
+        let sql = build_query(&query);
+        assert!(sql.contains("AS purchase_position"));
+        assert!(sql.contains("purchase_position < 2, 1"));
+    }
+}
@@ -1,5 +1,18 @@
+mod abilities;
+mod compare;
+pub(super) mod confidence;
+mod drafts;
+mod evaluate;
+mod export;
 mod handlers;
+mod next_items;
+mod patch;
+mod personal;
+mod recommend;
+mod sell_timing;
 pub(super) mod structs;
+mod substitutes;
+mod upgrades;
 
 use core::time::Duration;
 
@@ -18,15 +31,26 @@ Helps players quickly create optimal builds based on winrate data.
 struct ApiDoc;
 
 pub(super) fn router() -> OpenApiRouter<AppState> {
-    OpenApiRouter::with_openapi(ApiDoc::openapi()).merge(
-        OpenApiRouter::new()
-            .routes(routes!(items))
-            .layer(
-                CacheControlMiddleware::new(Duration::from_secs(60 * 60))
-                    .with_stale_while_revalidate(Duration::from_secs(12 * 60 * 60))
-                    .with_stale_if_error(Duration::from_secs(24 * 60 * 60)),
-            ),
-    )
+    OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .routes(routes!(export))
+        .routes(routes!(evaluate))
+        .routes(routes!(create_draft))
+        .routes(routes!(get_draft, update_draft, delete_draft))
+        .merge(
+            OpenApiRouter::new()
+                .routes(routes!(items))
+                .routes(routes!(recommend))
+                .routes(routes!(next_items))
+                .routes(routes!(substitutes))
+                .routes(routes!(sell_timing))
+                .routes(routes!(compare))
+                .routes(routes!(patch_changes))
+                .layer(
+                    CacheControlMiddleware::new(Duration::from_secs(60 * 60))
+                        .with_stale_while_revalidate(Duration::from_secs(12 * 60 * 60))
+                        .with_stale_if_error(Duration::from_secs(24 * 60 * 60)),
+                ),
+        )
 }
 
 #[utoipa::path(
@@ -41,16 +65,31 @@ pub(super) fn router() -> OpenApiRouter<AppState> {
     tags = ["Build Creator"],
     summary = "Build Creator Items",
     description = "
-Retrieves item statistics for a hero, grouped by tier and with winrates by networth bracket.
+Retrieves item statistics for a hero, grouped by tier and with winrates by networth bracket or game time.
 
 Each item includes:
 - Name and metadata
 - Total matches
 - Average buy time
-- Winrates at different networth brackets (5k, 10k, 15k, 20k+)
+- Winrates by bucket, bucketed by net worth, game time, relative game time or purchase order (`timing_mode`), with custom bucket edges (`buckets`)
+- Wilson score lower bounds and smoothed winrates, which are robust against small samples
+- The items it is upgraded from and upgrades into
+- Winrates of buying the item and later upgrading it, per upgrade
 
 Items within each tier are sorted by weighted average winrate (descending).
 
+With `patch`, only matches of a patch are considered (`latest`, `previous` or the patch live at a date), instead of a fixed time range.
+This avoids mixing stats from before and after a balance change.
+
+With `enemy_hero_ids`, only matches where the hero faced all of the given heroes are considered.
+Every item then also includes its winrate against any enemies and the difference to it.
+
+With `account_id` or `account_ids`, every item also includes the item usage of the accounts next to the one of successful players (the winners of all matches).
+Items the accounts buy much later or much less often than successful players are flagged. Protected accounts are ignored.
+These responses are only cached privately, never by shared caches.
+
+The response also includes the best performing ability orders of the hero, with the average time every ability point was spent at.
+
 Results are cached for **1 hour** based on the unique combination of query parameters provided.
 
 ### Rate Limits:
@@ -67,3 +106,448 @@ pub(crate) async fn items(
 ) -> crate::error::APIResult<impl axum::response::IntoResponse> {
     handlers::build_creator_items(query, state).await
 }
+
+#[utoipa::path(
+    get,
+    path = "/recommend",
+    params(handlers::BuildCreatorQuery),
+    responses(
+        (status = OK, description = "Recommended Build", body = structs::BuildCreatorRecommendation),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to generate recommended build")
+    ),
+    tags = ["Build Creator"],
+    summary = "Recommended Build",
+    description = "
+Generates a complete 12-slot build for a hero from the same item statistics as the items endpoint.
+
+Items are picked by weighted average winrate while keeping the build balanced:
+- At most 5 items per slot (weapon, vitality, spirit)
+- At most 3 items per tier
+- Only items with at least `min_matches` matches are considered, 1000 by default
+
+If the limits leave the build incomplete, the remaining slots are filled with the best remaining items.
+The build is ordered by average buy time and every item includes a reason why it was picked.
+
+Results are cached for **1 hour** based on the unique combination of query parameters provided.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn recommend(
+    query: axum_extra::extract::Query<handlers::BuildCreatorQuery>,
+    state: axum::extract::State<AppState>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    recommend::build_creator_recommend(query, state).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/next-items",
+    params(handlers::BuildCreatorQuery, next_items::BuildCreatorNextItemsQuery),
+    responses(
+        (status = OK, description = "Next Items", body = structs::BuildCreatorNextItems),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to fetch next items")
+    ),
+    tags = ["Build Creator"],
+    summary = "Next Items",
+    description = "
+Retrieves the items bought next after a set of items, to guide building step by step.
+
+Only players that bought all items of `item_ids` are considered.
+For each of them, the first upgrade bought after the last item of the set is the *next item*.
+Leave `item_ids` empty to get the first item bought.
+
+Each item includes:
+- Winrate and Wilson score lower bound when it is bought next
+- Pick rate, the share of players completing the set that bought it next
+- Average buy time
+
+Results are cached for **1 hour** based on the unique combination of query parameters provided.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn next_items(
+    query: axum_extra::extract::Query<next_items::BuildCreatorNextItemsQuery>,
+    state: axum::extract::State<AppState>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    next_items::build_creator_next_items(query, state).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/substitutes",
+    params(handlers::BuildCreatorQuery, substitutes::BuildCreatorSubstitutesQuery),
+    responses(
+        (status = OK, description = "Item Substitutes", body = structs::BuildCreatorSubstitutes),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to fetch item substitutes")
+    ),
+    tags = ["Build Creator"],
+    summary = "Item Substitutes",
+    description = "
+Retrieves alternatives to an item of a build, for players that do not want to buy it.
+
+Substitutes are the items of the same tier and slot. For every substitute the response includes:
+- Winrate when bought without the item, and the difference to the winrate of the item
+- Substitution rate, the share of players skipping the item that bought the substitute instead
+- Cost difference to the item
+
+Substitutes are ranked by `rank_by` and limited to `limit` items.
+
+Results are cached for **1 hour** based on the unique combination of query parameters provided.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn substitutes(
+    query: axum_extra::extract::Query<substitutes::BuildCreatorSubstitutesQuery>,
+    state: axum::extract::State<AppState>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    substitutes::build_creator_substitutes(query, state).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/sell-timing",
+    params(handlers::BuildCreatorQuery, sell_timing::BuildCreatorSellTimingQuery),
+    responses(
+        (status = OK, description = "Sell Timing", body = structs::BuildCreatorSellTiming),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to fetch sell timing")
+    ),
+    tags = ["Build Creator"],
+    summary = "Sell Timing",
+    description = "
+Retrieves when selling an item pays off for a hero.
+
+The response includes:
+- Winrate of the players that sold the item versus those that held it until the end of the match
+- Winrate by sell time, bucketed in minutes (`buckets`, **Default:** 0,5,10,20,30)
+- The most common items bought right after the sale, overall and per sell time bucket
+
+Results are cached for **1 hour** based on the unique combination of query parameters provided.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn sell_timing(
+    query: axum_extra::extract::Query<sell_timing::BuildCreatorSellTimingQuery>,
+    state: axum::extract::State<AppState>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    sell_timing::build_creator_sell_timing(query, state).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/compare",
+    params(handlers::BuildCreatorQuery, compare::BuildCreatorCompareQuery),
+    responses(
+        (status = OK, description = "Rank Comparison", body = structs::BuildCreatorComparison),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to compare badge ranges")
+    ),
+    tags = ["Build Creator"],
+    summary = "Rank Comparison",
+    description = "
+Compares the item stats of a hero between two badge ranges, e.g. the rank of the user and the top ranks.
+
+The base range is set with `min_average_badge` and `max_average_badge`, the range to compare against with `compare_min_average_badge` and `compare_max_average_badge`.
+For every item the response includes its pick rate, winrate and average buy time in both ranges and the differences, the compared range minus the base range.
+
+Items are sorted by the absolute pick rate difference, so the items bought most differently come first.
+
+Results are cached for **1 hour** based on the unique combination of query parameters provided.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn compare(
+    query: axum_extra::extract::Query<compare::BuildCreatorCompareQuery>,
+    state: axum::extract::State<AppState>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    compare::build_creator_compare(query, state).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/patch-changes",
+    params(handlers::BuildCreatorQuery),
+    responses(
+        (status = OK, description = "Patch Changes", body = structs::BuildCreatorPatchChanges),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to fetch patch changes")
+    ),
+    tags = ["Build Creator"],
+    summary = "Patch Changes",
+    description = "
+Compares the item stats of a hero between the two most recent patches.
+
+For every item the response includes its pick rate, winrate and average buy time on both patches and the differences, the latest patch minus the previous one.
+Items are sorted by the absolute winrate change, so the items affected most by the patch come first.
+
+Patch windows are based on the big patch days and the changelog posts published since the last of them, see the patches endpoints. Posts less than a week after the previous patch day are hotfixes and don't start a new patch.
+The time filters `min_unix_timestamp`, `max_unix_timestamp` and `patch` are replaced by the patch windows.
+
+Results are cached for **1 hour** based on the unique combination of query parameters provided.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn patch_changes(
+    query: axum_extra::extract::Query<handlers::BuildCreatorQuery>,
+    state: axum::extract::State<AppState>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    patch::build_creator_patch_changes(query, state).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/export",
+    request_body = structs::BuildExportRequest,
+    responses(
+        (status = OK, description = "Exported Build", body = crate::routes::v1::builds::structs::BuildHero),
+        (status = BAD_REQUEST, description = "Provided build is invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to export build")
+    ),
+    tags = ["Build Creator"],
+    summary = "Export Build",
+    description = "
+Converts a list of item IDs and an ability order into the in-game hero build format.
+
+The returned document has the same structure as the `hero_build` of the builds search endpoint:
+- Items are grouped into the mod categories *Early Game* (tier 1-2), *Mid Game* (tier 3) and *Late Game* (tier 4), keeping their order
+- Annotations are attached to the matching items, annotating an item that is not in the build is an error
+- The ability order is converted into `currency_changes`, where the first occurrence of an ability unlocks it and every further occurrence upgrades it. It may only contain abilities of the hero.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn export(
+    state: axum::extract::State<AppState>,
+    request: axum::Json<structs::BuildExportRequest>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    export::build_creator_export(state, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/evaluate",
+    request_body = structs::BuildEvaluationRequest,
+    responses(
+        (status = OK, description = "Build Evaluation", body = structs::BuildEvaluation),
+        (status = BAD_REQUEST, description = "Provided build is invalid."),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to evaluate build")
+    ),
+    tags = ["Build Creator"],
+    summary = "Evaluate Build",
+    description = "
+Grades a candidate build based on the matches whose purchases are similar to it.
+
+A match is similar if the hero bought at least `min_matching_items` of the build items, by default 75% of them.
+The response includes:
+- The expected winrate of the similar matches and a score from 0 to 100 (the Wilson score lower bound in percent)
+- The marginal contribution of every item, the winrate of the similar matches with the item minus without it
+- The winrate of the similar matches that started with the given ability order, which may only contain abilities of the hero
+- Warnings such as a missing tier 4 item, unbalanced slots or too few similar matches
+
+Results are cached for **1 hour** based on the build and filters provided.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn evaluate(
+    state: axum::extract::State<AppState>,
+    request: axum::Json<structs::BuildEvaluationRequest>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    evaluate::build_creator_evaluate(state, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/drafts",
+    request_body = structs::BuildDraftRequest,
+    responses(
+        (status = CREATED, description = "Created Draft", body = structs::CreatedBuildDraft),
+        (status = BAD_REQUEST, description = "Provided draft is invalid."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to create draft")
+    ),
+    tags = ["Build Creator"],
+    summary = "Create Draft",
+    description = "
+Saves a build draft, so it survives a reload and can be shared by its `share_code`.
+
+A draft consists of the hero, the ordered item slots, notes and the build creator filters it was made with.
+
+If the request carries an API key, the draft is owned by it and can be modified with the same key.
+Otherwise the response includes an `edit_token`, which has to be sent in the `X-Edit-Token` header to modify the draft.
+The edit token is only returned once.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/h |
+| Key | 1000req/h |
+| Global | - |
+    "
+)]
+pub(crate) async fn create_draft(
+    rate_limit_key: crate::services::rate_limiter::extractor::RateLimitKey,
+    state: axum::extract::State<AppState>,
+    request: axum::Json<structs::BuildDraftRequest>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    drafts::create_draft(rate_limit_key, state, request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/drafts/{share_code}",
+    params(("share_code" = String, Path, description = "Share code of the draft")),
+    responses(
+        (status = OK, description = "Draft", body = structs::BuildDraft),
+        (status = BAD_REQUEST, description = "Provided share code is invalid."),
+        (status = NOT_FOUND, description = "Draft not found"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to fetch draft")
+    ),
+    tags = ["Build Creator"],
+    summary = "Get Draft",
+    description = "
+Retrieves a build draft by its share code.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(crate) async fn get_draft(
+    share_code: axum::extract::Path<String>,
+    state: axum::extract::State<AppState>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    drafts::get_draft(share_code, state).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/drafts/{share_code}",
+    params(
+        ("share_code" = String, Path, description = "Share code of the draft"),
+        ("X-Edit-Token" = Option<String>, Header, description = "Edit token of drafts created without an API key"),
+    ),
+    request_body = structs::BuildDraftRequest,
+    responses(
+        (status = OK, description = "Updated Draft", body = structs::BuildDraft),
+        (status = BAD_REQUEST, description = "Provided draft is invalid."),
+        (status = FORBIDDEN, description = "Not allowed to modify the draft"),
+        (status = NOT_FOUND, description = "Draft not found"),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to update draft")
+    ),
+    tags = ["Build Creator"],
+    summary = "Update Draft",
+    description = "
+Replaces a build draft, keeping its share code.
+
+Requires the API key that created the draft, or its edit token in the `X-Edit-Token` header.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/h |
+| Key | 1000req/h |
+| Global | - |
+    "
+)]
+pub(crate) async fn update_draft(
+    rate_limit_key: crate::services::rate_limiter::extractor::RateLimitKey,
+    headers: axum::http::HeaderMap,
+    share_code: axum::extract::Path<String>,
+    state: axum::extract::State<AppState>,
+    request: axum::Json<structs::BuildDraftRequest>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    drafts::update_draft(rate_limit_key, headers, share_code, state, request).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/drafts/{share_code}",
+    params(
+        ("share_code" = String, Path, description = "Share code of the draft"),
+        ("X-Edit-Token" = Option<String>, Header, description = "Edit token of drafts created without an API key"),
+    ),
+    responses(
+        (status = NO_CONTENT, description = "Draft deleted"),
+        (status = BAD_REQUEST, description = "Provided share code is invalid."),
+        (status = FORBIDDEN, description = "Not allowed to modify the draft"),
+        (status = NOT_FOUND, description = "Draft not found"),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Failed to delete draft")
+    ),
+    tags = ["Build Creator"],
+    summary = "Delete Draft",
+    description = "
+Deletes a build draft.
+
+Requires the API key that created the draft, or its edit token in the `X-Edit-Token` header.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/h |
+| Key | 1000req/h |
+| Global | - |
+    "
+)]
+pub(crate) async fn delete_draft(
+    rate_limit_key: crate::services::rate_limiter::extractor::RateLimitKey,
+    headers: axum::http::HeaderMap,
+    share_code: axum::extract::Path<String>,
+    state: axum::extract::State<AppState>,
+) -> crate::error::APIResult<impl axum::response::IntoResponse> {
+    drafts::delete_draft(rate_limit_key, headers, share_code, state).await
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use itertools::Itertools;
+use serde::Deserialize;
+use tracing::debug;
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::confidence::wilson_lower_bound;
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, build_enemy_filter, build_info_filters, normalize_match_filters,
+};
+use crate::routes::v1::build_creator::structs::{BuildCreatorNextItems, NextItem, SortBy};
+use crate::utils::parse::comma_separated_deserialize_option;
+
+/// Maximum number of items in the build
+const MAX_ITEMS: usize = 64;
+
+#[derive(Debug, Clone, Deserialize, IntoParams, Eq, PartialEq, Hash, Default)]
+pub(crate) struct BuildCreatorNextItemsQuery {
+    #[serde(flatten)]
+    #[param(ignore)]
+    pub filters: BuildCreatorQuery,
+    /// Comma separated list of the item IDs already in the build. Leave empty to get the first
+    /// item to buy. See more: <https://assets.deadlock-api.com/v2/items>
+    #[param(value_type = Option<String>)]
+    #[serde(default, deserialize_with = "comma_separated_deserialize_option")]
+    pub item_ids: Option<Vec<u32>>,
+}
+
+#[derive(Debug, Clone, Row, Deserialize)]
+struct NextItemRow {
+    /// 0 for players that did not buy anything after completing the build
+    item_id: u32,
+    wins: u64,
+    matches: u64,
+    avg_buy_time_s: f64,
+}
+
+fn build_query(query: &BuildCreatorNextItemsQuery) -> String {
+    let info_filters = build_info_filters(&query.filters);
+    let enemy_filter = build_enemy_filter(&query.filters);
+    let hero_id = query.filters.hero_id;
+    let item_ids = query.item_ids.as_deref().unwrap_or_default();
+
+    // Time the last item of the build was bought, and whether a purchase is part of the build
+    let (build_filter, build_time_expr, not_in_build_expr) = if item_ids.is_empty() {
+        (String::new(), "0".to_owned(), "true".to_owned())
+    } else {
+        let item_ids = item_ids.iter().join(", ");
+        (
+            format!(" AND hasAll(items.item_id, [{item_ids}])"),
+            format!("arrayMax(arrayMap(p -> if(has([{item_ids}], p.1), p.2, 0), purchases))"),
+            format!("NOT has([{item_ids}], p.1)"),
+        )
+    };
+
+    format!(
+        "
+WITH
+    t_upgrades AS (SELECT id FROM items WHERE type = 'upgrade'),
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    ),
+    exploded_players AS (
+        SELECT
+            match_id,
+            account_id,
+            won,
+            it.item_id AS item_id,
+            it.game_time_s AS buy_time
+        FROM match_player
+            ARRAY JOIN items AS it
+        WHERE match_id IN (SELECT match_id FROM t_matches)
+            AND it.item_id IN t_upgrades
+            AND it.game_time_s > 0
+            AND hero_id = {hero_id}{build_filter}{enemy_filter}
+    ),
+    t_players AS (
+        SELECT
+            any(won) AS won,
+            arraySort(p -> p.2, groupArray((item_id, buy_time))) AS purchases
+        FROM exploded_players
+        GROUP BY match_id, account_id
+    ),
+    t_next_purchases AS (
+        SELECT
+            won,
+            arrayFirst(p -> p.2 > build_time AND {not_in_build_expr}, purchases) AS next_purchase,
+            {build_time_expr} AS build_time
+        FROM t_players
+    )
+SELECT
+    toUInt32(next_purchase.1) AS item_id,
+    sum(won) AS wins,
+    count() AS matches,
+    avgIf(next_purchase.2, item_id > 0) AS avg_buy_time_s
+FROM t_next_purchases
+GROUP BY item_id
+ORDER BY item_id
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<NextItemRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<NextItemRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+pub(super) async fn build_creator_next_items(
+    Query(mut query): Query<BuildCreatorNextItemsQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    normalize_match_filters(&state, &mut query.filters).await?;
+    // The order of the build does not matter, only which items it contains
+    query.item_ids = query
+        .item_ids
+        .map(|ids| ids.into_iter().sorted_unstable().dedup().collect());
+    if query
+        .item_ids
+        .as_ref()
+        .is_some_and(|ids| ids.len() > MAX_ITEMS)
+    {
+        return Err(APIError::bad_request(format!(
+            "item_ids can contain at most {MAX_ITEMS} items"
+        )));
+    }
+
+    let hero_name = state
+        .assets_client
+        .fetch_hero_name_from_id(query.filters.hero_id)
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch hero: {e}")))?
+        .ok_or_else(|| {
+            APIError::bad_request(format!("Hero {} not found", query.filters.hero_id))
+        })?;
+    let items_map: HashMap<u32, _> = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?
+        .into_iter()
+        .filter(|item| item.item_type.as_deref() == Some("upgrade"))
+        .map(|item| (item.id, item))
+        .collect();
+
+    let query_str = build_query(&query);
+    debug!(?query_str);
+    let rows = run_query(&state.ch_client_ro, &query_str).await?;
+
+    // All players that completed the build, including those that did not buy anything after
+    let matches: u64 = rows.iter().map(|row| row.matches).sum();
+    let min_matches = u64::from(query.filters.min_matches.unwrap_or(50));
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut items = rows
+        .into_iter()
+        .filter(|row| row.item_id > 0 && row.matches >= min_matches)
+        .filter_map(|row| {
+            let item_meta = items_map.get(&row.item_id)?;
+            Some(NextItem {
+                item_id: row.item_id,
+                name: item_meta.name.clone(),
+                tier: item_meta.tier.unwrap_or_default(),
+                slot: item_meta.slot.clone(),
+                matches: row.matches,
+                winrate: row.wins as f64 / row.matches as f64,
+                wilson_lower_bound: wilson_lower_bound(row.wins, row.matches),
+                pick_rate: row.matches as f64 / matches as f64,
+                avg_buy_time_s: row.avg_buy_time_s,
+            })
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| match query.filters.sort_by {
+        SortBy::WinRate => b.winrate.total_cmp(&a.winrate),
+        SortBy::Popularity => b.matches.cmp(&a.matches),
+        SortBy::AvgBuyOrder => a.avg_buy_time_s.total_cmp(&b.avg_buy_time_s),
+        SortBy::WilsonLowerBound => b.wilson_lower_bound.total_cmp(&a.wilson_lower_bound),
+    });
+
+    Ok(Json(BuildCreatorNextItems {
+        hero_id: query.filters.hero_id,
+        hero_name,
+        item_ids: query.item_ids.unwrap_or_default(),
+        matches,
+        items,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_query_with_build() {
+        let query = BuildCreatorNextItemsQuery {
+            filters: BuildCreatorQuery {
+                hero_id: 7,
+                min_average_badge: Some(50),
+                enemy_hero_ids: Some(vec![2, 3]),
+                ..Default::default()
+            },
+            item_ids: Some(vec![1, 2]),
+        };
+        let sql = build_query(&query);
+        assert!(sql.contains("hero_id = 7 AND hasAll(items.item_id, [1, 2])"));
+        assert!(sql.contains("NOT has([1, 2], p.1)"));
+        assert!(sql.contains("average_badge_team0 >= 50 AND average_badge_team1 >= 50"));
+        assert!(sql.contains("AND hero_id IN (2, 3)"));
+    }
+
+    #[test]
+    fn test_build_query_empty_build() {
+        let query = BuildCreatorNextItemsQuery {
+            filters: BuildCreatorQuery {
+                hero_id: 7,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sql = build_query(&query);
+        assert!(!sql.contains("hasAll"));
+        assert!(sql.contains("0 AS build_time"));
+    }
+}
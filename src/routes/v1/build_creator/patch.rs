@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use chrono::{DateTime, NaiveDate};
+use futures::try_join;
+use itertools::Itertools;
+use tracing::{debug, warn};
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::compare::{
+    build_query, compare_items, range_stats, run_query,
+};
+use crate::routes::v1::build_creator::handlers::{BuildCreatorQuery, validate_enemy_hero_ids};
+use crate::routes::v1::build_creator::structs::{BuildCreatorPatchChanges, PatchWindow};
+use crate::routes::v1::patches::big_patch_days::BIG_PATCH_DAYS;
+use crate::services::steam::client::SteamClient;
+
+/// Minimum time between two patch days, changelog posts closer to the previous patch day are
+/// hotfixes and do not start a new patch
+const MIN_PATCH_GAP: i64 = 7 * 24 * 60 * 60;
+
+/// Unix timestamps of the patch days, latest first.
+///
+/// The manually maintained big patch days, extended by the changelog posts published after the
+/// last of them, so the latest patch can not fall behind the changelog when the list is not
+/// updated. A post only starts a new patch if it is at least [`MIN_PATCH_GAP`] after the
+/// previous patch day.
+fn patch_days(changelog_posts: &[i64]) -> Vec<i64> {
+    let mut patch_days = BIG_PATCH_DAYS
+        .iter()
+        .filter_map(|day| DateTime::parse_from_rfc3339(day).ok())
+        .map(|day| day.timestamp())
+        .collect::<Vec<_>>();
+    let mut last_patch_day = patch_days.iter().max().copied().unwrap_or_default();
+    for &post in changelog_posts.iter().sorted_unstable() {
+        if post >= last_patch_day + MIN_PATCH_GAP {
+            patch_days.push(post);
+            last_patch_day = post;
+        }
+    }
+    patch_days.sort_unstable_by(|a, b| b.cmp(a));
+    patch_days.dedup();
+    patch_days
+}
+
+#[cached(
+    ty = "TimedCache<u8, Vec<i64>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60 * 60)) }",
+    result = true,
+    convert = "{ 0 }",
+    sync_writes = "default"
+)]
+async fn fetch_patch_days(steam_client: &SteamClient) -> APIResult<Vec<i64>> {
+    let changelog_posts = steam_client
+        .fetch_patch_notes()
+        .await?
+        .iter()
+        .map(|patch| patch.pub_date.timestamp())
+        .collect::<Vec<_>>();
+    Ok(patch_days(&changelog_posts))
+}
+
+/// Resolves a patch to the window of match start times it was live.
+///
+/// `patch` is `latest`, `previous` or a date (`YYYY-MM-DD`), selecting the patch that was live
+/// at the end of that day. The window of the latest patch is open-ended.
+fn resolve_patch_window(patch: &str, patch_days: &[i64]) -> APIResult<PatchWindow> {
+    let index = match patch {
+        "latest" => 0,
+        "previous" => 1,
+        date => {
+            let end_of_day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(23, 59, 59))
+                .map(|d| d.and_utc().timestamp())
+                .ok_or_else(|| {
+                    APIError::bad_request(format!(
+                        "Invalid patch {date}, expected latest, previous or a date (YYYY-MM-DD)"
+                    ))
+                })?;
+            patch_days
+                .iter()
+                .position(|&day| day <= end_of_day)
+                .ok_or_else(|| APIError::bad_request(format!("No patch found before {date}")))?
+        }
+    };
+    let start = *patch_days
+        .get(index)
+        .ok_or_else(|| APIError::bad_request(format!("Patch {patch} not found")))?;
+    let end = index
+        .checked_sub(1)
+        .and_then(|i| patch_days.get(i))
+        .map(|next| next - 1);
+    Ok(PatchWindow {
+        min_unix_timestamp: start,
+        max_unix_timestamp: end,
+        matches: 0,
+    })
+}
+
+/// Resolves a patch to the window of match start times it was live, see
+/// [`resolve_patch_window`]. Falls back to the big patch days if the changelog is unavailable.
+pub(super) async fn patch_window(state: &AppState, patch: &str) -> APIResult<PatchWindow> {
+    let patch_days = fetch_patch_days(&state.steam_client)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to fetch the changelog, using the big patch days: {e}");
+            patch_days(&[])
+        });
+    resolve_patch_window(patch, &patch_days)
+}
+
+/// Build creator query of the matches played on a patch, replacing the time filters of the query.
+fn patch_query(query: &BuildCreatorQuery, window: &PatchWindow) -> BuildCreatorQuery {
+    BuildCreatorQuery {
+        min_unix_timestamp: Some(window.min_unix_timestamp),
+        max_unix_timestamp: window.max_unix_timestamp,
+        patch: None,
+        ..query.clone()
+    }
+}
+
+pub(super) async fn build_creator_patch_changes(
+    Query(mut query): Query<BuildCreatorQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    validate_enemy_hero_ids(query.hero_id, &mut query.enemy_hero_ids)?;
+    let mut previous_patch = patch_window(&state, "previous").await?;
+    let mut latest_patch = patch_window(&state, "latest").await?;
+
+    let hero_name = state
+        .assets_client
+        .fetch_hero_name_from_id(query.hero_id)
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch hero: {e}")))?
+        .ok_or_else(|| APIError::bad_request(format!("Hero {} not found", query.hero_id)))?;
+    let items_map: HashMap<u32, _> = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?
+        .into_iter()
+        .filter(|item| item.item_type.as_deref() == Some("upgrade"))
+        .map(|item| (item.id, item))
+        .collect();
+
+    let previous_query_str = build_query(&patch_query(&query, &previous_patch));
+    let latest_query_str = build_query(&patch_query(&query, &latest_patch));
+    debug!(?previous_query_str, ?latest_query_str);
+    let (previous_rows, latest_rows) = try_join!(
+        run_query(&state.ch_client_ro, &previous_query_str),
+        run_query(&state.ch_client_ro, &latest_query_str),
+    )?;
+    let (previous_stats, previous_matches) = range_stats(&previous_rows);
+    let (latest_stats, latest_matches) = range_stats(&latest_rows);
+    previous_patch.matches = previous_matches;
+    latest_patch.matches = latest_matches;
+
+    let min_matches = u64::from(query.min_matches.unwrap_or(50));
+    let mut items = compare_items(
+        items_map.values(),
+        &previous_stats,
+        &latest_stats,
+        min_matches,
+    );
+    // Items bought on only one of the patches have no winrate change and come last
+    items.sort_by(|a, b| {
+        let change = |d: Option<f64>| d.map_or(-1.0, f64::abs);
+        change(b.winrate_delta)
+            .total_cmp(&change(a.winrate_delta))
+            .then_with(|| a.item_id.cmp(&b.item_id))
+    });
+
+    Ok(Json(BuildCreatorPatchChanges {
+        hero_id: query.hero_id,
+        hero_name,
+        previous_patch,
+        latest_patch,
+        items,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH_DAYS: [i64; 3] = [1_755_549_832, 1_753_827_772, 1_751_659_423];
+
+    #[test]
+    fn test_patch_days() {
+        let days = patch_days(&[]);
+        assert_eq!(days.len(), BIG_PATCH_DAYS.len());
+        assert!(days.is_sorted_by(|a, b| a > b));
+
+        // Only changelog posts a patch gap after the last big patch day are added
+        let latest = days[0];
+        let next = latest + MIN_PATCH_GAP + 100;
+        let extended = patch_days(&[next, latest, latest - MIN_PATCH_GAP - 100]);
+        assert_eq!(extended.len(), BIG_PATCH_DAYS.len() + 1);
+        assert_eq!(extended[0], next);
+        assert_eq!(extended[1], latest);
+    }
+
+    #[test]
+    fn test_patch_days_hotfix() {
+        let days = patch_days(&[]);
+        let latest = days[0];
+
+        // Hotfixes shortly after a patch day don't start a new patch
+        let hotfix = latest + 2 * 24 * 60 * 60;
+        let with_hotfix = patch_days(&[hotfix]);
+        assert_eq!(with_hotfix, days);
+        let window = resolve_patch_window("latest", &with_hotfix).unwrap();
+        assert_eq!(window.min_unix_timestamp, latest);
+
+        // Nor do hotfixes shortly after a patch from the changelog
+        let next = latest + MIN_PATCH_GAP;
+        let extended = patch_days(&[hotfix, next, next + 100, next + 2 * 24 * 60 * 60]);
+        assert_eq!(extended.len(), BIG_PATCH_DAYS.len() + 1);
+        assert_eq!(extended[0], next);
+        assert_eq!(extended[1], latest);
+    }
+
+    #[test]
+    fn test_resolve_patch_window() {
+        let latest = resolve_patch_window("latest", &PATCH_DAYS).unwrap();
+        assert_eq!(latest.min_unix_timestamp, PATCH_DAYS[0]);
+        assert_eq!(latest.max_unix_timestamp, None);
+
+        let previous = resolve_patch_window("previous", &PATCH_DAYS).unwrap();
+        assert_eq!(previous.min_unix_timestamp, PATCH_DAYS[1]);
+        assert_eq!(previous.max_unix_timestamp, Some(PATCH_DAYS[0] - 1));
+
+        // The patch of 2025-07-29 was released in the evening, so it is live at the end of the day
+        let by_date = resolve_patch_window("2025-07-29", &PATCH_DAYS).unwrap();
+        assert_eq!(by_date.min_unix_timestamp, PATCH_DAYS[1]);
+        let by_date = resolve_patch_window("2025-07-28", &PATCH_DAYS).unwrap();
+        assert_eq!(by_date.min_unix_timestamp, PATCH_DAYS[2]);
+
+        assert!(resolve_patch_window("2025-01-01", &PATCH_DAYS).is_err());
+        assert!(resolve_patch_window("yesterday", &PATCH_DAYS).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use itertools::Itertools;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::context::AppState;
+use crate::error::APIResult;
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, build_enemy_filter, build_info_filters,
+};
+use crate::routes::v1::build_creator::structs::{PersonalItemFlag, PersonalItemStats};
+
+/// Seconds the accounts have to buy an item later than successful players to be flagged
+const BUYS_LATER_THRESHOLD_S: f64 = 120.0;
+/// Share of the successful players' pick rate below which an item is flagged as bought less
+const BUYS_LESS_RATIO: f64 = 0.5;
+/// Minimum pick rate of successful players for an item to be flagged as bought less
+const MIN_SUCCESSFUL_PICK_RATE: f64 = 0.1;
+
+#[derive(Debug, Clone, Row, Deserialize)]
+struct PersonalItemRow {
+    item_id: u32,
+    player_wins: u64,
+    player_matches: u64,
+    player_avg_buy_time_s: f64,
+    winner_matches: u64,
+    winner_avg_buy_time_s: f64,
+    /// Number of matches of the accounts on the hero, the same for every row
+    player_hero_matches: u64,
+    /// Number of won matches on the hero, the same for every row
+    winner_hero_matches: u64,
+}
+
+fn build_query(query: &BuildCreatorQuery, account_ids: &[u32]) -> String {
+    let info_filters = build_info_filters(query);
+    let enemy_filter = build_enemy_filter(query);
+    let hero_id = query.hero_id;
+    let account_ids = account_ids.iter().join(", ");
+    format!(
+        "
+WITH
+    t_upgrades AS (SELECT id FROM items WHERE type = 'upgrade'),
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    ),
+    t_players AS (
+        SELECT account_id IN ({account_ids}) AS is_player, won, items
+        FROM match_player
+        WHERE match_id IN (SELECT match_id FROM t_matches)
+            AND hero_id = {hero_id}{enemy_filter}
+    )
+SELECT
+    it.item_id AS item_id,
+    sumIf(won, is_player) AS player_wins,
+    countIf(is_player) AS player_matches,
+    avgIf(it.game_time_s, is_player) AS player_avg_buy_time_s,
+    countIf(won) AS winner_matches,
+    avgIf(it.game_time_s, won) AS winner_avg_buy_time_s,
+    (SELECT countIf(is_player) FROM t_players) AS player_hero_matches,
+    (SELECT countIf(won) FROM t_players) AS winner_hero_matches
+FROM t_players
+    ARRAY JOIN items AS it
+WHERE it.item_id IN t_upgrades AND it.game_time_s > 0
+GROUP BY item_id
+ORDER BY item_id
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<PersonalItemRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<PersonalItemRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn personal_item_stats(row: &PersonalItemRow) -> PersonalItemStats {
+    let pick_rate = row.player_matches as f64 / row.player_hero_matches.max(1) as f64;
+    let successful_pick_rate = row.winner_matches as f64 / row.winner_hero_matches.max(1) as f64;
+    let avg_buy_time_s = (row.player_matches > 0).then_some(row.player_avg_buy_time_s);
+    let successful_avg_buy_time_s = (row.winner_matches > 0).then_some(row.winner_avg_buy_time_s);
+
+    let mut flags = Vec::new();
+    if let Some((player, successful)) = avg_buy_time_s.zip(successful_avg_buy_time_s)
+        && player - successful >= BUYS_LATER_THRESHOLD_S
+    {
+        flags.push(PersonalItemFlag::BuysLater);
+    }
+    if row.player_hero_matches > 0
+        && successful_pick_rate >= MIN_SUCCESSFUL_PICK_RATE
+        && pick_rate < successful_pick_rate * BUYS_LESS_RATIO
+    {
+        flags.push(PersonalItemFlag::BuysLess);
+    }
+
+    PersonalItemStats {
+        matches: row.player_matches,
+        winrate: (row.player_matches > 0)
+            .then(|| row.player_wins as f64 / row.player_matches as f64),
+        pick_rate,
+        avg_buy_time_s,
+        successful_pick_rate,
+        successful_avg_buy_time_s,
+        flags,
+    }
+}
+
+/// Fetches the item usage of the accounts of the query next to the one of successful players.
+///
+/// Returns the stats keyed by item and the number of matches of the accounts on the hero.
+pub(super) async fn fetch_personal_stats(
+    state: &AppState,
+    query: &BuildCreatorQuery,
+    account_ids: &[u32],
+) -> APIResult<(HashMap<u32, PersonalItemStats>, u64)> {
+    let query_str = build_query(query, account_ids);
+    debug!(?query_str);
+    let rows = run_query(&state.ch_client_ro, &query_str).await?;
+    let account_matches = rows.first().map_or(0, |row| row.player_hero_matches);
+    let stats = rows
+        .iter()
+        .map(|row| (row.item_id, personal_item_stats(row)))
+        .collect();
+    Ok((stats, account_matches))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(
+        player_matches: u64,
+        player_avg_buy_time_s: f64,
+        winner_matches: u64,
+    ) -> PersonalItemRow {
+        PersonalItemRow {
+            item_id: 1,
+            player_wins: player_matches / 2,
+            player_matches,
+            player_avg_buy_time_s,
+            winner_matches,
+            winner_avg_buy_time_s: 600.0,
+            player_hero_matches: 100,
+            winner_hero_matches: 1000,
+        }
+    }
+
+    #[test]
+    fn test_build_query() {
+        let query = BuildCreatorQuery {
+            hero_id: 7,
+            ..Default::default()
+        };
+        let sql = build_query(&query, &[1, 2]);
+        assert!(sql.contains("account_id IN (1, 2) AS is_player"));
+        assert!(sql.contains("hero_id = 7"));
+    }
+
+    #[test]
+    fn test_personal_item_stats() {
+        let stats = personal_item_stats(&row(50, 620.0, 500));
+        assert!((stats.pick_rate - 0.5).abs() < f64::EPSILON);
+        assert!((stats.successful_pick_rate - 0.5).abs() < f64::EPSILON);
+        assert!(stats.flags.is_empty());
+
+        let stats = personal_item_stats(&row(10, 800.0, 500));
+        assert_eq!(
+            stats.flags,
+            vec![PersonalItemFlag::BuysLater, PersonalItemFlag::BuysLess]
+        );
+
+        let stats = personal_item_stats(&row(0, 0.0, 50));
+        assert!(stats.avg_buy_time_s.is_none());
+        assert!(stats.winrate.is_none());
+        assert!(stats.flags.is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, calculate_avg_winrate, fetch_build_creator_response,
+};
+use crate::routes::v1::build_creator::structs::{
+    BuildCreatorItem, BuildCreatorRecommendation, RecommendedItem,
+};
+use crate::routes::v1::build_creator::upgrades::UpgradeGraph;
+
+/// Number of item slots in a full build
+pub(super) const BUILD_SLOTS: usize = 12;
+/// Maximum number of items of the same shop slot (weapon, vitality, spirit)
+pub(super) const MAX_ITEMS_PER_SLOT: usize = 5;
+/// Maximum number of items of the same tier
+const MAX_ITEMS_PER_TIER: usize = 3;
+/// Minimum number of matches for an item to be recommended, if `min_matches` is not given
+const MIN_RECOMMEND_MATCHES: u64 = 1000;
+
+struct Candidate<'a> {
+    tier: u32,
+    item: &'a BuildCreatorItem,
+    winrate: f64,
+    /// Rank of the item by winrate within its tier (1 = best)
+    tier_rank: usize,
+}
+
+impl Candidate<'_> {
+    fn slot(&self) -> &str {
+        self.item.slot.as_deref().unwrap_or("unknown")
+    }
+
+    fn reason(&self, balanced: bool) -> String {
+        let mut reason = format!(
+            "#{} tier {} {} item by winrate ({:.1}% over {} matches), usually bought around \
+             minute {:.0}",
+            self.tier_rank,
+            self.tier,
+            self.slot(),
+            self.winrate * 100.0,
+            self.item.matches_total,
+            self.item.avg_buy_time_s / 60.0,
+        );
+        if !balanced {
+            reason.push_str("; added to fill the build after slot and tier limits were reached");
+        }
+        reason
+    }
+}
+
+/// Picks up to 12 items with at least `min_matches` matches from the per-tier item stats.
+///
+/// Items are picked greedily by winrate while keeping the build balanced across shop slots and
+/// tiers. Components of picked items are skipped, as they are used up by the upgrade. If the
+/// limits leave the build incomplete, the remaining slots are filled with the best remaining
+/// items. The final build is ordered by average buy time.
+fn recommend_build(
+    tiers: &HashMap<String, Vec<BuildCreatorItem>>,
+    upgrade_graph: &UpgradeGraph,
+    min_matches: u64,
+) -> Vec<RecommendedItem> {
+    let mut candidates = tiers
+        .iter()
+        .filter_map(|(tier, items)| tier.parse::<u32>().ok().map(|tier| (tier, items)))
+        .flat_map(|(tier, items)| {
+            items
+                .iter()
+                .filter(move |item| item.matches_total >= min_matches)
+                .map(move |item| Candidate {
+                    tier,
+                    item,
+                    winrate: calculate_avg_winrate(&item.winrates_by_bucket),
+                    tier_rank: 0,
+                })
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| {
+        b.winrate
+            .total_cmp(&a.winrate)
+            .then_with(|| b.item.matches_total.cmp(&a.item.matches_total))
+            .then_with(|| a.item.item_id.cmp(&b.item.item_id))
+    });
+
+    let mut tier_ranks: HashMap<u32, usize> = HashMap::new();
+    for candidate in &mut candidates {
+        let rank = tier_ranks.entry(candidate.tier).or_default();
+        *rank += 1;
+        candidate.tier_rank = *rank;
+    }
+
+    let mut picked: Vec<(&Candidate, bool)> = Vec::with_capacity(BUILD_SLOTS);
+    let mut picked_ids = HashSet::new();
+    let mut component_ids = HashSet::new();
+    let mut slot_counts: HashMap<&str, usize> = HashMap::new();
+    let mut tier_counts: HashMap<u32, usize> = HashMap::new();
+
+    // First pass: respect slot and tier balance
+    for candidate in &candidates {
+        if picked.len() >= BUILD_SLOTS {
+            break;
+        }
+        if component_ids.contains(&candidate.item.item_id) {
+            continue;
+        }
+        let slot_count = slot_counts.entry(candidate.slot()).or_default();
+        let tier_count = tier_counts.entry(candidate.tier).or_default();
+        if *slot_count >= MAX_ITEMS_PER_SLOT || *tier_count >= MAX_ITEMS_PER_TIER {
+            continue;
+        }
+        *slot_count += 1;
+        *tier_count += 1;
+        picked_ids.insert(candidate.item.item_id);
+        component_ids.extend(upgrade_graph.all_components(candidate.item.item_id));
+        picked.push((candidate, true));
+    }
+
+    // Second pass: fill the remaining slots with the best remaining items
+    for candidate in &candidates {
+        if picked.len() >= BUILD_SLOTS {
+            break;
+        }
+        if component_ids.contains(&candidate.item.item_id) {
+            continue;
+        }
+        if picked_ids.insert(candidate.item.item_id) {
+            component_ids.extend(upgrade_graph.all_components(candidate.item.item_id));
+            picked.push((candidate, false));
+        }
+    }
+
+    picked.sort_by(|(a, _), (b, _)| {
+        a.item
+            .avg_buy_time_s
+            .total_cmp(&b.item.avg_buy_time_s)
+            .then_with(|| a.tier.cmp(&b.tier))
+    });
+
+    picked
+        .into_iter()
+        .zip(0..)
+        .map(|((candidate, balanced), position)| RecommendedItem {
+            position,
+            item_id: candidate.item.item_id,
+            name: candidate.item.name.clone(),
+            tier: candidate.tier,
+            slot: candidate.item.slot.clone(),
+            winrate: candidate.winrate,
+            matches_total: candidate.item.matches_total,
+            avg_buy_time_s: candidate.item.avg_buy_time_s,
+            reason: candidate.reason(balanced),
+        })
+        .collect()
+}
+
+pub(super) async fn build_creator_recommend(
+    Query(query): Query<BuildCreatorQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    let min_matches = query.min_matches.map_or(MIN_RECOMMEND_MATCHES, u64::from);
+    let items = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?;
+    let upgrade_graph = UpgradeGraph::new(
+        items
+            .iter()
+            .filter(|item| item.item_type.as_deref() == Some("upgrade")),
+    );
+    let response = fetch_build_creator_response(&state, query, false).await?;
+    Ok(Json(BuildCreatorRecommendation {
+        items: recommend_build(&response.tiers, &upgrade_graph, min_matches),
+        hero_id: response.hero_id,
+        hero_name: response.hero_name,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::v1::build_creator::structs::BucketWinrate;
+    use crate::services::assets::types::AssetsItem;
+
+    fn assets_item(id: u32, class_name: &str, component_items: &[&str]) -> AssetsItem {
+        AssetsItem {
+            id,
+            name: class_name.to_owned(),
+            tier: None,
+            cost: None,
+            item_type: Some("upgrade".to_owned()),
+            slot: None,
+            class_name: Some(class_name.to_owned()),
+            component_items: Some(component_items.iter().map(|&c| c.to_owned()).collect()),
+        }
+    }
+
+    fn item(item_id: u32, slot: &str, winrate: f64, avg_buy_time_s: f64) -> BuildCreatorItem {
+        BuildCreatorItem {
+            item_id,
+            name: format!("Item {item_id}"),
+            slot: Some(slot.to_owned()),
+            matches_total: 2000,
+            avg_buy_time_s,
+            avg_sell_time_s: None,
+            avg_sell_time_relative: None,
+            sell_rate: 0.0,
+            wilson_lower_bound: winrate,
+            smoothed_winrate: winrate,
+            baseline_winrate: None,
+            winrate_delta: None,
+            winrates_by_bucket: HashMap::from([(
+                "5000".to_owned(),
+                BucketWinrate {
+                    winrate,
+                    matches: 2000,
+                    wilson_lower_bound: winrate,
+                    smoothed_winrate: winrate,
+                },
+            )]),
+            upgrades_from: vec![],
+            upgrades_into: vec![],
+            upgrade_chains: vec![],
+            personal: None,
+        }
+    }
+
+    fn tiers() -> HashMap<String, Vec<BuildCreatorItem>> {
+        let mut tiers = HashMap::new();
+        for tier in 1..=4u32 {
+            let items = (0..6u32)
+                .map(|i| {
+                    let slot = ["weapon", "vitality", "spirit"][(i % 3) as usize];
+                    item(
+                        tier * 100 + i,
+                        slot,
+                        0.5 + f64::from(i) / 100.0,
+                        f64::from(tier * 600 + i),
+                    )
+                })
+                .collect();
+            tiers.insert(tier.to_string(), items);
+        }
+        tiers
+    }
+
+    #[test]
+    fn test_recommend_build_fills_all_slots() {
+        let build = recommend_build(&tiers(), &UpgradeGraph::default(), MIN_RECOMMEND_MATCHES);
+        assert_eq!(build.len(), BUILD_SLOTS);
+        assert!(
+            build
+                .iter()
+                .all(|i| !i.reason.contains("limits were reached"))
+        );
+    }
+
+    #[test]
+    fn test_recommend_build_respects_tier_limit() {
+        let build = recommend_build(&tiers(), &UpgradeGraph::default(), MIN_RECOMMEND_MATCHES);
+        for tier in 1..=4 {
+            assert_eq!(
+                build.iter().filter(|i| i.tier == tier).count(),
+                MAX_ITEMS_PER_TIER
+            );
+        }
+    }
+
+    #[test]
+    fn test_recommend_build_ordered_by_buy_time() {
+        let build = recommend_build(&tiers(), &UpgradeGraph::default(), MIN_RECOMMEND_MATCHES);
+        assert!(
+            build
+                .windows(2)
+                .all(|w| w[0].avg_buy_time_s <= w[1].avg_buy_time_s)
+        );
+        assert!(build.iter().zip(0..).all(|(i, p)| i.position == p));
+    }
+
+    #[test]
+    fn test_recommend_build_skips_low_sample_items() {
+        let mut tiers = tiers();
+        for items in tiers.values_mut() {
+            for item in items {
+                item.matches_total = MIN_RECOMMEND_MATCHES - 1;
+            }
+        }
+        assert!(
+            recommend_build(&tiers, &UpgradeGraph::default(), MIN_RECOMMEND_MATCHES).is_empty()
+        );
+
+        // A lower min_matches of the query lets them in again
+        let build = recommend_build(&tiers, &UpgradeGraph::default(), MIN_RECOMMEND_MATCHES - 1);
+        assert_eq!(build.len(), BUILD_SLOTS);
+    }
+
+    #[test]
+    fn test_recommend_build_skips_components_of_picked_items() {
+        // Item 105 is the best tier 1 item and upgrades into 205, the best tier 2 item
+        let items = [
+            assets_item(105, "upgrade_component", &[]),
+            assets_item(205, "upgrade_upgrade", &["upgrade_component"]),
+        ];
+        let graph = UpgradeGraph::new(&items);
+        let mut tiers = tiers();
+        for item in tiers.get_mut("2").into_iter().flatten() {
+            if item.item_id == 205 {
+                item.winrates_by_bucket
+                    .values_mut()
+                    .for_each(|b| b.winrate = 0.9);
+            }
+        }
+        let with_component = recommend_build(&tiers, &UpgradeGraph::default(), 0);
+        assert!(with_component.iter().any(|i| i.item_id == 105));
+
+        let build = recommend_build(&tiers, &graph, 0);
+        assert_eq!(build.len(), BUILD_SLOTS);
+        assert!(build.iter().any(|i| i.item_id == 205));
+        assert!(build.iter().all(|i| i.item_id != 105));
+    }
+
+    #[test]
+    fn test_recommend_build_relaxes_limits_when_short() {
+        let mut tiers = HashMap::new();
+        tiers.insert(
+            "1".to_owned(),
+            (0..12)
+                .map(|i| item(i, "weapon", 0.5, f64::from(i)))
+                .collect::<Vec<_>>(),
+        );
+        let build = recommend_build(&tiers, &UpgradeGraph::default(), MIN_RECOMMEND_MATCHES);
+        assert_eq!(build.len(), BUILD_SLOTS);
+        assert_eq!(
+            build
+                .iter()
+                .filter(|i| i.reason.contains("limits were reached"))
+                .count(),
+            BUILD_SLOTS - MAX_ITEMS_PER_TIER
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use serde::Deserialize;
+use tracing::debug;
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::confidence::wilson_lower_bound;
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, bucket_expr, build_enemy_filter, build_info_filters,
+    normalize_match_filters, validate_buckets,
+};
+use crate::routes::v1::build_creator::structs::{
+    BuildCreatorSellTiming, GAME_TIME_BUCKETS, SellOutcome, SellReplacement, SellTimeBucket,
+    TimingMode,
+};
+
+/// Number of replacements returned across all sell times
+const MAX_REPLACEMENTS: usize = 5;
+
+#[derive(Debug, Clone, Deserialize, IntoParams, Eq, PartialEq, Hash, Default)]
+pub(crate) struct BuildCreatorSellTimingQuery {
+    #[serde(flatten)]
+    #[param(ignore)]
+    pub filters: BuildCreatorQuery,
+    /// Item ID to analyze the sales of. See more: <https://assets.deadlock-api.com/v2/items>
+    pub item_id: u32,
+}
+
+#[derive(Debug, Clone, Row, Deserialize)]
+struct SellTimingRow {
+    sold: bool,
+    /// Lower edge of the sell time bucket, 0 if the item was held
+    bucket: u32,
+    /// Upgrade bought right after the sale, 0 if none or the item was held
+    replacement_id: u32,
+    wins: u64,
+    matches: u64,
+}
+
+fn build_query(query: &BuildCreatorSellTimingQuery) -> String {
+    let info_filters = build_info_filters(&query.filters);
+    let enemy_filter = build_enemy_filter(&query.filters);
+    let hero_id = query.filters.hero_id;
+    let item_id = query.item_id;
+    let edges = query
+        .filters
+        .buckets
+        .as_deref()
+        .unwrap_or(GAME_TIME_BUCKETS);
+    let bucket_expr = bucket_expr("sold_time / 60", edges);
+    format!(
+        "
+WITH
+    (SELECT groupArray(id) FROM items WHERE type = 'upgrade') AS upgrade_ids_array,
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    ),
+    t_purchases AS (
+        SELECT
+            won,
+            it.sold_time_s AS sold_time,
+            arrayFirst(
+                (id, t) -> has(upgrade_ids_array, id) AND id != {item_id} AND t >= it.sold_time_s,
+                items.item_id,
+                items.game_time_s
+            ) AS next_item_id
+        FROM match_player
+            ARRAY JOIN items AS it
+        WHERE match_id IN (SELECT match_id FROM t_matches)
+            AND it.item_id = {item_id}
+            AND it.game_time_s > 0
+            AND hero_id = {hero_id}{enemy_filter}
+    )
+SELECT
+    toBool(sold_time > 0) AS sold,
+    if(sold, {bucket_expr}, toUInt32(0)) AS bucket,
+    toUInt32(if(sold, next_item_id, 0)) AS replacement_id,
+    sum(won) AS wins,
+    count() AS matches
+FROM t_purchases
+GROUP BY sold, bucket, replacement_id
+ORDER BY sold, bucket, replacement_id
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<SellTimingRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<SellTimingRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn winrate(wins: u64, matches: u64) -> Option<f64> {
+    (matches > 0).then(|| wins as f64 / matches as f64)
+}
+
+/// Sums wins and matches of the rows by replacement, most common replacements first.
+fn replacements<'a>(
+    rows: impl Iterator<Item = &'a SellTimingRow>,
+    item_names: &HashMap<u32, String>,
+) -> Vec<SellReplacement> {
+    let mut totals: HashMap<u32, (u64, u64)> = HashMap::new();
+    for row in rows.filter(|row| row.sold && row.replacement_id > 0) {
+        let (wins, matches) = totals.entry(row.replacement_id).or_default();
+        *wins += row.wins;
+        *matches += row.matches;
+    }
+    let mut replacements = totals
+        .into_iter()
+        .filter_map(|(item_id, (wins, matches))| {
+            Some(SellReplacement {
+                item_id,
+                name: item_names.get(&item_id)?.clone(),
+                matches,
+                winrate: winrate(wins, matches)?,
+            })
+        })
+        .collect::<Vec<_>>();
+    replacements.sort_by(|a, b| {
+        b.matches
+            .cmp(&a.matches)
+            .then_with(|| a.item_id.cmp(&b.item_id))
+    });
+    replacements
+}
+
+fn sell_time_buckets(
+    rows: &[SellTimingRow],
+    edges: &[u32],
+    min_matches: u64,
+    item_names: &HashMap<u32, String>,
+) -> Vec<SellTimeBucket> {
+    edges
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &edge)| {
+            let bucket_rows = rows
+                .iter()
+                .filter(|row| row.sold && row.bucket == edge)
+                .collect::<Vec<_>>();
+            let wins = bucket_rows.iter().map(|row| row.wins).sum();
+            let matches = bucket_rows.iter().map(|row| row.matches).sum();
+            if matches < min_matches.max(1) {
+                return None;
+            }
+            Some(SellTimeBucket {
+                bucket: TimingMode::GameTime.bucket_key(edges, index),
+                matches,
+                winrate: winrate(wins, matches)?,
+                wilson_lower_bound: wilson_lower_bound(wins, matches),
+                most_common_replacement: replacements(bucket_rows.into_iter(), item_names)
+                    .into_iter()
+                    .next(),
+            })
+        })
+        .collect()
+}
+
+pub(super) async fn build_creator_sell_timing(
+    Query(mut query): Query<BuildCreatorSellTimingQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    normalize_match_filters(&state, &mut query.filters).await?;
+    validate_buckets(&mut query.filters.buckets)?;
+
+    let hero_name = state
+        .assets_client
+        .fetch_hero_name_from_id(query.filters.hero_id)
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch hero: {e}")))?
+        .ok_or_else(|| {
+            APIError::bad_request(format!("Hero {} not found", query.filters.hero_id))
+        })?;
+    let item_names: HashMap<u32, String> = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?
+        .into_iter()
+        .filter(|item| item.item_type.as_deref() == Some("upgrade"))
+        .map(|item| (item.id, item.name))
+        .collect();
+    let name = item_names.get(&query.item_id).cloned().ok_or_else(|| {
+        APIError::bad_request(format!("Item {} is not an upgrade", query.item_id))
+    })?;
+
+    let query_str = build_query(&query);
+    debug!(?query_str);
+    let rows = run_query(&state.ch_client_ro, &query_str).await?;
+
+    let (sold_wins, sold_matches) = rows
+        .iter()
+        .filter(|row| row.sold)
+        .fold((0, 0), |(w, m), row| (w + row.wins, m + row.matches));
+    let (held_wins, held_matches) = rows
+        .iter()
+        .filter(|row| !row.sold)
+        .fold((0, 0), |(w, m), row| (w + row.wins, m + row.matches));
+    let matches = sold_matches + held_matches;
+    let edges = query
+        .filters
+        .buckets
+        .as_deref()
+        .unwrap_or(GAME_TIME_BUCKETS);
+    let min_matches = u64::from(query.filters.min_matches.unwrap_or(50));
+
+    let mut replacements = replacements(rows.iter(), &item_names);
+    replacements.truncate(MAX_REPLACEMENTS);
+
+    Ok(Json(BuildCreatorSellTiming {
+        hero_id: query.filters.hero_id,
+        hero_name,
+        item_id: query.item_id,
+        name,
+        matches,
+        sell_rate: winrate(sold_matches, matches).unwrap_or_default(),
+        sold: SellOutcome {
+            matches: sold_matches,
+            winrate: winrate(sold_wins, sold_matches),
+        },
+        held: SellOutcome {
+            matches: held_matches,
+            winrate: winrate(held_wins, held_matches),
+        },
+        buckets: sell_time_buckets(&rows, edges, min_matches, &item_names),
+        replacements,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(bucket: u32, replacement_id: u32, wins: u64, matches: u64) -> SellTimingRow {
+        SellTimingRow {
+            sold: bucket > 0 || replacement_id > 0,
+            bucket,
+            replacement_id,
+            wins,
+            matches,
+        }
+    }
+
+    #[test]
+    fn test_build_query() {
+        let query = BuildCreatorSellTimingQuery {
+            filters: BuildCreatorQuery {
+                hero_id: 7,
+                buckets: Some(vec![0, 15]),
+                ..Default::default()
+            },
+            item_id: 10,
+        };
+        let sql = build_query(&query);
+        assert!(sql.contains("it.item_id = 10"));
+        assert!(sql.contains("hero_id = 7"));
+        assert!(sql.contains("id != 10 AND t >= it.sold_time_s"));
+        assert!(sql.contains("multiIf(sold_time / 60 < 15, 0, 15)"));
+    }
+
+    #[test]
+    fn test_sell_time_buckets() {
+        let names = HashMap::from([(20, "Item 20".to_owned()), (21, "Item 21".to_owned())]);
+        let rows = vec![
+            row(0, 0, 30, 100),
+            row(5, 20, 40, 60),
+            row(5, 21, 10, 30),
+            row(10, 21, 5, 10),
+        ];
+        let buckets = sell_time_buckets(&rows, &[0, 5, 10], 50, &names);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].bucket, "5-10");
+        assert_eq!(buckets[0].matches, 90);
+        assert_eq!(
+            buckets[0]
+                .most_common_replacement
+                .as_ref()
+                .map(|r| r.item_id),
+            Some(20)
+        );
+
+        let replacements = replacements(rows.iter(), &names);
+        assert_eq!(
+            replacements.iter().map(|r| r.item_id).collect::<Vec<_>>(),
+            vec![20, 21]
+        );
+        assert_eq!(replacements[1].matches, 40);
+    }
+}
@@ -1,11 +1,18 @@
 use std::collections::HashMap;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use strum::Display;
 use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::routes::v1::builds::query::BuildLanguage;
+use crate::utils::parse::{default_last_month_timestamp, parse_steam_id_option};
 
 /// Sorting options for items in the build creator
-#[derive(Debug, Clone, Copy, Deserialize, ToSchema, Default, Display, PartialEq, Eq, Hash)]
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, ToSchema, Default, Display, PartialEq, Eq, Hash,
+)]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub(crate) enum SortBy {
@@ -16,28 +23,91 @@ pub(crate) enum SortBy {
     Popularity,
     /// Sort by average buy time (ascending - earliest purchases first)
     AvgBuyOrder,
+    /// Sort by the lower bound of the 95% Wilson score interval of the winrate (descending)
+    WilsonLowerBound,
+}
+
+/// Ranking options for item substitutes
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, ToSchema, Default, Display, PartialEq, Eq, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum SubstituteSortBy {
+    /// Sort by winrate of the substitute minus winrate of the item (descending) - default
+    #[default]
+    WinrateDelta,
+    /// Sort by share of the players skipping the item that bought the substitute (descending)
+    SubstitutionRate,
+    /// Sort by absolute cost difference to the item (ascending)
+    CostDifference,
 }
 
+/// Width of the default net worth buckets
+pub(crate) const NETWORTH_BUCKET_WIDTH: u32 = 5000;
+/// Lower edges of the default game time buckets, in minutes
+pub(crate) const GAME_TIME_BUCKETS: &[u32] = &[0, 5, 10, 20, 30];
+
 /// Timing mode for bucketing win rates
-#[derive(Debug, Clone, Copy, Deserialize, ToSchema, Default, Display, PartialEq, Eq, Hash)]
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, ToSchema, Default, Display, PartialEq, Eq, Hash,
+)]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub(crate) enum TimingMode {
-    /// Bucket by net worth (5000, 10000, 15000, 20000) - default
+    /// Bucket by net worth at the time of purchase (0, 5000, 10000, ...) - default
     #[default]
     Networth,
     /// Bucket by game phase (0-5, 5-10, 10-20, 20-30, 30+ minutes)
     GameTime,
+    /// Bucket by buy time as percentage of the match duration (0-25, 25-50, 50-75, 75+)
+    RelativeGameTime,
+    /// Bucket by the position of the item in the purchase order (1, 2, ..., 12+)
+    PurchaseOrder,
+}
+
+impl TimingMode {
+    /// Lower edges of the buckets used if no custom buckets are given.
+    ///
+    /// Net worth has no default edges, it is bucketed in steps of [`NETWORTH_BUCKET_WIDTH`] without
+    /// an upper limit.
+    pub(crate) fn default_buckets(self) -> Option<&'static [u32]> {
+        match self {
+            Self::Networth => None,
+            Self::GameTime => Some(GAME_TIME_BUCKETS),
+            Self::RelativeGameTime => Some(&[0, 25, 50, 75]),
+            Self::PurchaseOrder => Some(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+        }
+    }
+
+    /// Key of the bucket starting at `edges[index]` in `winrates_by_bucket`.
+    ///
+    /// Net worth buckets are keyed by their lower edge, all other buckets by their range.
+    pub(crate) fn bucket_key(self, edges: &[u32], index: usize) -> String {
+        let lower = edges.get(index).copied().unwrap_or_default();
+        if self == Self::Networth {
+            return lower.to_string();
+        }
+        match edges.get(index + 1) {
+            None => format!("{lower}+"),
+            Some(&upper) if upper - lower == 1 => lower.to_string(),
+            Some(upper) => format!("{lower}-{upper}"),
+        }
+    }
 }
 
-/// Winrate statistics at a specific bucket (networth or game phase)
+/// Winrate statistics at a specific bucket (networth, game time or purchase order)
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub(crate) struct BucketWinrate {
     pub(crate) winrate: f64,
     pub(crate) matches: u64,
+    /// Lower bound of the 95% Wilson score interval of the winrate
+    pub(crate) wilson_lower_bound: f64,
+    /// Winrate smoothed towards the hero's average winrate, small samples are pulled the most
+    pub(crate) smoothed_winrate: f64,
 }
 
-/// Item with winrate statistics across buckets (networth or game phase)
+/// Item with winrate statistics across buckets (networth, game time or purchase order)
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub(crate) struct BuildCreatorItem {
     pub(crate) item_id: u32,
@@ -54,10 +124,71 @@ pub(crate) struct BuildCreatorItem {
     pub(crate) avg_sell_time_relative: Option<f64>,
     /// Percentage of times this item was sold (0.0-1.0)
     pub(crate) sell_rate: f64,
-    /// Winrates keyed by bucket. Keys depend on timing_mode:
-    /// - networth mode: "5000", "10000", "15000", "20000"
-    /// - game_time mode: "0-5", "5-10", "10-20", "20-30", "30+"
+    /// Lower bound of the 95% Wilson score interval of the winrate across all buckets
+    pub(crate) wilson_lower_bound: f64,
+    /// Winrate across all buckets smoothed towards the hero's average winrate
+    pub(crate) smoothed_winrate: f64,
+    /// Winrate of the item against any enemies (only set if `enemy_hero_ids` is given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) baseline_winrate: Option<f64>,
+    /// Winrate against the enemy heroes minus the baseline winrate (only set if `enemy_hero_ids`
+    /// is given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) winrate_delta: Option<f64>,
+    /// Winrates keyed by bucket. Keys depend on timing_mode and buckets:
+    /// - networth mode: the lower edge of the bucket, e.g. "0", "5000", "10000"
+    /// - other modes: the range of the bucket, e.g. "0-5", "5-10", "30+", or a single value for
+    ///   buckets of width 1, e.g. "1", "2"
     pub(crate) winrates_by_bucket: HashMap<String, BucketWinrate>,
+    /// Item IDs this item is upgraded from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) upgrades_from: Vec<u32>,
+    /// Item IDs this item upgrades into
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) upgrades_into: Vec<u32>,
+    /// Winrates of buying this item and upgrading it later, one entry per upgrade
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) upgrade_chains: Vec<UpgradeChain>,
+    /// Item usage of the given accounts (only set if `account_id` or `account_ids` is given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) personal: Option<PersonalItemStats>,
+}
+
+/// Ways the item usage of a player differs from successful players
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PersonalItemFlag {
+    /// Bought much later than by successful players
+    BuysLater,
+    /// Bought much less often than by successful players
+    BuysLess,
+}
+
+/// Item usage of the given accounts next to the one of successful players, the winners of all
+/// matches under the same filters
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct PersonalItemStats {
+    pub(crate) matches: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) winrate: Option<f64>,
+    /// Share of the accounts' matches on the hero the item was bought in (0.0-1.0)
+    pub(crate) pick_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) avg_buy_time_s: Option<f64>,
+    /// Share of the successful players that bought the item (0.0-1.0)
+    pub(crate) successful_pick_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) successful_avg_buy_time_s: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) flags: Vec<PersonalItemFlag>,
+}
+
+/// Winrate of matches where an item was bought and later upgraded into another item
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct UpgradeChain {
+    pub(crate) upgrade_item_id: u32,
+    pub(crate) winrate: f64,
+    pub(crate) matches: u64,
 }
 
 /// Response for the build creator items endpoint
@@ -67,4 +198,413 @@ pub(crate) struct BuildCreatorResponse {
     pub hero_name: String,
     /// Items grouped by tier (1, 2, 3, 4), sorted by winrate descending
     pub tiers: HashMap<String, Vec<BuildCreatorItem>>,
+    /// Best performing ability orders, ranked by the Wilson lower bound of their winrate
+    pub ability_orders: Vec<BuildCreatorAbilityOrder>,
+    /// Number of matches of the given accounts on the hero (only set if `account_id` or
+    /// `account_ids` is given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_matches: Option<u64>,
+}
+
+/// A single ability point spent in an ability order
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct AbilityUpgrade {
+    pub(crate) ability_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) name: Option<String>,
+    /// Average game time in seconds the ability point was spent at
+    pub(crate) avg_time_s: f64,
+}
+
+/// Ability unlock and upgrade sequence with winrate statistics
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildCreatorAbilityOrder {
+    /// Ability points in the order they were spent, the first occurrence of an ability unlocks it
+    pub(crate) upgrades: Vec<AbilityUpgrade>,
+    pub(crate) matches: u64,
+    pub(crate) winrate: f64,
+    /// Lower bound of the 95% Wilson score interval of the winrate
+    pub(crate) wilson_lower_bound: f64,
+}
+
+/// Item picked for a recommended build
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct RecommendedItem {
+    /// Position of the item in the build (0-11), ordered by average buy time
+    pub(crate) position: u32,
+    pub(crate) item_id: u32,
+    pub(crate) name: String,
+    pub(crate) tier: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) slot: Option<String>,
+    /// Weighted average winrate across all buckets (0.0-1.0)
+    pub(crate) winrate: f64,
+    pub(crate) matches_total: u64,
+    pub(crate) avg_buy_time_s: f64,
+    /// Human-readable explanation why this item was picked
+    pub(crate) reason: String,
+}
+
+/// Response for the build creator recommend endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildCreatorRecommendation {
+    pub hero_id: u32,
+    pub hero_name: String,
+    /// Up to 12 items, ordered by average buy time
+    pub items: Vec<RecommendedItem>,
+}
+
+/// Item bought next after a set of items
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct NextItem {
+    pub(crate) item_id: u32,
+    pub(crate) name: String,
+    pub(crate) tier: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) slot: Option<String>,
+    /// Number of matches this item was bought next
+    pub(crate) matches: u64,
+    pub(crate) winrate: f64,
+    /// Lower bound of the 95% Wilson score interval of the winrate
+    pub(crate) wilson_lower_bound: f64,
+    /// Share of the players completing the set that bought this item next (0.0-1.0)
+    pub(crate) pick_rate: f64,
+    pub(crate) avg_buy_time_s: f64,
+}
+
+/// Response for the build creator next items endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildCreatorNextItems {
+    pub hero_id: u32,
+    pub hero_name: String,
+    /// The items already in the build
+    pub item_ids: Vec<u32>,
+    /// Number of matches in which all items of the build were bought
+    pub matches: u64,
+    pub items: Vec<NextItem>,
+}
+
+/// Alternative to an item in the same tier and slot
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct ItemSubstitute {
+    pub(crate) item_id: u32,
+    pub(crate) name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cost: Option<u32>,
+    /// Cost of the substitute minus cost of the item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cost_difference: Option<i64>,
+    /// Number of matches the substitute was bought without the item
+    pub(crate) matches: u64,
+    /// Winrate of the substitute when bought without the item
+    pub(crate) winrate: f64,
+    /// Lower bound of the 95% Wilson score interval of the winrate
+    pub(crate) wilson_lower_bound: f64,
+    /// Winrate of the substitute minus winrate of the item (missing if the item was not bought in
+    /// any match)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) winrate_delta: Option<f64>,
+    /// Share of the players skipping the item that bought the substitute (0.0-1.0)
+    pub(crate) substitution_rate: f64,
+}
+
+/// Response for the build creator substitutes endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildCreatorSubstitutes {
+    pub hero_id: u32,
+    pub hero_name: String,
+    pub item_id: u32,
+    pub name: String,
+    pub tier: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<u32>,
+    /// Number of matches the item was bought
+    pub matches: u64,
+    pub winrate: Option<f64>,
+    /// Number of matches the item was not bought
+    pub skipped_matches: u64,
+    pub substitutes: Vec<ItemSubstitute>,
+}
+
+/// Item bought right after selling an item
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct SellReplacement {
+    pub(crate) item_id: u32,
+    pub(crate) name: String,
+    /// Number of matches this item was bought right after the sale
+    pub(crate) matches: u64,
+    pub(crate) winrate: f64,
+}
+
+/// Winrate of the players that sold an item within a sell time bucket
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct SellTimeBucket {
+    /// Sell time range in minutes, e.g. "10-20" or "30+"
+    pub(crate) bucket: String,
+    pub(crate) matches: u64,
+    pub(crate) winrate: f64,
+    /// Lower bound of the 95% Wilson score interval of the winrate
+    pub(crate) wilson_lower_bound: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) most_common_replacement: Option<SellReplacement>,
+}
+
+/// Winrate of the players that sold or held an item
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct SellOutcome {
+    pub(crate) matches: u64,
+    pub(crate) winrate: Option<f64>,
+}
+
+/// Response for the build creator sell timing endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildCreatorSellTiming {
+    pub hero_id: u32,
+    pub hero_name: String,
+    pub item_id: u32,
+    pub name: String,
+    /// Number of matches the item was bought
+    pub matches: u64,
+    /// Share of the purchases that were sold (0.0-1.0)
+    pub sell_rate: f64,
+    pub sold: SellOutcome,
+    pub held: SellOutcome,
+    /// Only buckets with at least `min_matches` matches are included
+    pub buckets: Vec<SellTimeBucket>,
+    /// Most common items bought right after the sale, across all sell times
+    pub replacements: Vec<SellReplacement>,
+}
+
+/// Stats of an item within one badge range
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct ItemRangeStats {
+    pub(crate) matches: u64,
+    /// Share of the hero's players in the badge range that bought the item (0.0-1.0)
+    pub(crate) pick_rate: f64,
+    pub(crate) winrate: f64,
+    pub(crate) avg_buy_time_s: f64,
+}
+
+/// Item stats of both badge ranges and their differences, `compare` minus `base`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct ItemComparison {
+    pub(crate) item_id: u32,
+    pub(crate) name: String,
+    pub(crate) tier: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) slot: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) base: Option<ItemRangeStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) compare: Option<ItemRangeStats>,
+    pub(crate) pick_rate_delta: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) winrate_delta: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) avg_buy_time_delta_s: Option<f64>,
+}
+
+/// Badge range of a comparison and the number of the hero's players in it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BadgeRange {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) min_average_badge: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) max_average_badge: Option<u8>,
+    pub(crate) matches: u64,
+}
+
+/// Response for the build creator compare endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildCreatorComparison {
+    pub hero_id: u32,
+    pub hero_name: String,
+    pub base: BadgeRange,
+    pub compare: BadgeRange,
+    /// Items sorted by the absolute pick rate difference (descending)
+    pub items: Vec<ItemComparison>,
+}
+
+/// Window of match start times a patch was live
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct PatchWindow {
+    pub(crate) min_unix_timestamp: i64,
+    /// Not set for the latest patch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) max_unix_timestamp: Option<i64>,
+    /// Number of the hero's players on the patch
+    pub(crate) matches: u64,
+}
+
+/// Response for the build creator patch changes endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildCreatorPatchChanges {
+    pub hero_id: u32,
+    pub hero_name: String,
+    pub previous_patch: PatchWindow,
+    pub latest_patch: PatchWindow,
+    /// Items with their stats on the previous (`base`) and the latest patch (`compare`), sorted by
+    /// the absolute winrate change (descending)
+    pub items: Vec<ItemComparison>,
+}
+
+/// Request body for exporting a build to the in-game hero build format
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub(crate) struct BuildExportRequest {
+    /// See more: <https://assets.deadlock-api.com/v2/heroes>
+    pub(crate) hero_id: u32,
+    /// Name of the build. Defaults to "<hero name> Build"
+    pub(crate) name: Option<String>,
+    pub(crate) description: Option<String>,
+    /// Language of the build. Defaults to English
+    pub(crate) language: Option<BuildLanguage>,
+    /// The author's `SteamID3`
+    #[serde(default, deserialize_with = "parse_steam_id_option")]
+    pub(crate) author_account_id: Option<u32>,
+    /// Ordered list of item IDs. See more: <https://assets.deadlock-api.com/v2/items>
+    pub(crate) item_ids: Vec<u32>,
+    /// Optional annotations keyed by item ID
+    #[serde(default)]
+    pub(crate) annotations: HashMap<u32, String>,
+    /// Ability IDs in the order they are spent on. The first occurrence of an ability unlocks
+    /// it, every further occurrence upgrades it.
+    #[serde(default)]
+    pub(crate) ability_order: Vec<u32>,
+}
+
+/// Request body for evaluating a build
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub(crate) struct BuildEvaluationRequest {
+    /// See more: <https://assets.deadlock-api.com/v2/heroes>
+    pub(crate) hero_id: u32,
+    /// Ordered list of item IDs. See more: <https://assets.deadlock-api.com/v2/items>
+    pub(crate) item_ids: Vec<u32>,
+    /// Ability IDs in the order they are spent on
+    #[serde(default)]
+    pub(crate) ability_order: Vec<u32>,
+    /// Number of build items a match has to contain to be considered similar. Defaults to 75% of
+    /// the build
+    pub(crate) min_matching_items: Option<u32>,
+    /// Filter matches based on their start time (Unix timestamp). **Default:** 30 days ago.
+    #[serde(default = "default_last_month_timestamp")]
+    pub(crate) min_unix_timestamp: Option<i64>,
+    /// Filter matches based on their start time (Unix timestamp).
+    pub(crate) max_unix_timestamp: Option<i64>,
+    /// Filter matches based on the average badge level. See more: <https://assets.deadlock-api.com/v2/ranks>
+    pub(crate) min_average_badge: Option<u8>,
+    /// Filter matches based on the average badge level.
+    pub(crate) max_average_badge: Option<u8>,
+}
+
+/// Contribution of a single item to the winrate of a build
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct ItemContribution {
+    pub(crate) item_id: u32,
+    pub(crate) name: String,
+    pub(crate) tier: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) slot: Option<String>,
+    /// Number of similar matches that contain this item
+    pub(crate) matches_with: u64,
+    pub(crate) winrate_with: Option<f64>,
+    /// Number of similar matches that do not contain this item
+    pub(crate) matches_without: u64,
+    pub(crate) winrate_without: Option<f64>,
+    /// Winrate with the item minus winrate without it
+    pub(crate) marginal_contribution: Option<f64>,
+}
+
+/// Winrate of the similar matches that started with the given ability order
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct AbilityOrderEvaluation {
+    pub(crate) matches: u64,
+    pub(crate) winrate: Option<f64>,
+}
+
+/// Response for the build creator evaluate endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildEvaluation {
+    pub hero_id: u32,
+    pub hero_name: String,
+    /// Number of matches whose purchases are similar to the build
+    pub matches: u64,
+    /// Winrate of the similar matches
+    pub expected_winrate: Option<f64>,
+    /// Lower bound of the 95% Wilson score interval of the expected winrate
+    pub wilson_lower_bound: f64,
+    /// Score of the build from 0 to 100, the Wilson lower bound in percent
+    pub score: f64,
+    pub items: Vec<ItemContribution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ability_order: Option<AbilityOrderEvaluation>,
+    /// Problems found in the build, e.g. a missing tier 4 item or unbalanced slots
+    pub warnings: Vec<String>,
+}
+
+/// Build creator filters a draft was created with, so the frontend can restore them
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildDraftFilters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) min_matches: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) min_unix_timestamp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_unix_timestamp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) patch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) min_average_badge: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_average_badge: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sort_by: Option<SortBy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) timing_mode: Option<TimingMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) buckets: Option<Vec<u32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) enemy_hero_ids: Option<Vec<u32>>,
+}
+
+/// Request body for creating or updating a build draft
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub(crate) struct BuildDraftRequest {
+    /// See more: <https://assets.deadlock-api.com/v2/heroes>
+    pub(crate) hero_id: u32,
+    pub(crate) name: Option<String>,
+    /// Ordered list of item IDs in the build slots. See more: <https://assets.deadlock-api.com/v2/items>
+    #[serde(default)]
+    pub(crate) item_ids: Vec<u32>,
+    pub(crate) notes: Option<String>,
+    #[serde(default)]
+    pub(crate) filters: BuildDraftFilters,
+}
+
+/// A build draft of the build creator
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct BuildDraft {
+    /// Short code to share the draft, e.g. in a link
+    pub share_code: String,
+    pub hero_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub item_ids: Vec<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    pub filters: BuildDraftFilters,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Response for creating a build draft
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct CreatedBuildDraft {
+    #[serde(flatten)]
+    pub draft: BuildDraft,
+    /// Token to update or delete the draft, sent in the `X-Edit-Token` header. Only returned
+    /// once, for drafts created without an API key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edit_token: Option<Uuid>,
 }
@@ -0,0 +1,304 @@
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use itertools::Itertools;
+use serde::Deserialize;
+use tracing::debug;
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::confidence::wilson_lower_bound;
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, build_enemy_filter, build_info_filters, normalize_match_filters,
+};
+use crate::routes::v1::build_creator::structs::{
+    BuildCreatorSubstitutes, ItemSubstitute, SubstituteSortBy,
+};
+
+/// Maximum number of substitutes returned
+const MAX_LIMIT: u32 = 20;
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_limit() -> Option<u32> {
+    Some(5)
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams, Eq, PartialEq, Hash, Default)]
+pub(crate) struct BuildCreatorSubstitutesQuery {
+    #[serde(flatten)]
+    #[param(ignore)]
+    pub filters: BuildCreatorQuery,
+    /// Item ID to find substitutes for. See more: <https://assets.deadlock-api.com/v2/items>
+    pub item_id: u32,
+    /// Rank substitutes by: winrate_delta (default), substitution_rate or cost_difference
+    #[serde(default)]
+    #[param(inline)]
+    pub rank_by: SubstituteSortBy,
+    /// Maximum number of substitutes to return.
+    #[serde(default = "default_limit")]
+    #[param(minimum = 1, maximum = 20, default = 5)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Row, Deserialize)]
+struct SubstituteRow {
+    item_id: u32,
+    /// For the item itself the matches it was bought, for candidates the matches they were bought
+    /// without the item
+    wins: u64,
+    matches: u64,
+    /// Number of matches the item was not bought
+    skipped_matches: u64,
+}
+
+fn build_query(query: &BuildCreatorSubstitutesQuery, candidate_ids: &[u32]) -> String {
+    let info_filters = build_info_filters(&query.filters);
+    let enemy_filter = build_enemy_filter(&query.filters);
+    let hero_id = query.filters.hero_id;
+    let item_id = query.item_id;
+    let item_ids = core::iter::once(&item_id).chain(candidate_ids).join(", ");
+    format!(
+        "
+WITH
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    ),
+    t_players AS (
+        SELECT
+            won,
+            items.item_id AS item_ids,
+            has(item_ids, {item_id}) AS has_item
+        FROM match_player
+        WHERE match_id IN (SELECT match_id FROM t_matches)
+            AND hero_id = {hero_id}{enemy_filter}
+    ),
+    t_candidates AS (
+        SELECT
+            won,
+            has_item,
+            candidate_id,
+            if(candidate_id = {item_id}, has_item, NOT has_item AND has(item_ids, candidate_id)) AS bought
+        FROM t_players
+            ARRAY JOIN [{item_ids}] AS candidate_id
+    )
+SELECT
+    candidate_id AS item_id,
+    sumIf(won, bought) AS wins,
+    countIf(bought) AS matches,
+    countIf(NOT has_item) AS skipped_matches
+FROM t_candidates
+GROUP BY item_id
+ORDER BY item_id
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<SubstituteRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<SubstituteRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+/// Compares the winrate deltas descending, unknown deltas last.
+fn cmp_winrate_delta(a: &ItemSubstitute, b: &ItemSubstitute) -> core::cmp::Ordering {
+    match (a.winrate_delta, b.winrate_delta) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+fn sort_substitutes(substitutes: &mut [ItemSubstitute], sort_by: SubstituteSortBy) {
+    substitutes.sort_by(|a, b| match sort_by {
+        SubstituteSortBy::WinrateDelta => cmp_winrate_delta(a, b),
+        SubstituteSortBy::SubstitutionRate => b.substitution_rate.total_cmp(&a.substitution_rate),
+        SubstituteSortBy::CostDifference => a
+            .cost_difference
+            .map(i64::abs)
+            .unwrap_or(i64::MAX)
+            .cmp(&b.cost_difference.map(i64::abs).unwrap_or(i64::MAX))
+            .then_with(|| cmp_winrate_delta(a, b)),
+    });
+}
+
+pub(super) async fn build_creator_substitutes(
+    Query(mut query): Query<BuildCreatorSubstitutesQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    normalize_match_filters(&state, &mut query.filters).await?;
+    query.limit = query.limit.map(|l| l.clamp(1, MAX_LIMIT));
+
+    let hero_name = state
+        .assets_client
+        .fetch_hero_name_from_id(query.filters.hero_id)
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch hero: {e}")))?
+        .ok_or_else(|| {
+            APIError::bad_request(format!("Hero {} not found", query.filters.hero_id))
+        })?;
+    let upgrades = state
+        .assets_client
+        .fetch_items()
+        .await
+        .map_err(|e| APIError::internal(format!("Failed to fetch items: {e}")))?
+        .into_iter()
+        .filter(|item| item.item_type.as_deref() == Some("upgrade"))
+        .collect::<Vec<_>>();
+    let item = upgrades
+        .iter()
+        .find(|item| item.id == query.item_id)
+        .ok_or_else(|| {
+            APIError::bad_request(format!("Item {} is not an upgrade", query.item_id))
+        })?;
+    let candidates = upgrades
+        .iter()
+        .filter(|c| c.id != item.id && c.tier == item.tier && c.slot == item.slot)
+        .collect::<Vec<_>>();
+    let candidate_ids = candidates.iter().map(|c| c.id).collect::<Vec<_>>();
+
+    let query_str = build_query(&query, &candidate_ids);
+    debug!(?query_str);
+    let rows = run_query(&state.ch_client_ro, &query_str).await?;
+
+    let item_row = rows.iter().find(|row| row.item_id == item.id);
+    let matches = item_row.map_or(0, |row| row.matches);
+    let skipped_matches = item_row.map_or(0, |row| row.skipped_matches);
+    #[allow(clippy::cast_precision_loss)]
+    let winrate = item_row
+        .filter(|row| row.matches > 0)
+        .map(|row| row.wins as f64 / row.matches as f64);
+    let min_matches = u64::from(query.filters.min_matches.unwrap_or(50));
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut substitutes = rows
+        .iter()
+        .filter(|row| row.item_id != item.id && row.matches >= min_matches.max(1))
+        .filter_map(|row| {
+            let candidate = candidates.iter().find(|c| c.id == row.item_id)?;
+            let substitute_winrate = row.wins as f64 / row.matches as f64;
+            Some(ItemSubstitute {
+                item_id: candidate.id,
+                name: candidate.name.clone(),
+                cost: candidate.cost,
+                cost_difference: candidate
+                    .cost
+                    .zip(item.cost)
+                    .map(|(c, i)| i64::from(c) - i64::from(i)),
+                matches: row.matches,
+                winrate: substitute_winrate,
+                wilson_lower_bound: wilson_lower_bound(row.wins, row.matches),
+                winrate_delta: winrate.map(|winrate| substitute_winrate - winrate),
+                substitution_rate: if row.skipped_matches > 0 {
+                    row.matches as f64 / row.skipped_matches as f64
+                } else {
+                    0.0
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    sort_substitutes(&mut substitutes, query.rank_by);
+    substitutes.truncate(query.limit.unwrap_or(5) as usize);
+
+    Ok(Json(BuildCreatorSubstitutes {
+        hero_id: query.filters.hero_id,
+        hero_name,
+        item_id: item.id,
+        name: item.name.clone(),
+        tier: item.tier.unwrap_or_default(),
+        slot: item.slot.clone(),
+        cost: item.cost,
+        matches,
+        winrate,
+        skipped_matches,
+        substitutes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substitute(
+        item_id: u32,
+        cost_difference: i64,
+        rate: f64,
+        delta: Option<f64>,
+    ) -> ItemSubstitute {
+        ItemSubstitute {
+            item_id,
+            name: format!("Item {item_id}"),
+            cost: None,
+            cost_difference: Some(cost_difference),
+            matches: 100,
+            winrate: 0.5,
+            wilson_lower_bound: 0.4,
+            winrate_delta: delta,
+            substitution_rate: rate,
+        }
+    }
+
+    #[test]
+    fn test_build_query() {
+        let query = BuildCreatorSubstitutesQuery {
+            filters: BuildCreatorQuery {
+                hero_id: 7,
+                enemy_hero_ids: Some(vec![1]),
+                ..Default::default()
+            },
+            item_id: 10,
+            ..Default::default()
+        };
+        let sql = build_query(&query, &[11, 12]);
+        assert!(sql.contains("hero_id = 7"));
+        assert!(sql.contains("has(item_ids, 10) AS has_item"));
+        assert!(sql.contains("ARRAY JOIN [10, 11, 12] AS candidate_id"));
+        assert!(sql.contains("hero_id IN (1)"));
+    }
+
+    #[test]
+    fn test_sort_substitutes() {
+        let mut substitutes = vec![
+            substitute(1, 500, 0.1, Some(0.02)),
+            substitute(2, -250, 0.3, Some(-0.01)),
+            substitute(3, 0, 0.2, Some(0.01)),
+        ];
+        let ids = |s: &[ItemSubstitute]| s.iter().map(|s| s.item_id).collect::<Vec<_>>();
+
+        sort_substitutes(&mut substitutes, SubstituteSortBy::WinrateDelta);
+        assert_eq!(ids(&substitutes), vec![1, 3, 2]);
+        sort_substitutes(&mut substitutes, SubstituteSortBy::SubstitutionRate);
+        assert_eq!(ids(&substitutes), vec![2, 3, 1]);
+        sort_substitutes(&mut substitutes, SubstituteSortBy::CostDifference);
+        assert_eq!(ids(&substitutes), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_sort_substitutes_unknown_delta() {
+        let mut substitutes = vec![
+            substitute(1, 0, 0.1, None),
+            substitute(2, 0, 0.3, Some(-0.01)),
+            substitute(3, 0, 0.2, Some(0.01)),
+        ];
+        let ids = |s: &[ItemSubstitute]| s.iter().map(|s| s.item_id).collect::<Vec<_>>();
+
+        sort_substitutes(&mut substitutes, SubstituteSortBy::WinrateDelta);
+        assert_eq!(ids(&substitutes), vec![3, 2, 1]);
+        sort_substitutes(&mut substitutes, SubstituteSortBy::CostDifference);
+        assert_eq!(ids(&substitutes), vec![3, 2, 1]);
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{HashMap, HashSet};
+
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use itertools::Itertools;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::context::AppState;
+use crate::error::APIResult;
+use crate::routes::v1::build_creator::handlers::{
+    BuildCreatorQuery, build_enemy_filter, build_info_filters,
+};
+use crate::routes::v1::build_creator::structs::UpgradeChain;
+use crate::services::assets::types::AssetsItem;
+
+/// Upgrade graph of the shop items, built from the `component_items` of the assets API.
+#[derive(Debug, Default)]
+pub(super) struct UpgradeGraph {
+    upgrades_from: HashMap<u32, Vec<u32>>,
+    upgrades_into: HashMap<u32, Vec<u32>>,
+}
+
+impl UpgradeGraph {
+    pub(super) fn new<'a>(items: impl IntoIterator<Item = &'a AssetsItem>) -> Self {
+        let items = items.into_iter().collect::<Vec<_>>();
+        let ids_by_class_name: HashMap<&str, u32> = items
+            .iter()
+            .filter_map(|item| item.class_name.as_deref().map(|c| (c, item.id)))
+            .collect();
+
+        let mut graph = Self::default();
+        for item in items {
+            for component in item.component_items.iter().flatten() {
+                let Some(&component_id) = ids_by_class_name.get(component.as_str()) else {
+                    continue;
+                };
+                graph
+                    .upgrades_from
+                    .entry(item.id)
+                    .or_default()
+                    .push(component_id);
+                graph
+                    .upgrades_into
+                    .entry(component_id)
+                    .or_default()
+                    .push(item.id);
+            }
+        }
+        for ids in graph
+            .upgrades_from
+            .values_mut()
+            .chain(graph.upgrades_into.values_mut())
+        {
+            ids.sort_unstable();
+            ids.dedup();
+        }
+        graph
+    }
+
+    /// Item IDs the item is upgraded from
+    pub(super) fn upgrades_from(&self, item_id: u32) -> Vec<u32> {
+        self.upgrades_from
+            .get(&item_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Item IDs the item upgrades into
+    pub(super) fn upgrades_into(&self, item_id: u32) -> Vec<u32> {
+        self.upgrades_into
+            .get(&item_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Item IDs the item is built from, including the components of its components
+    pub(super) fn all_components(&self, item_id: u32) -> HashSet<u32> {
+        let mut components = HashSet::new();
+        let mut stack = self.upgrades_from(item_id);
+        while let Some(component) = stack.pop() {
+            if components.insert(component) {
+                stack.extend(self.upgrades_from(component));
+            }
+        }
+        components
+    }
+
+    /// All (component, upgrade) pairs, sorted to keep the query stable for caching
+    fn pairs(&self) -> Vec<(u32, u32)> {
+        self.upgrades_into
+            .iter()
+            .flat_map(|(&component, upgrades)| upgrades.iter().map(move |&u| (component, u)))
+            .sorted_unstable()
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Row, Deserialize)]
+struct UpgradeChainRow {
+    component_id: u32,
+    upgrade_id: u32,
+    wins: u64,
+    matches: u64,
+}
+
+fn build_query(query: &BuildCreatorQuery, pairs: &[(u32, u32)]) -> String {
+    let info_filters = build_info_filters(query);
+    let enemy_filter = build_enemy_filter(query);
+    let hero_id = query.hero_id;
+    let min_matches = query.min_matches.unwrap_or(50);
+    let pairs = pairs
+        .iter()
+        .map(|(component, upgrade)| format!("({component}, {upgrade})"))
+        .join(", ");
+    format!(
+        "
+WITH
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    )
+SELECT
+    toUInt32(pair.1) AS component_id,
+    toUInt32(pair.2) AS upgrade_id,
+    sum(won) AS wins,
+    count() AS matches
+FROM match_player
+    ARRAY JOIN [{pairs}] AS pair
+WHERE match_id IN (SELECT match_id FROM t_matches)
+    AND hero_id = {hero_id}{enemy_filter}
+    AND indexOf(items.item_id, pair.1) > 0
+    AND indexOf(items.item_id, pair.2) > 0
+    AND items.game_time_s[indexOf(items.item_id, pair.1)] < items.game_time_s[indexOf(items.item_id, pair.2)]
+GROUP BY component_id, upgrade_id
+HAVING matches >= {min_matches}
+ORDER BY component_id, upgrade_id
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<UpgradeChainRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<UpgradeChainRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+/// Fetches the winrates of buying a component and later upgrading it, keyed by component ID.
+pub(super) async fn fetch_upgrade_chains(
+    state: &AppState,
+    query: &BuildCreatorQuery,
+    graph: &UpgradeGraph,
+) -> APIResult<HashMap<u32, Vec<UpgradeChain>>> {
+    let pairs = graph.pairs();
+    if pairs.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let query_str = build_query(query, &pairs);
+    debug!(?query_str);
+    let rows = run_query(&state.ch_client_ro, &query_str).await?;
+
+    let mut chains: HashMap<u32, Vec<UpgradeChain>> = HashMap::new();
+    for row in rows {
+        #[allow(clippy::cast_precision_loss)]
+        let winrate = if row.matches > 0 {
+            row.wins as f64 / row.matches as f64
+        } else {
+            0.0
+        };
+        chains
+            .entry(row.component_id)
+            .or_default()
+            .push(UpgradeChain {
+                upgrade_item_id: row.upgrade_id,
+                winrate,
+                matches: row.matches,
+            });
+    }
+    Ok(chains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: u32, class_name: &str, component_items: &[&str]) -> AssetsItem {
+        AssetsItem {
+            id,
+            name: class_name.to_owned(),
+            tier: Some(1),
+            cost: None,
+            item_type: Some("upgrade".to_owned()),
+            slot: None,
+            class_name: Some(class_name.to_owned()),
+            component_items: Some(component_items.iter().map(|&c| c.to_owned()).collect()),
+        }
+    }
+
+    fn graph() -> UpgradeGraph {
+        let items = [
+            item(1, "upgrade_a", &[]),
+            item(2, "upgrade_b", &[]),
+            item(3, "upgrade_c", &["upgrade_a"]),
+            item(
+                4,
+                "upgrade_d",
+                &["upgrade_a", "upgrade_b", "upgrade_unknown"],
+            ),
+        ];
+        UpgradeGraph::new(&items)
+    }
+
+    #[test]
+    fn test_upgrade_graph() {
+        let graph = graph();
+        assert_eq!(graph.upgrades_into(1), vec![3, 4]);
+        assert_eq!(graph.upgrades_into(2), vec![4]);
+        assert_eq!(graph.upgrades_from(4), vec![1, 2]);
+        assert!(graph.upgrades_from(1).is_empty());
+        assert!(graph.upgrades_into(4).is_empty());
+        assert_eq!(graph.pairs(), vec![(1, 3), (1, 4), (2, 4)]);
+    }
+
+    #[test]
+    fn test_all_components() {
+        let items = [
+            item(1, "upgrade_a", &[]),
+            item(2, "upgrade_b", &["upgrade_a"]),
+            item(3, "upgrade_c", &["upgrade_b"]),
+        ];
+        let graph = UpgradeGraph::new(&items);
+        assert_eq!(graph.all_components(3), HashSet::from([1, 2]));
+        assert_eq!(graph.all_components(2), HashSet::from([1]));
+        assert!(graph.all_components(1).is_empty());
+    }
+
+    #[test]
+    fn test_build_query_pairs_and_filters() {
+        let query = BuildCreatorQuery {
+            hero_id: 7,
+            min_matches: Some(100),
+            min_unix_timestamp: Some(1_672_531_200),
+            min_average_badge: Some(50),
+            ..Default::default()
+        };
+        let sql = build_query(&query, &graph().pairs());
+        assert!(sql.contains("ARRAY JOIN [(1, 3), (1, 4), (2, 4)] AS pair"));
+        assert!(sql.contains("hero_id = 7"));
+        assert!(sql.contains("start_time >= 1672531200"));
+        assert!(sql.contains("average_badge_team0 >= 50 AND average_badge_team1 >= 50"));
+        assert!(sql.contains("HAVING matches >= 100"));
+    }
+}
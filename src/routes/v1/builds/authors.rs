@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use futures::try_join;
+use serde::Deserialize;
+use sqlx::postgres::PgRow;
+use sqlx::{QueryBuilder, Row};
+use strum::Display;
+use tracing::debug;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::builds::structs::{
+    Build, BuildAuthor, BuildAuthorProfile, BuildAuthorStats,
+};
+use crate::routes::v1::players::steam::route::{SteamProfile, get_steam_many, get_steam_single};
+use crate::utils::types::AccountIdQuery;
+
+/// Maximum number of authors returned
+const MAX_LIMIT: u32 = 1000;
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_limit() -> Option<u32> {
+    Some(100)
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema, Default, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(super) enum BuildAuthorsSortBy {
+    /// Sort by the number of all-time favorites across the author's builds.
+    #[default]
+    Favorites,
+    /// Sort by the number of weekly favorites across the author's builds.
+    WeeklyFavorites,
+    /// Sort by the number of published builds.
+    NumBuilds,
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub(super) struct BuildAuthorsQuery {
+    /// Only count builds of this hero. See more: <https://assets.deadlock-api.com/v2/heroes>
+    hero_id: Option<u32>,
+    /// The field to sort the authors by.
+    #[serde(default)]
+    #[param(inline)]
+    sort_by: BuildAuthorsSortBy,
+    /// The maximum number of authors to return.
+    #[serde(default = "default_limit")]
+    #[param(minimum = 1, maximum = 1000, default = 100)]
+    limit: Option<u32>,
+}
+
+/// Query of the stats of the authors, aggregated over the latest version of their builds.
+fn author_stats_query(
+    hero_id: Option<u32>,
+    author_id: Option<u32>,
+    protected_users: &[u32],
+) -> QueryBuilder<'static, sqlx::Postgres> {
+    let mut query_builder = QueryBuilder::new(
+        "WITH latest AS (SELECT DISTINCT ON (build_id) hero, author_id, favorites, \
+         weekly_favorites, language FROM hero_builds WHERE author_id IS NOT NULL",
+    );
+    if let Some(hero_id) = hero_id {
+        query_builder.push(" AND hero = ");
+        query_builder.push_bind(hero_id.cast_signed());
+    }
+    if let Some(author_id) = author_id {
+        query_builder.push(" AND author_id = ");
+        query_builder.push_bind(author_id.cast_signed());
+    }
+    if !protected_users.is_empty() {
+        query_builder.push(" AND author_id <> ALL(");
+        query_builder.push_bind(
+            protected_users
+                .iter()
+                .copied()
+                .map(u32::cast_signed)
+                .collect::<Vec<_>>(),
+        );
+        query_builder.push(")");
+    }
+    query_builder.push(
+        " ORDER BY build_id, version DESC) SELECT author_id, count(*) AS num_builds, \
+         sum(favorites)::bigint AS favorites, sum(weekly_favorites)::bigint AS weekly_favorites, \
+         array_agg(DISTINCT hero ORDER BY hero) AS hero_ids, coalesce(array_agg(DISTINCT \
+         language ORDER BY language) FILTER (WHERE language IS NOT NULL), '{}') AS languages \
+         FROM latest GROUP BY author_id",
+    );
+    query_builder
+}
+
+fn leaderboard_query(
+    query: &BuildAuthorsQuery,
+    protected_users: &[u32],
+) -> QueryBuilder<'static, sqlx::Postgres> {
+    let mut query_builder = author_stats_query(query.hero_id, None, protected_users);
+    query_builder.push(format!(
+        " ORDER BY {} DESC, author_id LIMIT ",
+        query.sort_by
+    ));
+    query_builder.push_bind(i64::from(query.limit.unwrap_or(100)));
+    query_builder
+}
+
+fn author_stats(row: &PgRow) -> BuildAuthorStats {
+    let to_u32 = |ids: Vec<i32>| ids.into_iter().map(i32::cast_unsigned).collect();
+    BuildAuthorStats {
+        account_id: row.get::<i32, _>("author_id").cast_unsigned(),
+        num_builds: row.get::<i64, _>("num_builds").cast_unsigned(),
+        favorites: row.get::<i64, _>("favorites").cast_unsigned(),
+        weekly_favorites: row.get::<i64, _>("weekly_favorites").cast_unsigned(),
+        hero_ids: to_u32(row.get("hero_ids")),
+        languages: to_u32(row.get("languages")),
+    }
+}
+
+async fn fetch_author_builds(
+    pg_client: &sqlx::Pool<sqlx::Postgres>,
+    account_id: u32,
+) -> sqlx::Result<Vec<Build>> {
+    let query = "SELECT builds FROM (SELECT DISTINCT ON (build_id) data AS builds, favorites, \
+                 build_id FROM hero_builds WHERE author_id = $1 ORDER BY build_id, version DESC) \
+                 latest ORDER BY favorites DESC, build_id DESC";
+    debug!(query, account_id);
+    Ok(sqlx::query(query)
+        .bind(account_id.cast_signed())
+        .fetch_all(pg_client)
+        .await?
+        .iter()
+        .map(|row| row.get::<sqlx::types::Json<_>, &str>("builds").0)
+        .collect())
+}
+
+async fn fetch_author_stats(
+    pg_client: &sqlx::Pool<sqlx::Postgres>,
+    account_id: u32,
+) -> sqlx::Result<Option<BuildAuthorStats>> {
+    let mut query_builder = author_stats_query(None, Some(account_id), &[]);
+    debug!(query = query_builder.sql());
+    Ok(query_builder
+        .build()
+        .fetch_optional(pg_client)
+        .await?
+        .as_ref()
+        .map(author_stats))
+}
+
+/// Fetches the Steam profile of the account, a missing profile is not an error.
+async fn fetch_steam_profile(
+    ch_client: &clickhouse::Client,
+    account_id: u32,
+) -> APIResult<Option<SteamProfile>> {
+    match get_steam_single(ch_client, account_id).await {
+        Ok(profile) => Ok(Some(profile)),
+        Err(APIError::StatusMsg {
+            status: StatusCode::NOT_FOUND,
+            ..
+        }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/authors",
+    params(BuildAuthorsQuery),
+    responses(
+        (status = OK, body = [BuildAuthor]),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+    ),
+    tags = ["Builds"],
+    summary = "Top Authors",
+    description = "
+Ranks build authors by the favorites of their builds or the number of builds they published,
+optionally only counting builds of one hero.
+
+Only the latest version of each build is counted.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(super) async fn build_authors(
+    Query(mut query): Query<BuildAuthorsQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    query.limit = query.limit.map(|l| l.clamp(1, MAX_LIMIT));
+    let protected_users = state
+        .steam_client
+        .get_protected_users(&state.pg_client)
+        .await?;
+    let mut query_builder = leaderboard_query(&query, &protected_users);
+    debug!(query = query_builder.sql());
+    let stats = query_builder
+        .build()
+        .fetch_all(&state.pg_client)
+        .await?
+        .iter()
+        .map(author_stats)
+        .collect::<Vec<_>>();
+    if stats.is_empty() {
+        return Ok(Json(vec![]));
+    }
+
+    let account_ids = stats.iter().map(|s| s.account_id).collect::<Vec<_>>();
+    // Profiles are ordered by last update, keep the most recent one per account
+    let mut profiles: HashMap<u32, SteamProfile> = HashMap::new();
+    for profile in get_steam_many(&state.ch_client_ro, &account_ids).await? {
+        profiles.entry(profile.account_id).or_insert(profile);
+    }
+    Ok(Json(
+        stats
+            .into_iter()
+            .map(|stats| BuildAuthor {
+                steam_profile: profiles.remove(&stats.account_id),
+                stats,
+            })
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/authors/{account_id}",
+    params(AccountIdQuery),
+    responses(
+        (status = OK, body = BuildAuthorProfile),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = FORBIDDEN, description = "The requested user is protected."),
+        (status = NOT_FOUND, description = "Build author not found."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+    ),
+    tags = ["Builds"],
+    summary = "Author Profile",
+    description = "
+Returns the published builds of an author, most favorited first, together with the favorites across
+all builds, the heroes and languages covered and the author's Steam profile.
+
+Only the latest version of each build is returned and counted.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(super) async fn build_author(
+    Path(AccountIdQuery { account_id }): Path<AccountIdQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    if state
+        .steam_client
+        .is_user_protected(&state.pg_client, account_id)
+        .await?
+    {
+        return Err(APIError::protected_user());
+    }
+    let (stats, builds) = try_join!(
+        fetch_author_stats(&state.pg_client, account_id),
+        fetch_author_builds(&state.pg_client, account_id),
+    )?;
+    let stats = stats
+        .ok_or_else(|| APIError::status_msg(StatusCode::NOT_FOUND, "Build author not found."))?;
+    let steam_profile = fetch_steam_profile(&state.ch_client_ro, account_id).await?;
+    Ok(Json(BuildAuthorProfile {
+        author: BuildAuthor {
+            stats,
+            steam_profile,
+        },
+        builds,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leaderboard_query() {
+        let query = BuildAuthorsQuery {
+            hero_id: Some(10),
+            sort_by: BuildAuthorsSortBy::WeeklyFavorites,
+            limit: Some(10),
+        };
+        let query_builder = leaderboard_query(&query, &[1, 2]);
+        let sql = query_builder.sql();
+        assert!(sql.contains("WHERE author_id IS NOT NULL AND hero = $1 AND author_id <> ALL($2)"));
+        assert!(
+            sql.ends_with(" GROUP BY author_id ORDER BY weekly_favorites DESC, author_id LIMIT $3")
+        );
+    }
+
+    #[test]
+    fn test_author_stats_query() {
+        let query_builder = author_stats_query(None, Some(1), &[]);
+        let sql = query_builder.sql();
+        assert!(sql.contains("WHERE author_id IS NOT NULL AND author_id = $1 ORDER BY"));
+        assert!(!sql.contains("ALL("));
+    }
+}
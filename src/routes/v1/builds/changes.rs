@@ -0,0 +1,390 @@
+use core::time::Duration;
+use std::collections::{HashMap, VecDeque};
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum_extra::extract::Query;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use futures::Stream;
+use serde::Deserialize;
+use sqlx::{QueryBuilder, Row};
+use tracing::{debug, warn};
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::builds::structs::{BuildChange, BuildChangeKind};
+use crate::utils::parse::parse_steam_id_option;
+
+/// Time between polls for new changes once the feed has caught up
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Maximum number of changes fetched per poll
+const BATCH_SIZE: u32 = 100;
+/// How far behind the position every poll scans again, builds are scraped some time after they
+/// were updated and can show up behind changes that were already emitted
+const RESCAN_WINDOW: TimeDelta = TimeDelta::minutes(30);
+/// Header sent by reconnecting `EventSource` clients with the id of the last received event
+const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+pub(super) struct BuildChangesQuery {
+    /// Position to resume the feed from, the `id` of the last received event. The
+    /// `Last-Event-ID` header takes precedence, so reconnecting clients resume automatically.
+    cursor: Option<String>,
+    /// Emit builds updated after this time (Unix timestamp) if no cursor is given.
+    /// **Default:** now.
+    since: Option<i64>,
+    /// Filter builds by hero ID. See more: <https://assets.deadlock-api.com/v2/heroes>
+    hero_id: Option<u32>,
+    /// Filter builds by the author's `SteamID3`.
+    #[serde(default, deserialize_with = "parse_steam_id_option")]
+    author_id: Option<u32>,
+}
+
+/// Position in the feed, the last change that was emitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ChangePosition {
+    updated_at: NaiveDateTime,
+    build_id: u32,
+    version: u32,
+}
+
+impl ChangePosition {
+    fn encode(self) -> String {
+        format!(
+            "{}_{}_{}",
+            self.updated_at.and_utc().timestamp_micros(),
+            self.build_id,
+            self.version
+        )
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let mut parts = cursor.split('_');
+        let updated_at = DateTime::from_timestamp_micros(parts.next()?.parse().ok()?)?;
+        let build_id = parts.next()?.parse().ok()?;
+        let version = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            updated_at: updated_at.naive_utc(),
+            build_id,
+            version,
+        })
+    }
+}
+
+fn changes_query(
+    query: &BuildChangesQuery,
+    position: ChangePosition,
+) -> QueryBuilder<'static, sqlx::Postgres> {
+    let mut query_builder = QueryBuilder::new(
+        "SELECT data AS builds, updated_at, build_id, version FROM hero_builds WHERE (updated_at, \
+         build_id, version) > (",
+    );
+    query_builder.push_bind(position.updated_at);
+    query_builder.push(", ");
+    query_builder.push_bind(position.build_id.cast_signed());
+    query_builder.push(", ");
+    query_builder.push_bind(position.version.cast_signed());
+    query_builder.push(")");
+    if let Some(hero_id) = query.hero_id {
+        query_builder.push(" AND hero = ");
+        query_builder.push_bind(hero_id.cast_signed());
+    }
+    if let Some(author_id) = query.author_id {
+        query_builder.push(" AND author_id = ");
+        query_builder.push_bind(author_id.cast_signed());
+    }
+    query_builder.push(" ORDER BY updated_at, build_id, version LIMIT ");
+    query_builder.push_bind(i64::from(BATCH_SIZE));
+    query_builder
+}
+
+async fn fetch_changes(
+    pg_client: &sqlx::Pool<sqlx::Postgres>,
+    query: &BuildChangesQuery,
+    position: ChangePosition,
+) -> sqlx::Result<Vec<(ChangePosition, BuildChange)>> {
+    let mut query_builder = changes_query(query, position);
+    debug!(query = query_builder.sql());
+    Ok(query_builder
+        .build()
+        .fetch_all(pg_client)
+        .await?
+        .iter()
+        .map(|row| {
+            let position = ChangePosition {
+                updated_at: row.get("updated_at"),
+                build_id: row.get::<i32, _>("build_id").cast_unsigned(),
+                version: row.get::<i32, _>("version").cast_unsigned(),
+            };
+            let change = BuildChange {
+                kind: if position.version > 1 {
+                    BuildChangeKind::Updated
+                } else {
+                    BuildChangeKind::Created
+                },
+                updated_at: position.updated_at.and_utc().timestamp(),
+                build: row.get::<sqlx::types::Json<_>, &str>("builds").0,
+            };
+            (position, change)
+        })
+        .collect())
+}
+
+fn change_event(position: ChangePosition, change: &BuildChange) -> Result<Event, axum::Error> {
+    Event::default()
+        .id(position.encode())
+        .event("build")
+        .json_data(change)
+}
+
+/// Position of a feed, remembering the changes emitted recently to scan behind the position
+/// without emitting them again.
+#[derive(Debug)]
+struct FeedCursor {
+    /// Position of the latest change emitted
+    position: ChangePosition,
+    /// Position the current scan continues after
+    scan: ChangePosition,
+    /// Changes before this time were emitted before the feed started, they are not scanned again
+    start: NaiveDateTime,
+    /// Build ID and version of the changes emitted within the rescan window, with their update time
+    emitted: HashMap<(u32, u32), NaiveDateTime>,
+}
+
+impl FeedCursor {
+    fn new(position: ChangePosition) -> Self {
+        Self {
+            position,
+            scan: position,
+            start: position.updated_at,
+            emitted: HashMap::new(),
+        }
+    }
+
+    /// Starts the next scan [`RESCAN_WINDOW`] behind the position, forgetting the changes emitted
+    /// before it.
+    fn rescan(&mut self) {
+        let updated_at = (self.position.updated_at - RESCAN_WINDOW).max(self.start);
+        self.emitted
+            .retain(|_, emitted_at| *emitted_at >= updated_at);
+        self.scan = ChangePosition {
+            updated_at,
+            build_id: 0,
+            version: 0,
+        };
+    }
+
+    /// Advances the scan past a batch and returns the changes that were not emitted yet, with the
+    /// position to emit them at.
+    ///
+    /// Late changes are emitted at the latest position, so resuming a feed never goes back.
+    fn advance<T>(&mut self, changes: Vec<(ChangePosition, T)>) -> Vec<(ChangePosition, T)> {
+        if let Some((position, _)) = changes.last() {
+            self.scan = *position;
+        }
+        changes
+            .into_iter()
+            .filter_map(|(position, change)| {
+                let key = (position.build_id, position.version);
+                if self.emitted.insert(key, position.updated_at).is_some() {
+                    return None;
+                }
+                self.position = self.position.max(position);
+                Some((self.position, change))
+            })
+            .collect()
+    }
+}
+
+struct Feed {
+    pg_client: sqlx::Pool<sqlx::Postgres>,
+    query: BuildChangesQuery,
+    cursor: FeedCursor,
+    pending: VecDeque<(ChangePosition, BuildChange)>,
+    caught_up: bool,
+}
+
+/// Emits the pending changes, fetching the next batch right away until the feed has caught up and
+/// polling every [`POLL_INTERVAL`] after that.
+fn feed_stream(feed: Feed) -> impl Stream<Item = Result<Event, axum::Error>> {
+    futures::stream::unfold(feed, |mut feed| async move {
+        loop {
+            if let Some((position, change)) = feed.pending.pop_front() {
+                return Some((change_event(position, &change), feed));
+            }
+            if feed.caught_up {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                feed.cursor.rescan();
+            }
+            match fetch_changes(&feed.pg_client, &feed.query, feed.cursor.scan).await {
+                Ok(changes) => {
+                    feed.caught_up = changes.len() < BATCH_SIZE as usize;
+                    let changes = feed.cursor.advance(changes);
+                    feed.pending.extend(changes);
+                }
+                Err(e) => {
+                    warn!("Failed to fetch build changes: {e}");
+                    feed.caught_up = true;
+                }
+            }
+        }
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/changes",
+    params(BuildChangesQuery),
+    responses(
+        (status = OK, description = "Server-sent events of changed builds", content_type = "text/event-stream", body = BuildChange),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+    ),
+    tags = ["Builds"],
+    summary = "Changes",
+    description = "
+Streams builds created or updated after a position as server-sent events, oldest first.
+
+Every event is of type `build` and carries the build as JSON. Its `id` is the position of the change,
+pass it as `cursor` (or the `Last-Event-ID` header, which `EventSource` clients send automatically
+when reconnecting) to resume the feed. New changes are polled every 30 seconds.
+
+Builds are scraped some time after they were updated, so every poll also looks 30 minutes behind the
+latest change for builds that showed up late. Those are emitted once, with the `id` of the latest
+change. Changes that show up late while no client is connected are not emitted when resuming.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(super) async fn build_changes(
+    Query(query): Query<BuildChangesQuery>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    let cursor = headers
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .or(query.cursor.as_deref());
+    let position = match cursor {
+        Some(cursor) => {
+            ChangePosition::decode(cursor).ok_or_else(|| APIError::bad_request("Invalid cursor"))?
+        }
+        None => ChangePosition {
+            updated_at: query
+                .since
+                .map_or_else(
+                    || Some(Utc::now()),
+                    |since| DateTime::from_timestamp(since, 0),
+                )
+                .ok_or_else(|| APIError::bad_request("Invalid since timestamp"))?
+                .naive_utc(),
+            build_id: 0,
+            version: 0,
+        },
+    };
+
+    let feed = Feed {
+        pg_client: state.pg_client.clone(),
+        query,
+        cursor: FeedCursor::new(position),
+        pending: VecDeque::new(),
+        caught_up: false,
+    };
+    Ok(Sse::new(feed_stream(feed)).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_position() {
+        let position = ChangePosition {
+            updated_at: DateTime::from_timestamp_micros(1_748_793_876_123_456)
+                .unwrap()
+                .naive_utc(),
+            build_id: 227_099,
+            version: 8,
+        };
+        assert_eq!(position.encode(), "1748793876123456_227099_8");
+        assert_eq!(ChangePosition::decode(&position.encode()), Some(position));
+        assert_eq!(ChangePosition::decode("1_2"), None);
+        assert_eq!(ChangePosition::decode("1_2_3_4"), None);
+        assert_eq!(ChangePosition::decode("a_2_3"), None);
+    }
+
+    fn position(updated_at: i64, build_id: u32) -> ChangePosition {
+        ChangePosition {
+            updated_at: DateTime::from_timestamp(updated_at, 0).unwrap().naive_utc(),
+            build_id,
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn test_feed_cursor_late_insert() {
+        let mut cursor = FeedCursor::new(position(1_000, 0));
+        let emitted = cursor.advance(vec![(position(1_100, 1), 1), (position(1_300, 2), 2)]);
+        assert_eq!(
+            emitted,
+            vec![(position(1_100, 1), 1), (position(1_300, 2), 2)]
+        );
+
+        // Build 3 was scraped after build 2 was emitted, but updated before it
+        cursor.rescan();
+        assert_eq!(
+            cursor.scan,
+            ChangePosition::decode("1000000000_0_0").unwrap()
+        );
+        let emitted = cursor.advance(vec![
+            (position(1_100, 1), 1),
+            (position(1_200, 3), 3),
+            (position(1_300, 2), 2),
+        ]);
+        assert_eq!(emitted, vec![(position(1_300, 2), 3)]);
+        assert_eq!(cursor.position, position(1_300, 2));
+        assert_eq!(cursor.scan, position(1_300, 2));
+    }
+
+    #[test]
+    fn test_feed_cursor_rescan_window() {
+        let mut cursor = FeedCursor::new(position(0, 0));
+        cursor.advance(vec![(position(60, 1), ()), (position(3_600, 2), ())]);
+        cursor.rescan();
+
+        // The scan starts 30 minutes behind the position, build 1 is forgotten
+        assert_eq!(cursor.scan.updated_at, position(1_800, 0).updated_at);
+        assert_eq!(cursor.emitted.len(), 1);
+        assert!(cursor.advance(vec![(position(3_600, 2), ())]).is_empty());
+    }
+
+    #[test]
+    fn test_changes_query() {
+        let query = BuildChangesQuery {
+            cursor: None,
+            since: None,
+            hero_id: Some(10),
+            author_id: None,
+        };
+        let position = ChangePosition::decode("0_0_0").unwrap();
+        let query_builder = changes_query(&query, position);
+        assert_eq!(
+            query_builder.sql(),
+            "SELECT data AS builds, updated_at, build_id, version FROM hero_builds WHERE \
+             (updated_at, build_id, version) > ($1, $2, $3) AND hero = $4 ORDER BY updated_at, \
+             build_id, version LIMIT $5"
+        );
+    }
+}
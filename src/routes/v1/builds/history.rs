@@ -0,0 +1,326 @@
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use serde::Deserialize;
+use sqlx::Row;
+use tracing::debug;
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::builds::structs::{
+    Build, BuildAbilityOrderChange, BuildAnnotationChange, BuildCategoryDiff, BuildHero,
+    BuildHeroDetailsAbilityOrderCurrencyChange, BuildHeroDetailsCategoryAbility, BuildVersion,
+    BuildVersionDiff, BuildVersionHistory,
+};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub(super) struct BuildIdQuery {
+    /// The ID of the build.
+    pub(super) build_id: u32,
+}
+
+fn category_mods<'a>(
+    hero_build: &'a BuildHero,
+    name: &str,
+) -> Option<&'a [BuildHeroDetailsCategoryAbility]> {
+    hero_build
+        .details
+        .mod_categories
+        .iter()
+        .find(|category| category.name == name)
+        .map(|category| category.mods.as_deref().unwrap_or_default())
+}
+
+fn category_diffs(previous: &BuildHero, current: &BuildHero) -> Vec<BuildCategoryDiff> {
+    let previous_names = previous.details.mod_categories.iter().map(|c| &c.name);
+    let current_names = current.details.mod_categories.iter().map(|c| &c.name);
+    let mut names: Vec<&String> = current_names.collect();
+    for name in previous_names {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let previous_mods = category_mods(previous, name).unwrap_or_default();
+            let current_mods = category_mods(current, name).unwrap_or_default();
+            let contains = |mods: &[BuildHeroDetailsCategoryAbility], id: u32| {
+                mods.iter().any(|m| m.ability_id == id)
+            };
+            let added_item_ids = current_mods
+                .iter()
+                .map(|m| m.ability_id)
+                .filter(|&id| !contains(previous_mods, id))
+                .collect::<Vec<_>>();
+            let removed_item_ids = previous_mods
+                .iter()
+                .map(|m| m.ability_id)
+                .filter(|&id| !contains(current_mods, id))
+                .collect::<Vec<_>>();
+            (!added_item_ids.is_empty() || !removed_item_ids.is_empty()).then(|| {
+                BuildCategoryDiff {
+                    name: name.clone(),
+                    added_item_ids,
+                    removed_item_ids,
+                }
+            })
+        })
+        .collect()
+}
+
+fn annotation_changes(previous: &BuildHero, current: &BuildHero) -> Vec<BuildAnnotationChange> {
+    current
+        .details
+        .mod_categories
+        .iter()
+        .flat_map(|category| {
+            let previous_mods = category_mods(previous, &category.name).unwrap_or_default();
+            category
+                .mods
+                .iter()
+                .flatten()
+                .filter_map(move |current_mod| {
+                    let previous_mod = previous_mods
+                        .iter()
+                        .find(|m| m.ability_id == current_mod.ability_id)?;
+                    (previous_mod.annotation != current_mod.annotation).then(|| {
+                        BuildAnnotationChange {
+                            category: category.name.clone(),
+                            item_id: current_mod.ability_id,
+                            previous_annotation: previous_mod.annotation.clone(),
+                            current_annotation: current_mod.annotation.clone(),
+                        }
+                    })
+                })
+        })
+        .collect()
+}
+
+fn currency_changes(hero_build: &BuildHero) -> &[BuildHeroDetailsAbilityOrderCurrencyChange] {
+    hero_build
+        .details
+        .ability_order
+        .as_ref()
+        .and_then(|order| order.currency_changes.as_deref())
+        .unwrap_or_default()
+}
+
+fn ability_order_change(
+    previous: &BuildHero,
+    current: &BuildHero,
+) -> Option<BuildAbilityOrderChange> {
+    let previous_steps = currency_changes(previous);
+    let current_steps = currency_changes(current);
+    let same_step = |a: &BuildHeroDetailsAbilityOrderCurrencyChange,
+                     b: &BuildHeroDetailsAbilityOrderCurrencyChange| {
+        a.ability_id == b.ability_id && a.currency_type == b.currency_type && a.delta == b.delta
+    };
+    let first_changed_step = previous_steps
+        .iter()
+        .zip(current_steps)
+        .position(|(a, b)| !same_step(a, b))
+        .or_else(|| {
+            (previous_steps.len() != current_steps.len())
+                .then(|| previous_steps.len().min(current_steps.len()))
+        })?;
+    Some(BuildAbilityOrderChange {
+        first_changed_step,
+        previous_steps: previous_steps[first_changed_step..].to_vec(),
+        current_steps: current_steps[first_changed_step..].to_vec(),
+    })
+}
+
+fn diff_builds(previous: &BuildHero, current: &BuildHero) -> BuildVersionDiff {
+    BuildVersionDiff {
+        previous_version: previous.version,
+        categories: category_diffs(previous, current),
+        annotation_changes: annotation_changes(previous, current),
+        ability_order: ability_order_change(previous, current),
+    }
+}
+
+fn version_history(builds: Vec<Build>) -> Vec<BuildVersion> {
+    let mut versions: Vec<BuildVersion> = Vec::with_capacity(builds.len());
+    for build in builds {
+        let diff = versions
+            .last()
+            .map(|previous| diff_builds(&previous.build.hero_build, &build.hero_build));
+        versions.push(BuildVersion { build, diff });
+    }
+    versions
+}
+
+async fn fetch_build_versions(
+    pg_client: &sqlx::Pool<sqlx::Postgres>,
+    build_id: u32,
+) -> sqlx::Result<Vec<Build>> {
+    let query = "SELECT data AS builds FROM hero_builds WHERE build_id = $1 ORDER BY version";
+    debug!(query, build_id);
+    Ok(sqlx::query(query)
+        .bind(build_id.cast_signed())
+        .fetch_all(pg_client)
+        .await?
+        .iter()
+        .map(|row| row.get::<sqlx::types::Json<_>, &str>("builds").0)
+        .collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/{build_id}/history",
+    params(BuildIdQuery),
+    responses(
+        (status = OK, body = BuildVersionHistory),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = NOT_FOUND, description = "Build not found."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+    ),
+    tags = ["Builds"],
+    summary = "Version History",
+    description = "
+Returns all versions of a build, oldest first. Every version but the first comes with a diff to the
+version before it: items added and removed per category, changed item annotations and the changed
+part of the ability order.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(super) async fn build_history(
+    Path(BuildIdQuery { build_id }): Path<BuildIdQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    let builds = fetch_build_versions(&state.pg_client, build_id).await?;
+    let hero_id = builds
+        .first()
+        .map(|build| build.hero_build.hero_id)
+        .ok_or_else(|| APIError::status_msg(StatusCode::NOT_FOUND, "Build not found."))?;
+    Ok(Json(BuildVersionHistory {
+        hero_build_id: build_id,
+        hero_id,
+        versions: version_history(builds),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hero_build(
+        version: u32,
+        categories: &[(&str, &[(u32, Option<&str>)])],
+        ability_order: &[u32],
+    ) -> BuildHero {
+        serde_json::from_value(serde_json::json!({
+            "hero_id": 10,
+            "hero_build_id": 1,
+            "author_account_id": 1,
+            "name": "Build",
+            "language": 0,
+            "version": version,
+            "origin_build_id": 0,
+            "details": {
+                "mod_categories": categories
+                    .iter()
+                    .map(|(name, mods)| serde_json::json!({
+                        "name": name,
+                        "mods": mods
+                            .iter()
+                            .map(|(id, annotation)| serde_json::json!({
+                                "ability_id": id,
+                                "annotation": annotation,
+                            }))
+                            .collect::<Vec<_>>(),
+                    }))
+                    .collect::<Vec<_>>(),
+                "ability_order": {
+                    "currency_changes": ability_order
+                        .iter()
+                        .map(|id| serde_json::json!({
+                            "ability_id": id,
+                            "currency_type": 2,
+                            "delta": -1,
+                        }))
+                        .collect::<Vec<_>>(),
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_diff_builds() {
+        let previous = hero_build(
+            1,
+            &[
+                ("Early", &[(1, None), (2, Some("rush"))]),
+                ("Late", &[(3, None)]),
+            ],
+            &[10, 11, 12],
+        );
+        let current = hero_build(
+            2,
+            &[
+                ("Early", &[(2, Some("buy first")), (4, None)]),
+                ("Mid", &[(5, None)]),
+            ],
+            &[10, 12, 11],
+        );
+        let diff = diff_builds(&previous, &current);
+        assert_eq!(diff.previous_version, 1);
+
+        let categories = diff
+            .categories
+            .iter()
+            .map(|c| {
+                (
+                    c.name.as_str(),
+                    c.added_item_ids.clone(),
+                    c.removed_item_ids.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            categories,
+            vec![
+                ("Early", vec![4], vec![1]),
+                ("Mid", vec![5], vec![]),
+                ("Late", vec![], vec![3]),
+            ]
+        );
+
+        assert_eq!(diff.annotation_changes.len(), 1);
+        assert_eq!(diff.annotation_changes[0].item_id, 2);
+        assert_eq!(
+            diff.annotation_changes[0].current_annotation.as_deref(),
+            Some("buy first")
+        );
+
+        let ability_order = diff.ability_order.unwrap();
+        assert_eq!(ability_order.first_changed_step, 1);
+        assert_eq!(ability_order.current_steps.len(), 2);
+        assert_eq!(ability_order.current_steps[0].ability_id, 12);
+    }
+
+    #[test]
+    fn test_diff_builds_unchanged() {
+        let build = hero_build(1, &[("Early", &[(1, None)])], &[10]);
+        let diff = diff_builds(&build, &build);
+        assert!(diff.categories.is_empty());
+        assert!(diff.annotation_changes.is_empty());
+        assert!(diff.ability_order.is_none());
+
+        let longer = hero_build(2, &[("Early", &[(1, None)])], &[10, 11]);
+        let ability_order = diff_builds(&build, &longer).ability_order.unwrap();
+        assert_eq!(ability_order.first_changed_step, 1);
+        assert!(ability_order.previous_steps.is_empty());
+    }
+}
@@ -1,6 +1,12 @@
+mod authors;
+mod changes;
+mod history;
+mod performance;
 pub mod query;
 mod route;
+mod similar;
 pub mod structs;
+mod tags;
 
 use core::time::Duration;
 
@@ -19,10 +25,21 @@ struct ApiDoc;
 
 pub(super) fn router() -> OpenApiRouter<AppState> {
     OpenApiRouter::with_openapi(ApiDoc::openapi())
-        .routes(routes!(route::search_builds))
-        .layer(
-            CacheControlMiddleware::new(Duration::from_secs(60 * 60))
-                .with_stale_while_revalidate(Duration::from_secs(60 * 60))
-                .with_stale_if_error(Duration::from_secs(60 * 60)),
+        .routes(routes!(changes::build_changes))
+        .merge(
+            OpenApiRouter::new()
+                .routes(routes!(route::search_builds))
+                .routes(routes!(route::search_builds_paged))
+                .routes(routes!(similar::similar_builds))
+                .routes(routes!(history::build_history))
+                .routes(routes!(performance::build_performance))
+                .routes(routes!(authors::build_authors))
+                .routes(routes!(authors::build_author))
+                .routes(routes!(tags::build_tags))
+                .layer(
+                    CacheControlMiddleware::new(Duration::from_secs(60 * 60))
+                        .with_stale_while_revalidate(Duration::from_secs(60 * 60))
+                        .with_stale_if_error(Duration::from_secs(60 * 60)),
+                ),
         )
 }
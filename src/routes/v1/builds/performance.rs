@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use cached::TimedCache;
+use cached::proc_macro::cached;
+use clickhouse::Row;
+use itertools::Itertools;
+use serde::Deserialize;
+use sqlx::Row as _;
+use tracing::debug;
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::build_creator::confidence::wilson_lower_bound;
+use crate::routes::v1::builds::history::BuildIdQuery;
+use crate::routes::v1::builds::query;
+use crate::routes::v1::builds::query::{BuildsCursor, BuildsSearchQuery};
+use crate::routes::v1::builds::similar::build_item_ids;
+use crate::routes::v1::builds::structs::{Build, BuildPerformance, BuildsSearchResponse};
+use crate::utils::parse::default_last_month_timestamp;
+use crate::utils::types::SortDirectionDesc;
+
+/// Overlap a player needs with a build to count as following it, if not given
+const DEFAULT_MIN_OVERLAP: f64 = 0.5;
+/// Upgrades a player needs to have bought for their items to be compared to the builds, so players
+/// leaving early do not follow every build they share a few items with
+const MIN_PLAYER_UPGRADES: u32 = 6;
+/// Number of most favorited builds ranked when sorting the builds search by performance
+const MAX_PERFORMANCE_CANDIDATES: u32 = 100;
+
+#[derive(Debug, Clone, Deserialize, IntoParams, Default)]
+pub(super) struct BuildPerformanceQuery {
+    /// Minimum share of the items of the build a player has to buy to count as following the
+    /// build: the number of shared items divided by the number of items of the build.
+    /// **Default:** 0.5
+    #[param(minimum = 0.0, maximum = 1.0, default = 0.5)]
+    min_overlap: Option<f64>,
+    /// Filter matches based on their start time (Unix timestamp). **Default:** 30 days ago.
+    #[serde(default = "default_last_month_timestamp")]
+    #[param(default = default_last_month_timestamp)]
+    min_unix_timestamp: Option<i64>,
+    /// Filter matches based on their start time (Unix timestamp).
+    max_unix_timestamp: Option<i64>,
+    /// Filter matches based on the average badge level. See more: <https://assets.deadlock-api.com/v2/ranks>
+    #[param(minimum = 0, maximum = 116)]
+    min_average_badge: Option<u8>,
+    /// Filter matches based on the average badge level.
+    #[param(minimum = 0, maximum = 116)]
+    max_average_badge: Option<u8>,
+}
+
+#[derive(Debug, Clone, Row, Deserialize)]
+struct BuildPerformanceRow {
+    build_id: u32,
+    /// Wins of players following the build
+    wins: u64,
+    /// Matches of players following the build
+    matches: u64,
+    /// Matches of the hero with at least [`MIN_PLAYER_UPGRADES`] upgrades, the same for every row
+    hero_matches: u64,
+}
+
+fn build_info_filters(query: &BuildPerformanceQuery) -> String {
+    let mut info_filters = Vec::new();
+    if let Some(min_unix_timestamp) = query.min_unix_timestamp {
+        info_filters.push(format!("start_time >= {min_unix_timestamp}"));
+    }
+    if let Some(max_unix_timestamp) = query.max_unix_timestamp {
+        info_filters.push(format!("start_time <= {max_unix_timestamp}"));
+    }
+    if let Some(min_badge_level) = query.min_average_badge
+        && min_badge_level > 11
+    {
+        info_filters.push(format!(
+            "average_badge_team0 >= {min_badge_level} AND average_badge_team1 >= {min_badge_level}"
+        ));
+    }
+    if let Some(max_badge_level) = query.max_average_badge
+        && max_badge_level < 116
+    {
+        info_filters.push(format!(
+            "average_badge_team0 <= {max_badge_level} AND average_badge_team1 <= {max_badge_level}"
+        ));
+    }
+    if info_filters.is_empty() {
+        String::new()
+    } else {
+        format!(" AND {}", info_filters.join(" AND "))
+    }
+}
+
+/// Counts the matches of players of the hero who bought enough of the items of each build.
+///
+/// The overlap is relative to the build, so players buying only a few of its items do not follow
+/// it. Players with fewer than [`MIN_PLAYER_UPGRADES`] upgrades are left out entirely.
+///
+/// `builds` are pairs of build ID and the sorted item IDs of the build.
+fn build_query(query: &BuildPerformanceQuery, hero_id: u32, builds: &[(u32, Vec<u32>)]) -> String {
+    let info_filters = build_info_filters(query);
+    let min_overlap = query.min_overlap.unwrap_or(DEFAULT_MIN_OVERLAP);
+    let builds = builds
+        .iter()
+        .map(|(build_id, item_ids)| format!("({build_id}, [{}])", item_ids.iter().join(", ")))
+        .join(", ");
+    format!(
+        "
+WITH
+    (SELECT groupArray(id) FROM items WHERE type = 'upgrade') AS upgrade_ids_array,
+    t_matches AS (
+        SELECT match_id
+        FROM match_info
+        WHERE match_mode IN ('Ranked', 'Unranked'){info_filters}
+    ),
+    t_players AS (
+        SELECT
+            won,
+            arrayDistinct(arrayFilter(x -> has(upgrade_ids_array, x), items.item_id)) AS item_ids
+        FROM match_player
+        WHERE match_id IN (SELECT match_id FROM t_matches)
+            AND hero_id = {hero_id}
+            AND length(item_ids) >= {MIN_PLAYER_UPGRADES}
+    ),
+    t_overlaps AS (
+        SELECT
+            tupleElement(build, 1) AS build_id,
+            won,
+            length(arrayIntersect(item_ids, tupleElement(build, 2)))
+                / length(tupleElement(build, 2)) AS overlap
+        FROM t_players
+            ARRAY JOIN [{builds}] AS build
+    )
+SELECT
+    toUInt32(build_id) AS build_id,
+    sumIf(won, overlap >= {min_overlap}) AS wins,
+    countIf(overlap >= {min_overlap}) AS matches,
+    count() AS hero_matches
+FROM t_overlaps
+GROUP BY build_id
+ORDER BY build_id
+        "
+    )
+}
+
+#[cached(
+    ty = "TimedCache<String, Vec<BuildPerformanceRow>>",
+    create = "{ TimedCache::with_lifespan(std::time::Duration::from_secs(60*60)) }",
+    result = true,
+    convert = "{ query_str.to_string() }",
+    sync_writes = "by_key",
+    key = "String"
+)]
+async fn run_query(
+    ch_client: &clickhouse::Client,
+    query_str: &str,
+) -> clickhouse::error::Result<Vec<BuildPerformanceRow>> {
+    ch_client.query(query_str).fetch_all().await
+}
+
+/// Fetches the performance rows of the builds, builds without items are left out.
+async fn fetch_performance_rows(
+    state: &AppState,
+    query: &BuildPerformanceQuery,
+    hero_id: u32,
+    builds: &[Build],
+) -> APIResult<Vec<BuildPerformanceRow>> {
+    let item_sets = builds
+        .iter()
+        .map(|build| {
+            let item_ids = build_item_ids(&build.hero_build).into_iter().sorted();
+            (build.hero_build.hero_build_id, item_ids.collect::<Vec<_>>())
+        })
+        .filter(|(_, item_ids)| !item_ids.is_empty())
+        .collect::<Vec<_>>();
+    if item_sets.is_empty() {
+        return Ok(Vec::new());
+    }
+    let query_str = build_query(query, hero_id, &item_sets);
+    debug!(?query_str);
+    Ok(run_query(&state.ch_client_ro, &query_str).await?)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn build_performance(
+    build: &Build,
+    row: Option<&BuildPerformanceRow>,
+    min_overlap: f64,
+) -> BuildPerformance {
+    let wins = row.map_or(0, |row| row.wins);
+    let matches = row.map_or(0, |row| row.matches);
+    let hero_matches = row.map_or(0, |row| row.hero_matches);
+    BuildPerformance {
+        hero_build_id: build.hero_build.hero_build_id,
+        hero_id: build.hero_build.hero_id,
+        version: build.hero_build.version,
+        min_overlap,
+        matches,
+        winrate: (matches > 0).then(|| wins as f64 / matches as f64),
+        wilson_lower_bound: wilson_lower_bound(wins, matches),
+        usage_rate: if hero_matches > 0 {
+            matches as f64 / hero_matches as f64
+        } else {
+            0.0
+        },
+        hero_matches,
+    }
+}
+
+/// Sorts builds by score with ties broken on build ID and version, and returns the page after
+/// the cursor position and whether there are more builds after it.
+fn page_ranked(
+    mut ranked: Vec<(f64, Build)>,
+    sort_direction: SortDirectionDesc,
+    after: Option<(f64, u32, u32)>,
+    start: usize,
+    page_size: Option<usize>,
+) -> (Vec<(f64, Build)>, bool) {
+    let key = |(score, build): &(f64, Build)| {
+        (
+            *score,
+            build.hero_build.hero_build_id,
+            build.hero_build.version,
+        )
+    };
+    let compare = |a: (f64, u32, u32), b: (f64, u32, u32)| {
+        let ordering = a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2));
+        match sort_direction {
+            SortDirectionDesc::Desc => ordering.reverse(),
+            SortDirectionDesc::Asc => ordering,
+        }
+    };
+    ranked.sort_by(|a, b| compare(key(a), key(b)));
+    let mut page = ranked
+        .into_iter()
+        .filter(|entry| after.is_none_or(|after| compare(key(entry), after).is_gt()))
+        .skip(start)
+        .collect::<Vec<_>>();
+    let has_more = page_size.is_some_and(|size| page.len() > size);
+    if let Some(size) = page_size {
+        page.truncate(size);
+    }
+    (page, has_more)
+}
+
+/// Builds search sorted by performance, see [`query::BuildsSearchQuerySortBy::Performance`].
+pub(super) async fn fetch_builds_by_performance(
+    state: &AppState,
+    params: &BuildsSearchQuery,
+    cursor: Option<&BuildsCursor>,
+) -> APIResult<BuildsSearchResponse> {
+    let hero_id = params
+        .hero_id()
+        .ok_or_else(|| APIError::bad_request("sort_by=performance requires a hero_id"))?;
+    let after = cursor
+        .map(|cursor| {
+            let (value, build_id, version) = cursor.position();
+            value
+                .parse::<f64>()
+                .map(|score| (score, build_id, version))
+                .map_err(|_| APIError::bad_request("Invalid cursor"))
+        })
+        .transpose()?;
+
+    let mut query_builder = query::candidates_query(params, MAX_PERFORMANCE_CANDIDATES);
+    debug!(query = query_builder.sql());
+    let builds: Vec<Build> = query_builder
+        .build()
+        .fetch_all(&state.pg_client)
+        .await?
+        .iter()
+        .map(|row| row.get::<sqlx::types::Json<_>, &str>("builds").0)
+        .collect();
+
+    let query = BuildPerformanceQuery {
+        min_unix_timestamp: default_last_month_timestamp(),
+        ..Default::default()
+    };
+    let scores: HashMap<u32, f64> = fetch_performance_rows(state, &query, hero_id, &builds)
+        .await?
+        .into_iter()
+        .map(|row| (row.build_id, wilson_lower_bound(row.wins, row.matches)))
+        .collect();
+    let ranked = builds
+        .into_iter()
+        .map(|build| {
+            let score = scores
+                .get(&build.hero_build.hero_build_id)
+                .copied()
+                .unwrap_or_default();
+            (score, build)
+        })
+        .collect();
+
+    let (page, has_more) = page_ranked(
+        ranked,
+        params.sort_direction(),
+        after,
+        params.start(),
+        params.page_size(),
+    );
+    let next_cursor = page.last().filter(|_| has_more).map(|(score, build)| {
+        BuildsCursor::new(
+            params,
+            score.to_string(),
+            build.hero_build.hero_build_id,
+            build.hero_build.version,
+        )
+        .encode()
+    });
+    Ok(BuildsSearchResponse {
+        builds: page.into_iter().map(|(_, build)| build).collect(),
+        // Only a ranked subset of the matching builds, so there is no total
+        total: None,
+        has_more,
+        next_cursor,
+        facets: None,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/{build_id}/performance",
+    params(BuildIdQuery, BuildPerformanceQuery),
+    responses(
+        (status = OK, body = BuildPerformance),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = NOT_FOUND, description = "Build not found."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+    ),
+    tags = ["Builds"],
+    summary = "Performance",
+    description = format!("
+Estimates how players following the latest version of a build perform in real matches.
+
+A player follows a build if they bought at least `min_overlap` of the items of the build, the number
+of shared items divided by the number of items of the build. Only matches of the hero of the build
+in which the player bought at least {MIN_PLAYER_UPGRADES} upgrades are considered.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    ")
+)]
+pub(super) async fn build_performance(
+    Path(BuildIdQuery { build_id }): Path<BuildIdQuery>,
+    Query(mut query): Query<BuildPerformanceQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    // Normalize timestamps to hour boundaries for better caching
+    query.min_unix_timestamp = query.min_unix_timestamp.map(|v| v - v % 3600);
+    query.max_unix_timestamp = query.max_unix_timestamp.map(|v| v + 3600 - v % 3600);
+    let min_overlap = query.min_overlap.unwrap_or(DEFAULT_MIN_OVERLAP);
+    if !(0.0..=1.0).contains(&min_overlap) {
+        return Err(APIError::bad_request("min_overlap must be between 0 and 1"));
+    }
+
+    let build: Build = sqlx::query(
+        "SELECT data AS builds FROM hero_builds WHERE build_id = $1 ORDER BY version DESC LIMIT 1",
+    )
+    .bind(build_id.cast_signed())
+    .fetch_optional(&state.pg_client)
+    .await?
+    .map(|row| row.get::<sqlx::types::Json<_>, &str>("builds").0)
+    .ok_or_else(|| APIError::status_msg(StatusCode::NOT_FOUND, "Build not found."))?;
+
+    let hero_id = build.hero_build.hero_id;
+    let rows =
+        fetch_performance_rows(&state, &query, hero_id, core::slice::from_ref(&build)).await?;
+    Ok(Json(build_performance(&build, rows.first(), min_overlap)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(build_id: u32) -> Build {
+        serde_json::from_value(serde_json::json!({
+            "hero_build": {
+                "hero_id": 10,
+                "hero_build_id": build_id,
+                "author_account_id": 1,
+                "name": "Build",
+                "language": 0,
+                "version": 1,
+                "origin_build_id": 0,
+                "details": {"mod_categories": []},
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_query() {
+        let query = BuildPerformanceQuery {
+            min_unix_timestamp: Some(1_000),
+            ..Default::default()
+        };
+        let sql = build_query(&query, 7, &[(1, vec![10, 11]), (2, vec![12])]);
+        assert!(sql.contains("start_time >= 1000"));
+        assert!(sql.contains("AND hero_id = 7"));
+        assert!(sql.contains("ARRAY JOIN [(1, [10, 11]), (2, [12])] AS build"));
+        assert!(sql.contains("AND length(item_ids) >= 6"));
+        assert!(sql.contains("/ length(tupleElement(build, 2)) AS overlap"));
+        assert!(sql.contains("countIf(overlap >= 0.5) AS matches"));
+    }
+
+    #[test]
+    fn test_build_performance() {
+        let row = BuildPerformanceRow {
+            build_id: 1,
+            wins: 30,
+            matches: 50,
+            hero_matches: 200,
+        };
+        let performance = build_performance(&build(1), Some(&row), 0.7);
+        assert!(
+            performance
+                .winrate
+                .is_some_and(|w| (w - 0.6).abs() < f64::EPSILON)
+        );
+        assert!((performance.usage_rate - 0.25).abs() < f64::EPSILON);
+        assert!(performance.wilson_lower_bound < 0.6);
+
+        let performance = build_performance(&build(1), None, 0.7);
+        assert_eq!(performance.matches, 0);
+        assert!(performance.winrate.is_none());
+    }
+
+    #[test]
+    fn test_page_ranked() {
+        let ranked = vec![
+            (0.5, build(1)),
+            (0.6, build(2)),
+            (0.5, build(3)),
+            (0.4, build(4)),
+        ];
+        let ids = |page: &[(f64, Build)]| {
+            page.iter()
+                .map(|(_, build)| build.hero_build.hero_build_id)
+                .collect::<Vec<_>>()
+        };
+
+        let (page, has_more) =
+            page_ranked(ranked.clone(), SortDirectionDesc::Desc, None, 0, Some(2));
+        assert_eq!(ids(&page), vec![2, 3]);
+        assert!(has_more);
+
+        let (page, has_more) = page_ranked(
+            ranked.clone(),
+            SortDirectionDesc::Desc,
+            Some((0.5, 3, 1)),
+            0,
+            Some(2),
+        );
+        assert_eq!(ids(&page), vec![1, 4]);
+        assert!(!has_more);
+
+        let (page, _) = page_ranked(ranked, SortDirectionDesc::Asc, None, 1, None);
+        assert_eq!(ids(&page), vec![1, 3, 2]);
+    }
+}
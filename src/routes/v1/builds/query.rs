@@ -1,9 +1,14 @@
-use serde::Deserialize;
-use sqlx::{Execute, QueryBuilder};
-use strum::Display;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sqlx::QueryBuilder;
+use sqlx::types::Json;
+use strum::{Display, EnumIter, IntoEnumIterator};
 use utoipa::{IntoParams, ToSchema};
 
-use crate::utils::parse::parse_steam_id_option;
+use crate::error::{APIError, APIResult};
+use crate::utils::parse::{comma_separated_deserialize_option, parse_steam_id_option};
 use crate::utils::types::SortDirectionDesc;
 
 #[allow(clippy::unnecessary_wraps)]
@@ -11,6 +16,10 @@ fn default_limit() -> Option<u32> {
     100.into()
 }
 
+fn default_exclude_item_mode() -> BuildItemMatchMode {
+    BuildItemMatchMode::Any
+}
+
 #[derive(Debug, Clone, Copy, Deserialize, ToSchema, Default, Display)]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
@@ -30,9 +39,58 @@ pub enum BuildsSearchQuerySortBy {
     PublishedAt,
     /// Sort by the build version.
     Version,
+    /// Sort by the relevance to the `search` string. Requires `search`.
+    Relevance,
+    /// Sort by the lower bound of the winrate of players following the build over the last 30
+    /// days. Requires `hero_id`, only the latest version of the 100 most favorited builds matching
+    /// the filters is ranked.
+    Performance,
+}
+
+impl BuildsSearchQuerySortBy {
+    /// Expression the builds are sorted by. Never null, so it can be compared to a cursor.
+    fn sort_expr(self) -> &'static str {
+        match self {
+            Self::WeeklyFavorites => "weekly_favorites",
+            Self::Favorites => "favorites",
+            Self::Ignores => "ignores",
+            Self::Reports => "reports",
+            Self::UpdatedAt => "coalesce(updated_at, '-infinity')",
+            Self::PublishedAt => "coalesce(published_at, '-infinity')",
+            Self::Version => "version",
+            Self::Relevance => "relevance",
+            // Performance is ranked outside of Postgres, candidates are picked by favorites
+            Self::Performance => "favorites",
+        }
+    }
+
+    /// Postgres type of [`Self::sort_expr`].
+    fn sort_type(self) -> &'static str {
+        match self {
+            Self::UpdatedAt | Self::PublishedAt => "timestamp",
+            Self::Relevance => "real",
+            Self::Performance => "double precision",
+            Self::WeeklyFavorites
+            | Self::Favorites
+            | Self::Ignores
+            | Self::Reports
+            | Self::Version => "integer",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, ToSchema, Default, Display, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BuildItemMatchMode {
+    /// Match builds containing any of the items.
+    Any,
+    /// Match builds containing all of the items.
+    #[default]
+    All,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema, Default, Display, PartialEq, Eq, EnumIter)]
 pub enum BuildLanguage {
     #[default]
     English = 0,
@@ -55,6 +113,30 @@ pub enum BuildLanguage {
     Vietnamese = 27,
 }
 
+impl BuildLanguage {
+    /// Postgres text search configuration used to stem builds in this language.
+    fn text_search_config(self) -> &'static str {
+        match self {
+            Self::English => "english",
+            Self::German => "german",
+            Self::French => "french",
+            Self::Italian => "italian",
+            Self::SpanishSpain | Self::SpanishLatinAmerica => "spanish",
+            Self::Russian => "russian",
+            Self::PortuguesePortugal | Self::PortugueseBrazil => "portuguese",
+            Self::Turkish => "turkish",
+            Self::Korean
+            | Self::ChineseSimplified
+            | Self::Thai
+            | Self::Japanese
+            | Self::Polish
+            | Self::Czech
+            | Self::Ukrainian
+            | Self::Vietnamese => "simple",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, IntoParams)]
 #[into_params(style = Form, parameter_in = Query)]
 #[serde(rename_all = "snake_case")]
@@ -71,8 +153,11 @@ pub(super) struct BuildsSearchQuery {
     #[serde(default)]
     #[param(inline)]
     sort_by: BuildsSearchQuerySortBy,
-    /// The index of the first build to return.
+    /// The index of the first build to return. Prefer `cursor` for paging through many builds.
     start: Option<u32>,
+    /// Cursor of the page to return, the `next_cursor` of the previous page. Must be used with the
+    /// same filters and sort as the previous page.
+    cursor: Option<String>,
     /// The maximum number of builds to return.
     #[serde(default = "default_limit")]
     #[param(inline, default = "100")]
@@ -81,12 +166,18 @@ pub(super) struct BuildsSearchQuery {
     #[serde(default)]
     #[param(inline)]
     sort_direction: SortDirectionDesc,
+    /// Full-text search on the name and description of the builds, stemmed by the language of
+    /// each build. Supports quoted phrases, `or` and `-` to exclude words.
+    search: Option<String>,
     /// Search for builds with a name containing this string.
     search_name: Option<String>,
     /// Search for builds with a description containing this string.
     search_description: Option<String>,
-    /// Only return the latest version of each build.
+    /// Only return the latest version of each build. Always the case with `sort_by=performance`.
     only_latest: Option<bool>,
+    /// Also return the number of matching builds per tag, language and rollup category, across
+    /// all pages. Only used by the first page of the paged search.
+    facets: Option<bool>,
     /// Filter builds by language.
     #[deprecated]
     language: Option<u32>,
@@ -107,18 +198,38 @@ pub(super) struct BuildsSearchQuery {
     /// The author's `SteamID3`
     #[serde(default, deserialize_with = "parse_steam_id_option")]
     author_id: Option<u32>,
+    /// Comma separated list of item ids the builds must contain. See more: <https://assets.deadlock-api.com/v2/items>
+    #[param(value_type = Option<String>)]
+    #[serde(default, deserialize_with = "comma_separated_deserialize_option")]
+    include_item_ids: Option<Vec<u32>>,
+    /// Whether the builds must contain any or all of `include_item_ids`.
+    #[serde(default)]
+    #[param(inline)]
+    include_item_mode: BuildItemMatchMode,
+    /// Comma separated list of item ids the builds must not contain. See more: <https://assets.deadlock-api.com/v2/items>
+    #[param(value_type = Option<String>)]
+    #[serde(default, deserialize_with = "comma_separated_deserialize_option")]
+    exclude_item_ids: Option<Vec<u32>>,
+    /// Whether builds containing any or only those containing all of `exclude_item_ids` are
+    /// excluded.
+    #[serde(default = "default_exclude_item_mode")]
+    #[param(inline, default = "any")]
+    exclude_item_mode: BuildItemMatchMode,
 }
 
 impl Default for BuildsSearchQuery {
     fn default() -> Self {
         Self {
             start: None,
+            cursor: None,
             limit: Some(100),
             sort_by: BuildsSearchQuerySortBy::Favorites,
             sort_direction: SortDirectionDesc::Desc,
+            search: None,
             search_name: None,
             search_description: None,
             only_latest: None,
+            facets: None,
             #[allow(deprecated)]
             language: None,
             build_language: None,
@@ -132,106 +243,466 @@ impl Default for BuildsSearchQuery {
             min_published_unix_timestamp: None,
             max_published_unix_timestamp: None,
             tag: None,
+            include_item_ids: None,
+            include_item_mode: BuildItemMatchMode::All,
+            exclude_item_ids: None,
+            exclude_item_mode: BuildItemMatchMode::Any,
         }
     }
 }
 
-pub(super) fn sql_query(params: &BuildsSearchQuery) -> String {
-    let mut query_builder: QueryBuilder<sqlx::Postgres> = QueryBuilder::default();
+impl BuildsSearchQuery {
+    pub(super) fn validate(&self) -> APIResult<()> {
+        if matches!(self.sort_by, BuildsSearchQuerySortBy::Relevance) && self.search.is_none() {
+            return Err(APIError::bad_request(
+                "sort_by=relevance requires a search string",
+            ));
+        }
+        if matches!(self.sort_by, BuildsSearchQuerySortBy::Performance) && self.hero_id.is_none() {
+            return Err(APIError::bad_request(
+                "sort_by=performance requires a hero_id",
+            ));
+        }
+        Ok(())
+    }
+
+    pub(super) fn facets(&self) -> bool {
+        self.facets.unwrap_or_default()
+    }
+
+    pub(super) fn hero_id(&self) -> Option<u32> {
+        self.hero_id
+    }
+
+    pub(super) fn sort_by(&self) -> BuildsSearchQuerySortBy {
+        self.sort_by
+    }
+
+    pub(super) fn sort_direction(&self) -> SortDirectionDesc {
+        self.sort_direction
+    }
+
+    /// Number of builds skipped before the page.
+    pub(super) fn start(&self) -> usize {
+        self.start.unwrap_or_default() as usize
+    }
+
+    /// Maximum number of builds of a page.
+    pub(super) fn page_size(&self) -> Option<usize> {
+        self.limit.map(|limit| limit as usize)
+    }
+
+    /// Sort and direction a cursor is valid for, e.g. `favorites:desc`.
+    fn sort_key(&self) -> String {
+        format!("{}:{}", self.sort_by, self.sort_direction)
+    }
+
+    /// Decodes the cursor of the query and checks it was created for the same sort.
+    pub(super) fn cursor(&self) -> APIResult<Option<BuildsCursor>> {
+        let Some(cursor) = &self.cursor else {
+            return Ok(None);
+        };
+        if self.start.is_some() {
+            return Err(APIError::bad_request(
+                "start and cursor cannot be used together",
+            ));
+        }
+        let cursor =
+            BuildsCursor::decode(cursor).ok_or_else(|| APIError::bad_request("Invalid cursor"))?;
+        if cursor.sort != self.sort_key() {
+            return Err(APIError::bad_request(
+                "cursor was created for a different sort_by or sort_direction",
+            ));
+        }
+        Ok(Some(cursor))
+    }
+}
+
+/// Position after the last build of a page, in the order of the search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct BuildsCursor {
+    sort: String,
+    /// Sort value of the last build, as Postgres text
+    value: String,
+    build_id: u32,
+    version: u32,
+}
+
+impl BuildsCursor {
+    pub(super) fn new(
+        params: &BuildsSearchQuery,
+        value: String,
+        build_id: u32,
+        version: u32,
+    ) -> Self {
+        Self {
+            sort: params.sort_key(),
+            value,
+            build_id,
+            version,
+        }
+    }
+
+    /// Sort value, build ID and version of the last build of the previous page.
+    pub(super) fn position(&self) -> (&str, u32, u32) {
+        (&self.value, self.build_id, self.version)
+    }
+
+    pub(super) fn encode(&self) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let json = BASE64_URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+/// Condition matching builds that contain any or all of the items in one of their categories.
+fn push_item_filter(
+    query_builder: &mut QueryBuilder<'static, sqlx::Postgres>,
+    item_ids: &[u32],
+    mode: BuildItemMatchMode,
+) {
+    let categories = |ids: &[u32]| {
+        Json(
+            ids.iter()
+                .map(|id| serde_json::json!({"mods": [{"ability_id": id}]}))
+                .collect::<Vec<_>>(),
+        )
+    };
+    match mode {
+        BuildItemMatchMode::All => {
+            query_builder.push("data->'hero_build'->'details'->'mod_categories' @> ");
+            query_builder.push_bind(categories(item_ids));
+        }
+        BuildItemMatchMode::Any => {
+            query_builder.push("(");
+            for (i, id) in item_ids.iter().enumerate() {
+                if i > 0 {
+                    query_builder.push(" OR ");
+                }
+                query_builder.push("data->'hero_build'->'details'->'mod_categories' @> ");
+                query_builder.push_bind(categories(core::slice::from_ref(id)));
+            }
+            query_builder.push(")");
+        }
+    }
+}
+
+/// Escapes the `LIKE` wildcards of a search string and matches it anywhere in the text.
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .to_lowercase()
+        .replace('\\', r"\\")
+        .replace('%', r"\%")
+        .replace('_', r"\_");
+    format!("%{escaped}%")
+}
+
+/// Text search configuration of a build, picked by its language.
+///
+/// The configurations are constants, so the expression is immutable and can be indexed.
+fn text_search_config_expr() -> String {
+    let cases = BuildLanguage::iter()
+        .map(|language| {
+            format!(
+                " WHEN {} THEN '{}'::regconfig",
+                language as u32,
+                language.text_search_config()
+            )
+        })
+        .collect::<String>();
+    format!("(CASE language{cases} ELSE 'simple'::regconfig END)")
+}
+
+/// Text search document of a build, with the name weighted above the description.
+///
+/// Served by the `hero_builds_text_search_index` GIN expression index, which has to be created
+/// with exactly this expression, see `tests/data/postgres/builds.sql`.
+fn text_search_document() -> String {
+    let config = text_search_config_expr();
+    format!(
+        "(setweight(to_tsvector({config}, coalesce(data->'hero_build'->>'name', '')), 'A') || \
+         setweight(to_tsvector({config}, coalesce(data->'hero_build'->>'description', '')), 'B'))"
+    )
+}
+
+/// Pushes the search query stemmed by the language of each build.
+fn push_text_search_query(query_builder: &mut QueryBuilder<'static, sqlx::Postgres>, search: &str) {
+    query_builder.push(format!(
+        "websearch_to_tsquery({}, ",
+        text_search_config_expr()
+    ));
+    query_builder.push_bind(search.to_owned());
+    query_builder.push(")");
+}
+
+/// Pushes the search query stemmed by every text search configuration combined.
+///
+/// It does not depend on the build, so unlike [`push_text_search_query`] it can be matched against
+/// the text search index. It matches a superset of the builds, which are then checked against the
+/// query in their own language.
+fn push_text_search_prefilter(
+    query_builder: &mut QueryBuilder<'static, sqlx::Postgres>,
+    search: &str,
+) {
+    let configs = BuildLanguage::iter()
+        .map(BuildLanguage::text_search_config)
+        .chain(["simple"])
+        .unique();
+    query_builder.push("(");
+    for (i, config) in configs.enumerate() {
+        if i > 0 {
+            query_builder.push(" || ");
+        }
+        query_builder.push(format!("websearch_to_tsquery('{config}'::regconfig, "));
+        query_builder.push_bind(search.to_owned());
+        query_builder.push(")");
+    }
+    query_builder.push(")");
+}
+
+/// Pushes the CTE of the builds matching the filters of the query.
+///
+/// The CTE is left open, so callers can add conditions to its `WHERE` clause. With `numbered`, the
+/// versions of each build are numbered as `rn`, the latest being 1.
+fn push_filtered_builds(
+    query_builder: &mut QueryBuilder<'static, sqlx::Postgres>,
+    params: &BuildsSearchQuery,
+    numbered: bool,
+) {
+    let search = params
+        .search
+        .as_deref()
+        .map(|search| urlencoding::decode(search).unwrap_or(search.into()));
     query_builder.push(
-        " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-         reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-         version DESC) as rn FROM hero_builds WHERE TRUE",
+        " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, ignores, \
+         reports, updated_at, published_at, version",
     );
+    if numbered {
+        query_builder
+            .push(", ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY version DESC) as rn");
+    }
+    if let Some(search) = &search {
+        // The document is computed once per build in the subquery, the index condition on the
+        // prefilter is answered by the index, OFFSET 0 keeps the subquery from being inlined
+        query_builder.push(", ts_rank(document, ts_query) as relevance FROM (SELECT *, ");
+        query_builder.push(text_search_document());
+        query_builder.push(" AS document, ");
+        push_text_search_query(query_builder, search);
+        query_builder.push(" AS ts_query FROM hero_builds WHERE ");
+        query_builder.push(text_search_document());
+        query_builder.push(" @@ ");
+        push_text_search_prefilter(query_builder, search);
+    } else {
+        query_builder.push(" FROM hero_builds WHERE TRUE");
+    }
     if let Some(tag) = params.tag {
-        query_builder.push(" AND data->'hero_build'->'tags' @> '");
-        query_builder.push(tag.to_string());
-        query_builder.push("'");
+        query_builder.push(" AND data->'hero_build'->'tags' @> ");
+        query_builder.push_bind(Json(tag));
     }
     if let Some(search_name) = &params.search_name {
         let search_name = urlencoding::decode(search_name).unwrap_or(search_name.into());
-        query_builder.push(" AND lower(data->'hero_build'->>'name') LIKE '%");
-        query_builder.push(search_name.to_lowercase());
-        query_builder.push("%'");
+        query_builder.push(" AND lower(data->'hero_build'->>'name') LIKE ");
+        query_builder.push_bind(like_pattern(&search_name));
     }
     if let Some(search_description) = &params.search_description {
         let search_description =
             urlencoding::decode(search_description).unwrap_or(search_description.into());
-        query_builder.push(" AND lower(data->'hero_build'->>'description') LIKE '%");
-        query_builder.push(search_description.to_lowercase());
-        query_builder.push("%'");
+        query_builder.push(" AND lower(data->'hero_build'->>'description') LIKE ");
+        query_builder.push_bind(like_pattern(&search_description));
     }
     #[allow(deprecated)]
     if let Some(language) = params.language {
         query_builder.push(" AND language = ");
-        query_builder.push(language.to_string());
+        query_builder.push_bind(language.cast_signed());
     }
     if let Some(build_language) = params.build_language {
         query_builder.push(" AND language = ");
-        query_builder.push(build_language as u32);
+        query_builder.push_bind(build_language as i32);
     }
     if let Some(build_id) = params.build_id {
         query_builder.push(" AND build_id = ");
-        query_builder.push(build_id.to_string());
+        query_builder.push_bind(build_id.cast_signed());
     }
     if let Some(version) = params.version {
         query_builder.push(" AND version = ");
-        query_builder.push(version.to_string());
+        query_builder.push_bind(version.cast_signed());
     }
     if let Some(hero_id) = params.hero_id {
         query_builder.push(" AND hero = ");
-        query_builder.push(hero_id.to_string());
+        query_builder.push_bind(hero_id.cast_signed());
     }
     if let Some(author_id) = params.author_id {
         query_builder.push(" AND author_id = ");
-        query_builder.push(author_id.to_string());
+        query_builder.push_bind(author_id.cast_signed());
     }
     if let Some(rollup_category) = params.rollup_category {
         query_builder.push(" AND rollup_category = ");
-        query_builder.push(rollup_category.to_string());
+        query_builder.push_bind(rollup_category.cast_signed());
     }
     if let Some(min_unix_timestamp) = params.min_unix_timestamp {
-        query_builder.push(" AND updated_at >= ");
-        query_builder.push(format!("to_timestamp({min_unix_timestamp})"));
+        query_builder.push(" AND updated_at >= to_timestamp(");
+        query_builder.push_bind(min_unix_timestamp);
+        query_builder.push(")");
     }
     if let Some(max_unix_timestamp) = params.max_unix_timestamp {
-        query_builder.push(" AND updated_at <= ");
-        query_builder.push(format!("to_timestamp({max_unix_timestamp})"));
+        query_builder.push(" AND updated_at <= to_timestamp(");
+        query_builder.push_bind(max_unix_timestamp);
+        query_builder.push(")");
     }
     if let Some(min_published_unix_timestamp) = params.min_published_unix_timestamp {
-        query_builder.push(" AND published_at >= ");
-        query_builder.push(format!("to_timestamp({min_published_unix_timestamp})"));
+        query_builder.push(" AND published_at >= to_timestamp(");
+        query_builder.push_bind(min_published_unix_timestamp);
+        query_builder.push(")");
     }
     if let Some(max_published_unix_timestamp) = params.max_published_unix_timestamp {
-        query_builder.push(" AND published_at <= ");
-        query_builder.push(format!("to_timestamp({max_published_unix_timestamp})"));
+        query_builder.push(" AND published_at <= to_timestamp(");
+        query_builder.push_bind(max_published_unix_timestamp);
+        query_builder.push(")");
     }
-    if params.only_latest.unwrap_or_default() {
-        query_builder.push(" ) SELECT builds FROM hero_builds WHERE rn = 1");
-    } else {
-        query_builder.push(" ) SELECT builds FROM hero_builds");
+    if let Some(include_item_ids) = params.include_item_ids.as_deref()
+        && !include_item_ids.is_empty()
+    {
+        query_builder.push(" AND ");
+        push_item_filter(query_builder, include_item_ids, params.include_item_mode);
     }
-    query_builder.push(" ORDER BY ");
-    query_builder.push(params.sort_by.to_string().to_lowercase());
-    query_builder.push(" ");
-    query_builder.push(params.sort_direction.to_string().to_lowercase());
-    query_builder.push(" NULLS ");
+    if let Some(exclude_item_ids) = params.exclude_item_ids.as_deref()
+        && !exclude_item_ids.is_empty()
+    {
+        query_builder.push(" AND NOT ");
+        push_item_filter(query_builder, exclude_item_ids, params.exclude_item_mode);
+    }
+    if search.is_some() {
+        query_builder.push(" OFFSET 0) hero_builds WHERE document @@ ts_query");
+    }
+}
+
+/// Condition matching the builds after the cursor, in the order of the search.
+fn push_cursor_condition(
+    query_builder: &mut QueryBuilder<'static, sqlx::Postgres>,
+    params: &BuildsSearchQuery,
+    sort_expr: &str,
+    cursor: &BuildsCursor,
+) {
+    query_builder.push(format!("({sort_expr}, build_id, version) "));
     query_builder.push(if params.sort_direction == SortDirectionDesc::Desc {
-        "LAST"
+        "<"
     } else {
-        "FIRST"
+        ">"
     });
+    query_builder.push(" (CAST(");
+    query_builder.push_bind(cursor.value.clone());
+    query_builder.push(format!(" AS {}), ", params.sort_by.sort_type()));
+    query_builder.push_bind(cursor.build_id.cast_signed());
+    query_builder.push(", ");
+    query_builder.push_bind(cursor.version.cast_signed());
+    query_builder.push(")");
+}
+
+/// Query of a page of builds, starting after the cursor if given.
+///
+/// Builds are ordered by the sort of the query with ties broken on `build_id` and `version`, so
+/// the order is stable across pages. One build more than the limit is fetched to tell whether
+/// there are more pages.
+///
+/// Unless only the latest versions are returned, the cursor is applied to the filtered builds
+/// directly, so the builds before it are never numbered or sorted.
+pub(super) fn sql_query(
+    params: &BuildsSearchQuery,
+    cursor: Option<&BuildsCursor>,
+) -> QueryBuilder<'static, sqlx::Postgres> {
+    let mut query_builder: QueryBuilder<sqlx::Postgres> = QueryBuilder::default();
+    let only_latest = params.only_latest.unwrap_or_default();
+    push_filtered_builds(&mut query_builder, params, only_latest);
+    let sort_expr = params.sort_by.sort_expr();
+    let direction = params.sort_direction.to_string().to_lowercase();
+    if !only_latest && let Some(cursor) = cursor {
+        // The relevance is only selected by the CTE, in its WHERE clause it is computed again
+        let filter_sort_expr = match params.sort_by {
+            BuildsSearchQuerySortBy::Relevance => "ts_rank(document, ts_query)",
+            _ => sort_expr,
+        };
+        query_builder.push(" AND ");
+        push_cursor_condition(&mut query_builder, params, filter_sort_expr, cursor);
+    }
+    query_builder.push(format!(
+        " ) SELECT builds, build_id, version, {sort_expr}::text AS sort_value FROM hero_builds"
+    ));
+    if only_latest {
+        query_builder.push(" WHERE rn = 1");
+        if let Some(cursor) = cursor {
+            query_builder.push(" AND ");
+            push_cursor_condition(&mut query_builder, params, sort_expr, cursor);
+        }
+    }
+    query_builder.push(format!(
+        " ORDER BY {sort_expr} {direction}, build_id {direction}, version {direction}"
+    ));
 
     if let Some(limit) = params.limit {
         query_builder.push(" LIMIT ");
-        query_builder.push(limit.to_string());
+        query_builder.push_bind(i64::from(limit) + 1);
     }
     if let Some(start) = params.start {
         query_builder.push(" OFFSET ");
-        query_builder.push(start.to_string());
+        query_builder.push_bind(i64::from(start));
+    }
+    query_builder
+}
+
+/// Query of the latest version of the most favorited builds matching the filters of the query,
+/// to be ranked by performance.
+pub(super) fn candidates_query(
+    params: &BuildsSearchQuery,
+    max_candidates: u32,
+) -> QueryBuilder<'static, sqlx::Postgres> {
+    let mut query_builder: QueryBuilder<sqlx::Postgres> = QueryBuilder::default();
+    push_filtered_builds(&mut query_builder, params, true);
+    query_builder.push(
+        " ) SELECT builds FROM hero_builds WHERE rn = 1 ORDER BY favorites desc, build_id desc LIMIT ",
+    );
+    query_builder.push_bind(i64::from(max_candidates));
+    query_builder
+}
+
+/// Query of the number of builds matching the filters of the query across all pages.
+pub(super) fn count_query(params: &BuildsSearchQuery) -> QueryBuilder<'static, sqlx::Postgres> {
+    let only_latest = params.only_latest.unwrap_or_default();
+    let mut query_builder: QueryBuilder<sqlx::Postgres> = QueryBuilder::default();
+    push_filtered_builds(&mut query_builder, params, only_latest);
+    query_builder.push(" ) SELECT count(*) AS total FROM hero_builds");
+    if only_latest {
+        query_builder.push(" WHERE rn = 1");
     }
-    query_builder.build().sql().into()
+    query_builder
+}
+
+/// Query of the number of builds matching the filters of the query per tag, language and rollup
+/// category, as `facet`, `value` and `num_builds` rows.
+///
+/// The number of all matching builds is returned as the `total` facet, so the matching builds are
+/// only collected once instead of again by the [`count_query`].
+pub(super) fn facets_query(params: &BuildsSearchQuery) -> QueryBuilder<'static, sqlx::Postgres> {
+    let only_latest = params.only_latest.unwrap_or_default();
+    let mut query_builder: QueryBuilder<sqlx::Postgres> = QueryBuilder::default();
+    push_filtered_builds(&mut query_builder, params, only_latest);
+    query_builder.push(" ), matched AS (SELECT builds FROM hero_builds");
+    if only_latest {
+        query_builder.push(" WHERE rn = 1");
+    }
+    query_builder.push(
+        ") SELECT 'tag' AS facet, tag::bigint AS value, count(*) AS num_builds FROM matched, \
+         jsonb_path_query(builds, '$.hero_build.tags[*]') AS tag GROUP BY tag UNION ALL SELECT \
+         'language', (builds->'hero_build'->>'language')::bigint, count(*) FROM matched GROUP BY 2 \
+         UNION ALL SELECT 'rollup_category', (builds->>'rollup_category')::bigint, count(*) FROM \
+         matched WHERE builds->>'rollup_category' IS NOT NULL GROUP BY 2 UNION ALL SELECT 'total', \
+         NULL, count(*) FROM matched ORDER BY num_builds DESC, value",
+    );
+    query_builder
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -247,6 +718,7 @@ mod tests {
         assert_eq!(query.search_name, None);
         assert_eq!(query.search_description, None);
         assert_eq!(query.only_latest, None);
+        assert_eq!(query.facets, None);
         assert_eq!(query.build_language, None);
         assert_eq!(query.build_id, None);
         assert_eq!(query.version, None);
@@ -254,13 +726,14 @@ mod tests {
         assert_eq!(query.author_id, None);
         assert_eq!(query.tag, None);
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, favorites::text AS sort_value FROM hero_builds \
+             ORDER BY favorites desc, build_id desc, version desc LIMIT $1"
         );
     }
 
@@ -271,14 +744,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND \
-             lower(data->'hero_build'->>'name') LIKE '%tank build%' ) SELECT builds FROM \
-             hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             lower(data->'hero_build'->>'name') LIKE $1 ) SELECT builds, build_id, version, \
+             favorites::text AS sort_value FROM hero_builds ORDER BY favorites desc, build_id \
+             desc, version desc LIMIT $2"
         );
     }
 
@@ -289,13 +763,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND data->'hero_build'->'tags' @> \
-             '1' ) SELECT builds FROM hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             data->'hero_build'->'tags' @> $1 ) SELECT builds, build_id, version, favorites::text \
+             AS sort_value FROM hero_builds ORDER BY favorites desc, build_id desc, version desc \
+             LIMIT $2"
         );
     }
 
@@ -306,14 +782,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND \
-             lower(data->'hero_build'->>'name') LIKE '%tank build%' ) SELECT builds FROM \
-             hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             lower(data->'hero_build'->>'name') LIKE $1 ) SELECT builds, build_id, version, \
+             favorites::text AS sort_value FROM hero_builds ORDER BY favorites desc, build_id \
+             desc, version desc LIMIT $2"
         );
     }
 
@@ -324,14 +801,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND \
-             lower(data->'hero_build'->>'description') LIKE '%strength items%' ) SELECT builds \
-             FROM hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             lower(data->'hero_build'->>'description') LIKE $1 ) SELECT builds, build_id, version, \
+             favorites::text AS sort_value FROM hero_builds ORDER BY favorites desc, build_id \
+             desc, version desc LIMIT $2"
         );
     }
 
@@ -342,13 +820,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND language = 1 ) SELECT builds \
-             FROM hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             language = $1 ) SELECT builds, build_id, version, favorites::text AS sort_value FROM \
+             hero_builds ORDER BY favorites desc, build_id desc, version desc LIMIT $2"
         );
     }
 
@@ -359,13 +838,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND build_id = 12345 ) SELECT builds \
-             FROM hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             build_id = $1 ) SELECT builds, build_id, version, favorites::text AS sort_value FROM \
+             hero_builds ORDER BY favorites desc, build_id desc, version desc LIMIT $2"
         );
     }
 
@@ -376,13 +856,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND version = 2 ) SELECT builds FROM \
-             hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             version = $1 ) SELECT builds, build_id, version, favorites::text AS sort_value FROM \
+             hero_builds ORDER BY favorites desc, build_id desc, version desc LIMIT $2"
         );
     }
 
@@ -393,13 +874,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND hero = 23 ) SELECT builds FROM \
-             hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             hero = $1 ) SELECT builds, build_id, version, favorites::text AS sort_value FROM \
+             hero_builds ORDER BY favorites desc, build_id desc, version desc LIMIT $2"
         );
     }
 
@@ -410,13 +892,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND author_id = 74963221 ) SELECT \
-             builds FROM hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             author_id = $1 ) SELECT builds, build_id, version, favorites::text AS sort_value FROM \
+             hero_builds ORDER BY favorites desc, build_id desc, version desc LIMIT $2"
         );
     }
 
@@ -427,13 +910,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, favorites::text AS sort_value FROM hero_builds \
+             ORDER BY favorites desc, build_id desc, version desc LIMIT $1"
         );
     }
 
@@ -444,13 +928,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY ignores desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, ignores::text AS sort_value FROM hero_builds ORDER \
+             BY ignores desc, build_id desc, version desc LIMIT $1"
         );
     }
 
@@ -461,13 +946,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY reports desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, reports::text AS sort_value FROM hero_builds ORDER \
+             BY reports desc, build_id desc, version desc LIMIT $1"
         );
     }
 
@@ -478,13 +964,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY updated_at desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, coalesce(updated_at, '-infinity')::text AS \
+             sort_value FROM hero_builds ORDER BY coalesce(updated_at, '-infinity') desc, build_id \
+             desc, version desc LIMIT $1"
         );
     }
 
@@ -495,13 +983,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY published_at desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, coalesce(published_at, '-infinity')::text AS \
+             sort_value FROM hero_builds ORDER BY coalesce(published_at, '-infinity') desc, \
+             build_id desc, version desc LIMIT $1"
         );
     }
 
@@ -512,13 +1002,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY version desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, version::text AS sort_value FROM hero_builds ORDER \
+             BY version desc, build_id desc, version desc LIMIT $1"
         );
     }
 
@@ -529,13 +1020,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, favorites::text AS sort_value FROM hero_builds \
+             ORDER BY favorites desc, build_id desc, version desc LIMIT $1"
         );
     }
 
@@ -546,13 +1038,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY favorites asc NULLS FIRST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, favorites::text AS sort_value FROM hero_builds \
+             ORDER BY favorites asc, build_id asc, version asc LIMIT $1"
         );
     }
 
@@ -563,13 +1056,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY favorites desc NULLS LAST LIMIT 50"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, favorites::text AS sort_value FROM hero_builds \
+             ORDER BY favorites desc, build_id desc, version desc LIMIT $1"
         );
     }
 
@@ -580,13 +1074,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY favorites desc NULLS LAST LIMIT 100 OFFSET 10"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, favorites::text AS sort_value FROM hero_builds \
+             ORDER BY favorites desc, build_id desc, version desc LIMIT $1 OFFSET $2"
         );
     }
 
@@ -602,14 +1097,16 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND \
-             lower(data->'hero_build'->>'name') LIKE '%tank%' AND hero = 42 ) SELECT builds FROM \
-             hero_builds ORDER BY updated_at asc NULLS FIRST LIMIT 25 OFFSET 5"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             lower(data->'hero_build'->>'name') LIKE $1 AND hero = $2 ) SELECT builds, build_id, \
+             version, coalesce(updated_at, '-infinity')::text AS sort_value FROM hero_builds ORDER \
+             BY coalesce(updated_at, '-infinity') asc, build_id asc, version asc LIMIT $3 OFFSET \
+             $4"
         );
     }
 
@@ -621,15 +1118,16 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND \
-             lower(data->'hero_build'->>'name') LIKE '%tank%' AND \
-             lower(data->'hero_build'->>'description') LIKE '%strength%' ) SELECT builds FROM \
-             hero_builds ORDER BY favorites desc NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             lower(data->'hero_build'->>'name') LIKE $1 AND \
+             lower(data->'hero_build'->>'description') LIKE $2 ) SELECT builds, build_id, version, \
+             favorites::text AS sort_value FROM hero_builds ORDER BY favorites desc, build_id \
+             desc, version desc LIMIT $3"
         );
     }
 
@@ -640,13 +1138,14 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE ) SELECT builds FROM hero_builds \
-             ORDER BY favorites desc NULLS LAST"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE ) \
+             SELECT builds, build_id, version, favorites::text AS sort_value FROM hero_builds \
+             ORDER BY favorites desc, build_id desc, version desc"
         );
     }
 
@@ -657,14 +1156,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND updated_at >= \
-             to_timestamp(1672531200) ) SELECT builds FROM hero_builds ORDER BY favorites desc \
-             NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             updated_at >= to_timestamp($1) ) SELECT builds, build_id, version, favorites::text AS \
+             sort_value FROM hero_builds ORDER BY favorites desc, build_id desc, version desc \
+             LIMIT $2"
         );
     }
 
@@ -675,14 +1175,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND updated_at <= \
-             to_timestamp(1675209599) ) SELECT builds FROM hero_builds ORDER BY favorites desc \
-             NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             updated_at <= to_timestamp($1) ) SELECT builds, build_id, version, favorites::text AS \
+             sort_value FROM hero_builds ORDER BY favorites desc, build_id desc, version desc \
+             LIMIT $2"
         );
     }
 
@@ -693,14 +1194,15 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND published_at >= \
-             to_timestamp(1672531200) ) SELECT builds FROM hero_builds ORDER BY favorites desc \
-             NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             published_at >= to_timestamp($1) ) SELECT builds, build_id, version, favorites::text \
+             AS sort_value FROM hero_builds ORDER BY favorites desc, build_id desc, version desc \
+             LIMIT $2"
         );
     }
 
@@ -711,14 +1213,242 @@ mod tests {
             ..Default::default()
         };
 
-        let sql = sql_query(&query);
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
         assert_eq!(
             sql,
-            " WITH hero_builds AS (SELECT data as builds, weekly_favorites, favorites, ignores, \
-             reports, updated_at, published_at, version, ROW_NUMBER() OVER(PARTITION BY hero, build_id ORDER BY \
-             version DESC) as rn FROM hero_builds WHERE TRUE AND published_at <= \
-             to_timestamp(1675209599) ) SELECT builds FROM hero_builds ORDER BY favorites desc \
-             NULLS LAST LIMIT 100"
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             published_at <= to_timestamp($1) ) SELECT builds, build_id, version, favorites::text \
+             AS sort_value FROM hero_builds ORDER BY favorites desc, build_id desc, version desc \
+             LIMIT $2"
+        );
+    }
+
+    #[test]
+    fn test_include_item_ids() {
+        let query = BuildsSearchQuery {
+            include_item_ids: Some(vec![1, 2]),
+            ..Default::default()
+        };
+
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
+        assert_eq!(
+            sql,
+            " WITH hero_builds AS (SELECT data as builds, build_id, weekly_favorites, favorites, \
+             ignores, reports, updated_at, published_at, version FROM hero_builds WHERE TRUE AND \
+             data->'hero_build'->'details'->'mod_categories' @> $1 ) SELECT builds, build_id, \
+             version, favorites::text AS sort_value FROM hero_builds ORDER BY favorites desc, \
+             build_id desc, version desc LIMIT $2"
+        );
+    }
+
+    #[test]
+    fn test_include_item_ids_any() {
+        let query = BuildsSearchQuery {
+            include_item_ids: Some(vec![1, 2]),
+            include_item_mode: BuildItemMatchMode::Any,
+            ..Default::default()
+        };
+
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
+        assert!(sql.contains(
+            " AND (data->'hero_build'->'details'->'mod_categories' @> $1 \
+             OR data->'hero_build'->'details'->'mod_categories' @> $2) "
+        ));
+    }
+
+    #[test]
+    fn test_exclude_item_ids() {
+        let query = BuildsSearchQuery {
+            exclude_item_ids: Some(vec![3]),
+            ..Default::default()
+        };
+
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
+        assert!(sql.contains(" AND NOT (data->'hero_build'->'details'->'mod_categories' @> $1) "));
+    }
+
+    #[test]
+    fn test_like_pattern() {
+        assert_eq!(like_pattern("Jean's Build"), "%jean's build%");
+        assert_eq!(like_pattern(r"100%_\"), r"%100\%\_\\%");
+    }
+
+    #[test]
+    fn test_search() {
+        let query = BuildsSearchQuery {
+            search: Some("tank".to_owned()),
+            sort_by: BuildsSearchQuerySortBy::Relevance,
+            ..Default::default()
+        };
+        assert!(query.validate().is_ok());
+
+        let query_builder = sql_query(&query, None);
+        let sql = query_builder.sql();
+        let document = text_search_document();
+        assert!(document.starts_with(
+            "(setweight(to_tsvector((CASE language WHEN 0 THEN 'english'::regconfig WHEN 1 THEN \
+             'german'::regconfig"
+        ));
+        // The document is computed once, the prefilter is the only other use for the index
+        assert_eq!(sql.matches(&document).count(), 2);
+        assert!(
+            sql.contains(", ts_rank(document, ts_query) as relevance FROM (SELECT *, (setweight(")
         );
+        assert!(sql.contains(
+            " AS ts_query FROM hero_builds WHERE (setweight(to_tsvector((CASE language WHEN 0"
+        ));
+        assert!(sql.contains(
+            " @@ (websearch_to_tsquery('english'::regconfig, $2) || \
+             websearch_to_tsquery('german'::regconfig, $3) ||"
+        ));
+        assert!(sql.contains(" OFFSET 0) hero_builds WHERE document @@ ts_query )"));
+        assert!(sql.contains("ORDER BY relevance desc, build_id desc, version desc LIMIT $"));
+    }
+
+    fn text_search_index_statement() -> String {
+        format!(
+            "create index hero_builds_text_search_index on hero_builds using gin ({});",
+            text_search_document()
+        )
+    }
+
+    #[test]
+    fn test_text_search_index() {
+        let schema = include_str!("../../../../tests/data/postgres/builds.sql");
+        let statement = schema
+            .lines()
+            .find(|line| line.starts_with("create index hero_builds_text_search_index "))
+            .expect("Missing text search index in tests/data/postgres/builds.sql");
+        assert_eq!(statement, text_search_index_statement());
+    }
+
+    #[test]
+    fn test_relevance_requires_search() {
+        let query = BuildsSearchQuery {
+            sort_by: BuildsSearchQuerySortBy::Relevance,
+            ..Default::default()
+        };
+        assert!(query.validate().is_err());
+    }
+
+    #[test]
+    fn test_cursor() {
+        let query = BuildsSearchQuery {
+            only_latest: Some(true),
+            ..Default::default()
+        };
+        let cursor = BuildsCursor::new(&query, "12".to_owned(), 3, 4);
+
+        let query_builder = sql_query(&query, Some(&cursor));
+        let sql = query_builder.sql();
+        assert!(sql.ends_with(
+            " FROM hero_builds WHERE rn = 1 AND (favorites, build_id, version) < (CAST($1 AS \
+             integer), $2, $3) ORDER BY favorites desc, build_id desc, version desc LIMIT $4"
+        ));
+
+        let query = BuildsSearchQuery {
+            cursor: Some(cursor.encode()),
+            ..query
+        };
+        assert_eq!(query.cursor().unwrap(), Some(cursor));
+    }
+
+    #[test]
+    fn test_cursor_all_versions() {
+        let query = BuildsSearchQuery {
+            hero_id: Some(42),
+            sort_direction: SortDirectionDesc::Asc,
+            ..Default::default()
+        };
+        let cursor = BuildsCursor::new(&query, "12".to_owned(), 3, 4);
+
+        let query_builder = sql_query(&query, Some(&cursor));
+        let sql = query_builder.sql();
+        assert!(!sql.contains("ROW_NUMBER()"));
+        assert!(sql.contains(
+            " WHERE TRUE AND hero = $1 AND (favorites, build_id, version) > (CAST($2 AS integer), \
+             $3, $4) ) SELECT builds, build_id, version, favorites::text AS sort_value FROM \
+             hero_builds ORDER BY favorites asc, build_id asc, version asc LIMIT $5"
+        ));
+    }
+
+    #[test]
+    fn test_cursor_relevance() {
+        let query = BuildsSearchQuery {
+            search: Some("tank".to_owned()),
+            sort_by: BuildsSearchQuerySortBy::Relevance,
+            ..Default::default()
+        };
+        let cursor = BuildsCursor::new(&query, "0.5".to_owned(), 3, 4);
+
+        let query_builder = sql_query(&query, Some(&cursor));
+        let sql = query_builder.sql();
+        assert!(sql.contains(
+            " WHERE document @@ ts_query AND (ts_rank(document, ts_query), build_id, version) < \
+             (CAST("
+        ));
+    }
+
+    #[test]
+    fn test_cursor_invalid() {
+        let cursor = BuildsCursor::new(&BuildsSearchQuery::default(), "12".to_owned(), 3, 4);
+        let query = BuildsSearchQuery {
+            sort_direction: SortDirectionDesc::Asc,
+            cursor: Some(cursor.encode()),
+            ..Default::default()
+        };
+        assert!(query.cursor().is_err());
+
+        let query = BuildsSearchQuery {
+            cursor: Some("not a cursor".to_owned()),
+            ..Default::default()
+        };
+        assert!(query.cursor().is_err());
+
+        let query = BuildsSearchQuery {
+            start: Some(100),
+            cursor: Some(cursor.encode()),
+            ..Default::default()
+        };
+        assert!(query.cursor().is_err());
+    }
+
+    #[test]
+    fn test_count_query() {
+        let query = BuildsSearchQuery {
+            hero_id: Some(42),
+            only_latest: Some(true),
+            ..Default::default()
+        };
+
+        let query_builder = count_query(&query);
+        let sql = query_builder.sql();
+        assert!(sql.ends_with(
+            "WHERE TRUE AND hero = $1 ) SELECT count(*) AS total FROM hero_builds WHERE rn = 1"
+        ));
+    }
+
+    #[test]
+    fn test_facets_query() {
+        let query = BuildsSearchQuery {
+            hero_id: Some(42),
+            only_latest: Some(true),
+            ..Default::default()
+        };
+
+        let query_builder = facets_query(&query);
+        let sql = query_builder.sql();
+        assert!(sql.contains(
+            "WHERE TRUE AND hero = $1 ), matched AS (SELECT builds FROM hero_builds WHERE rn = 1)"
+        ));
+        assert!(sql.ends_with(
+            "GROUP BY 2 UNION ALL SELECT 'total', NULL, count(*) FROM matched ORDER BY num_builds \
+             DESC, value"
+        ));
     }
 }
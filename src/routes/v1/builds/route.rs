@@ -2,27 +2,132 @@ use axum::Json;
 use axum::extract::State;
 use axum::response::IntoResponse;
 use axum_extra::extract::Query;
+use futures::try_join;
 use sqlx::Row;
 use tracing::debug;
 
 use crate::context::AppState;
-use crate::error::APIResult;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::builds::performance::fetch_builds_by_performance;
 use crate::routes::v1::builds::query;
-use crate::routes::v1::builds::query::BuildsSearchQuery;
-use crate::routes::v1::builds::structs::Build;
+use crate::routes::v1::builds::query::{BuildsCursor, BuildsSearchQuery, BuildsSearchQuerySortBy};
+use crate::routes::v1::builds::structs::{
+    Build, BuildFacetCount, BuildsSearchFacets, BuildsSearchResponse,
+};
 
 async fn fetch_builds(
     pg_client: &sqlx::Pool<sqlx::Postgres>,
     query: &BuildsSearchQuery,
-) -> sqlx::Result<Vec<Build>> {
-    let query = query::sql_query(query);
-    debug!(query);
-    Ok(sqlx::query(&query)
-        .fetch_all(pg_client)
-        .await?
-        .iter()
-        .map(|row| row.get::<sqlx::types::Json<_>, &str>("builds").0)
-        .collect())
+    cursor: Option<&BuildsCursor>,
+    count: bool,
+) -> sqlx::Result<BuildsSearchResponse> {
+    let mut query_builder = query::sql_query(query, cursor);
+    debug!(query = query_builder.sql());
+    let total = async {
+        if !count {
+            return Ok(None);
+        }
+        let mut count_query_builder = query::count_query(query);
+        debug!(count_query = count_query_builder.sql());
+        count_query_builder
+            .build_query_scalar::<i64>()
+            .fetch_one(pg_client)
+            .await
+            .map(Some)
+    };
+    let (mut rows, total) = try_join!(query_builder.build().fetch_all(pg_client), total)?;
+
+    let has_more = query.page_size().is_some_and(|size| rows.len() > size);
+    if let Some(size) = query.page_size() {
+        rows.truncate(size);
+    }
+    let next_cursor = rows.last().filter(|_| has_more).map(|row| {
+        BuildsCursor::new(
+            query,
+            row.get("sort_value"),
+            row.get::<i32, _>("build_id").cast_unsigned(),
+            row.get::<i32, _>("version").cast_unsigned(),
+        )
+        .encode()
+    });
+    Ok(BuildsSearchResponse {
+        builds: rows
+            .iter()
+            .map(|row| row.get::<sqlx::types::Json<_>, &str>("builds").0)
+            .collect(),
+        total: total.map(i64::cast_unsigned),
+        has_more,
+        next_cursor,
+        facets: None,
+    })
+}
+
+/// Fetches the facets of the query and the total number of matching builds.
+async fn fetch_facets(
+    pg_client: &sqlx::Pool<sqlx::Postgres>,
+    query: &BuildsSearchQuery,
+) -> sqlx::Result<(BuildsSearchFacets, u64)> {
+    let mut query_builder = query::facets_query(query);
+    debug!(facets_query = query_builder.sql());
+    let mut facets = BuildsSearchFacets::default();
+    let mut total = 0;
+    for row in query_builder.build().fetch_all(pg_client).await? {
+        let num_builds = row.get::<i64, _>("num_builds").cast_unsigned();
+        let facet = match row.get::<&str, _>("facet") {
+            "total" => {
+                total = num_builds;
+                continue;
+            }
+            "tag" => &mut facets.tags,
+            "language" => &mut facets.languages,
+            _ => &mut facets.rollup_categories,
+        };
+        let Some(value) = row
+            .get::<Option<i64>, _>("value")
+            .and_then(|v| u32::try_from(v).ok())
+        else {
+            continue;
+        };
+        facet.push(BuildFacetCount { value, num_builds });
+    }
+    Ok((facets, total))
+}
+
+/// Fetches a page of builds, with the total and facets only for the first page of the paged
+/// search, later pages already know them.
+async fn fetch_search(
+    state: &AppState,
+    params: &BuildsSearchQuery,
+    paged: bool,
+) -> APIResult<BuildsSearchResponse> {
+    params.validate()?;
+    let cursor = params.cursor()?;
+    let first_page = paged && cursor.is_none();
+    let with_facets = first_page && params.facets();
+    let builds = async {
+        if matches!(params.sort_by(), BuildsSearchQuerySortBy::Performance) {
+            fetch_builds_by_performance(state, params, cursor.as_ref()).await
+        } else {
+            // The facets count the total as well
+            let count = first_page && !with_facets;
+            Ok(fetch_builds(&state.pg_client, params, cursor.as_ref(), count).await?)
+        }
+    };
+    let facets = async {
+        if !with_facets {
+            return Ok(None);
+        }
+        fetch_facets(&state.pg_client, params)
+            .await
+            .map(Some)
+            .map_err(APIError::from)
+    };
+    let (mut response, facets) = try_join!(builds, facets)?;
+    if let Some((facets, total)) = facets {
+        response.facets = Some(facets);
+        response.total.get_or_insert(total);
+    }
+    Ok(response)
 }
 
 #[utoipa::path(
@@ -40,6 +145,9 @@ async fn fetch_builds(
     description = "
 Search for builds based on various criteria.
 
+Only returns the builds of the page, `facets` is ignored. Use the paged search at `/v1/builds/search`
+for the cursor of the next page, the total number of matching builds and the facets.
+
 ### Rate Limits:
 | Type | Limit |
 | ---- | ----- |
@@ -52,5 +160,44 @@ pub(super) async fn search_builds(
     Query(params): Query<BuildsSearchQuery>,
     State(state): State<AppState>,
 ) -> APIResult<impl IntoResponse> {
-    Ok(Json(fetch_builds(&state.pg_client, &params).await?))
+    Ok(Json(fetch_search(&state, &params, false).await?.builds))
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    params(BuildsSearchQuery),
+    responses(
+        (status = OK, body = BuildsSearchResponse),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+    ),
+    tags = ["Builds"],
+    summary = "Paged Search",
+    description = "
+Search for builds based on various criteria, returning a page of builds.
+
+Builds are returned in pages of `limit` builds. To get the next page, pass the `next_cursor` of the
+response as `cursor` with the same filters and sort. Ties in the sort are broken by build ID and
+version, so the order is stable across pages. The `total` number of matching builds is only returned
+for the first page, requests with a `cursor` omit it. With `sort_by=performance` only a subset of the
+matching builds is ranked and `total` is omitted as well.
+
+With `facets=true` the first page also contains the number of matching builds per tag, language and
+rollup category across all pages, e.g. to show filter chips with counts.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(super) async fn search_builds_paged(
+    Query(params): Query<BuildsSearchQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    Ok(Json(fetch_search(&state, &params, true).await?))
 }
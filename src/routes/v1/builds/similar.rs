@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use serde::Deserialize;
+use sqlx::Row;
+use tracing::debug;
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::{APIError, APIResult};
+use crate::routes::v1::builds::structs::{Build, BuildHero, SimilarBuild};
+use crate::utils::parse::comma_separated_deserialize;
+
+/// Maximum number of items of the draft
+const MAX_ITEMS: usize = 64;
+/// Maximum number of similar builds returned
+const MAX_LIMIT: u32 = 100;
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_limit() -> Option<u32> {
+    Some(10)
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub(super) struct SimilarBuildsQuery {
+    /// Hero ID of the draft. See more: <https://assets.deadlock-api.com/v2/heroes>
+    hero_id: u32,
+    /// Comma separated list of the item IDs of the draft. See more: <https://assets.deadlock-api.com/v2/items>
+    #[param(inline, min_items = 1, max_items = 64)]
+    #[serde(deserialize_with = "comma_separated_deserialize")]
+    item_ids: Vec<u32>,
+    /// Minimum Jaccard similarity between the items of the draft and a build.
+    #[param(minimum = 0.0, maximum = 1.0)]
+    min_similarity: Option<f64>,
+    /// The maximum number of builds to return.
+    #[serde(default = "default_limit")]
+    #[param(minimum = 1, maximum = 100, default = 10)]
+    limit: Option<u32>,
+}
+
+/// Ranks the latest version of every build of the hero by the Jaccard similarity of its items
+/// with the items of the draft, bound as `$2`.
+const SIMILAR_BUILDS_QUERY: &str = "
+WITH
+    latest AS (
+        SELECT DISTINCT ON (build_id)
+            data,
+            favorites,
+            ARRAY(
+                SELECT DISTINCT item_id::bigint
+                FROM jsonb_path_query(data, '$.hero_build.details.mod_categories[*].mods[*].ability_id') AS item_id
+            ) AS item_ids
+        FROM hero_builds
+        WHERE hero = $1
+        ORDER BY build_id, version DESC
+    ),
+    scored AS (
+        SELECT
+            data,
+            favorites,
+            cardinality(ARRAY(SELECT unnest(item_ids) INTERSECT SELECT unnest($2::bigint[]))) AS overlap,
+            cardinality(item_ids) AS num_items
+        FROM latest
+        WHERE item_ids && $2::bigint[]
+    ),
+    ranked AS (
+        SELECT data, favorites, overlap::float8 / (num_items + cardinality($2::bigint[]) - overlap) AS similarity
+        FROM scored
+    )
+SELECT data AS builds, similarity
+FROM ranked
+WHERE similarity >= $3
+ORDER BY similarity DESC, favorites DESC
+LIMIT $4
+";
+
+/// Distinct item IDs across all categories of a build.
+pub(super) fn build_item_ids(hero_build: &BuildHero) -> HashSet<u32> {
+    hero_build
+        .details
+        .mod_categories
+        .iter()
+        .filter_map(|category| category.mods.as_ref())
+        .flatten()
+        .map(|m| m.ability_id)
+        .collect()
+}
+
+fn similar_build(build: Build, similarity: f64, item_ids: &[u32]) -> SimilarBuild {
+    let build_item_ids = build_item_ids(&build.hero_build);
+    let (matching_item_ids, missing_item_ids) = item_ids
+        .iter()
+        .copied()
+        .partition(|id| build_item_ids.contains(id));
+    SimilarBuild {
+        build,
+        similarity,
+        matching_item_ids,
+        missing_item_ids,
+    }
+}
+
+async fn fetch_similar_builds(
+    pg_client: &sqlx::Pool<sqlx::Postgres>,
+    query: &SimilarBuildsQuery,
+) -> sqlx::Result<Vec<SimilarBuild>> {
+    debug!(?query);
+    let item_ids = query
+        .item_ids
+        .iter()
+        .map(|&id| i64::from(id))
+        .collect::<Vec<_>>();
+    Ok(sqlx::query(SIMILAR_BUILDS_QUERY)
+        .bind(query.hero_id.cast_signed())
+        .bind(item_ids)
+        .bind(query.min_similarity.unwrap_or_default())
+        .bind(i64::from(query.limit.unwrap_or(10)))
+        .fetch_all(pg_client)
+        .await?
+        .iter()
+        .map(|row| {
+            similar_build(
+                row.get::<sqlx::types::Json<_>, &str>("builds").0,
+                row.get("similarity"),
+                &query.item_ids,
+            )
+        })
+        .collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/similar",
+    params(SimilarBuildsQuery),
+    responses(
+        (status = OK, body = [SimilarBuild]),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+    ),
+    tags = ["Builds"],
+    summary = "Similar Builds",
+    description = "
+Finds the published builds most similar to a draft item list.
+
+Only the latest version of each build of the hero is considered. Builds are ranked by the Jaccard
+similarity between the items of the draft and the items across all categories of the build, ties
+are broken by the number of favorites.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(super) async fn similar_builds(
+    Query(mut query): Query<SimilarBuildsQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    query.item_ids.sort_unstable();
+    query.item_ids.dedup();
+    if query.item_ids.is_empty() || query.item_ids.len() > MAX_ITEMS {
+        return Err(APIError::bad_request(format!(
+            "item_ids must contain between 1 and {MAX_ITEMS} items"
+        )));
+    }
+    if query
+        .min_similarity
+        .is_some_and(|s| !(0.0..=1.0).contains(&s))
+    {
+        return Err(APIError::bad_request(
+            "min_similarity must be between 0 and 1",
+        ));
+    }
+    query.limit = query.limit.map(|l| l.clamp(1, MAX_LIMIT));
+    Ok(Json(fetch_similar_builds(&state.pg_client, &query).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(mods: &[&[u32]]) -> Build {
+        serde_json::from_value(serde_json::json!({
+            "hero_build": {
+                "hero_id": 10,
+                "hero_build_id": 1,
+                "author_account_id": 1,
+                "name": "Build",
+                "language": 0,
+                "version": 1,
+                "origin_build_id": 0,
+                "details": {
+                    "mod_categories": mods
+                        .iter()
+                        .map(|ids| serde_json::json!({
+                            "name": "Category",
+                            "mods": ids
+                                .iter()
+                                .map(|id| serde_json::json!({"ability_id": id}))
+                                .collect::<Vec<_>>(),
+                        }))
+                        .collect::<Vec<_>>(),
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_item_ids() {
+        let build = build(&[&[1, 2], &[2, 3], &[]]);
+        assert_eq!(build_item_ids(&build.hero_build), HashSet::from([1, 2, 3]));
+    }
+
+    #[test]
+    fn test_similar_build() {
+        let similar = similar_build(build(&[&[1, 2], &[3]]), 0.5, &[2, 3, 4]);
+        assert_eq!(similar.matching_item_ids, vec![2, 3]);
+        assert_eq!(similar.missing_item_ids, vec![4]);
+    }
+}
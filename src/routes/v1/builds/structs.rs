@@ -1,47 +1,49 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::routes::v1::players::steam::route::SteamProfile;
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "snake_case")]
-struct BuildHeroDetailsCategoryAbility {
-    ability_id: u32,
-    annotation: Option<String>,
-    required_flex_slots: Option<u32>,
-    sell_priority: Option<u32>,
-    imbue_target_ability_id: Option<u32>,
+pub(crate) struct BuildHeroDetailsCategoryAbility {
+    pub(crate) ability_id: u32,
+    pub(crate) annotation: Option<String>,
+    pub(crate) required_flex_slots: Option<u32>,
+    pub(crate) sell_priority: Option<u32>,
+    pub(crate) imbue_target_ability_id: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "snake_case")]
-struct BuildHeroDetailsCategory {
-    name: String,
-    width: Option<f32>,
-    height: Option<f32>,
-    description: Option<String>,
-    mods: Option<Vec<BuildHeroDetailsCategoryAbility>>,
-    optional: Option<bool>,
+pub(crate) struct BuildHeroDetailsCategory {
+    pub(crate) name: String,
+    pub(crate) width: Option<f32>,
+    pub(crate) height: Option<f32>,
+    pub(crate) description: Option<String>,
+    pub(crate) mods: Option<Vec<BuildHeroDetailsCategoryAbility>>,
+    pub(crate) optional: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "snake_case")]
-struct BuildHeroDetailsAbilityOrderCurrencyChange {
-    ability_id: u32,
-    currency_type: i32,
-    delta: i32,
-    annotation: Option<String>,
+pub(crate) struct BuildHeroDetailsAbilityOrderCurrencyChange {
+    pub(crate) ability_id: u32,
+    pub(crate) currency_type: i32,
+    pub(crate) delta: i32,
+    pub(crate) annotation: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "snake_case")]
-struct BuildHeroDetailsAbilityOrder {
-    currency_changes: Option<Vec<BuildHeroDetailsAbilityOrderCurrencyChange>>,
+pub(crate) struct BuildHeroDetailsAbilityOrder {
+    pub(crate) currency_changes: Option<Vec<BuildHeroDetailsAbilityOrderCurrencyChange>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "snake_case")]
-struct BuildHeroDetails {
-    mod_categories: Vec<BuildHeroDetailsCategory>,
-    ability_order: Option<BuildHeroDetailsAbilityOrder>,
+pub(crate) struct BuildHeroDetails {
+    pub(crate) mod_categories: Vec<BuildHeroDetailsCategory>,
+    pub(crate) ability_order: Option<BuildHeroDetailsAbilityOrder>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -54,14 +56,14 @@ pub struct BuildHero {
     pub last_updated_timestamp: Option<i64>,
     pub publish_timestamp: Option<i64>,
     pub name: String,
-    description: Option<String>,
-    language: u32,
+    pub(crate) description: Option<String>,
+    pub(crate) language: u32,
     pub version: u32,
-    origin_build_id: u32,
+    pub(crate) origin_build_id: u32,
     #[serde(default)]
     pub tags: Vec<u32>,
-    development_build: Option<bool>,
-    details: BuildHeroDetails,
+    pub(crate) development_build: Option<bool>,
+    pub(crate) details: BuildHeroDetails,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -74,3 +76,185 @@ pub struct Build {
     pub num_weekly_favorites: Option<u32>,
     rollup_category: Option<u32>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimilarBuild {
+    #[serde(flatten)]
+    pub build: Build,
+    /// Jaccard similarity between the items of the draft and the items of the build.
+    pub similarity: f64,
+    /// Items of the draft that are part of the build.
+    pub matching_item_ids: Vec<u32>,
+    /// Items of the draft that are not part of the build.
+    pub missing_item_ids: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildCategoryDiff {
+    /// Name of the category.
+    pub name: String,
+    pub added_item_ids: Vec<u32>,
+    pub removed_item_ids: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildAnnotationChange {
+    /// Name of the category of the item.
+    pub category: String,
+    pub item_id: u32,
+    pub previous_annotation: Option<String>,
+    pub current_annotation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildAbilityOrderChange {
+    /// Index of the first step of the ability order that changed.
+    pub first_changed_step: usize,
+    /// Steps of the previous version from the first changed step on.
+    pub(crate) previous_steps: Vec<BuildHeroDetailsAbilityOrderCurrencyChange>,
+    /// Steps of this version from the first changed step on.
+    pub(crate) current_steps: Vec<BuildHeroDetailsAbilityOrderCurrencyChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildVersionDiff {
+    /// The version this version is compared to.
+    pub previous_version: u32,
+    /// Categories with items added or removed.
+    pub categories: Vec<BuildCategoryDiff>,
+    /// Items kept in a category with a changed annotation.
+    pub annotation_changes: Vec<BuildAnnotationChange>,
+    /// Changed part of the ability order, if it changed.
+    pub ability_order: Option<BuildAbilityOrderChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildVersion {
+    #[serde(flatten)]
+    pub build: Build,
+    /// Diff to the previous version, missing for the first version.
+    pub diff: Option<BuildVersionDiff>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildVersionHistory {
+    pub hero_build_id: u32,
+    /// See more: <https://assets.deadlock-api.com/v2/heroes>
+    pub hero_id: u32,
+    /// All versions of the build, oldest first.
+    pub versions: Vec<BuildVersion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildsSearchResponse {
+    pub builds: Vec<Build>,
+    /// Number of builds matching the filters across all pages, only on the first page (without
+    /// `cursor`). Missing with `sort_by=performance`, which only ranks the latest versions of the
+    /// 100 most favorited matching builds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    /// Whether there are more builds after this page.
+    pub has_more: bool,
+    /// Cursor of the next page, missing on the last page.
+    pub next_cursor: Option<String>,
+    /// Number of matching builds per tag, language and rollup category, only on the first page
+    /// with `facets=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<BuildsSearchFacets>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildFacetCount {
+    pub value: u32,
+    pub num_builds: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct BuildsSearchFacets {
+    /// Number of matching builds per tag, most common first.
+    pub tags: Vec<BuildFacetCount>,
+    /// Number of matching builds per language, most common first.
+    pub languages: Vec<BuildFacetCount>,
+    /// Number of matching builds per rollup category, most common first.
+    pub rollup_categories: Vec<BuildFacetCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildPerformance {
+    pub hero_build_id: u32,
+    /// See more: <https://assets.deadlock-api.com/v2/heroes>
+    pub hero_id: u32,
+    /// The version of the build the items were taken from.
+    pub version: u32,
+    /// Minimum share of the items of the build a player bought to count as following it.
+    pub min_overlap: f64,
+    /// Number of matches of players following the build.
+    pub matches: u64,
+    /// Winrate of players following the build.
+    pub winrate: Option<f64>,
+    /// Lower bound of the 95% Wilson score interval of the winrate.
+    pub wilson_lower_bound: f64,
+    /// Share of the matches of the hero in which the player followed the build.
+    pub usage_rate: f64,
+    /// Number of matches of the hero in which the player bought enough upgrades to be compared.
+    pub hero_matches: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildChangeKind {
+    /// The first version of the build was published.
+    Created,
+    /// A new version of the build was published.
+    Updated,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildChange {
+    pub kind: BuildChangeKind,
+    /// Time of the change (Unix timestamp).
+    pub updated_at: i64,
+    #[serde(flatten)]
+    pub build: Build,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct BuildAuthorStats {
+    /// The author's `SteamID3`.
+    pub(crate) account_id: u32,
+    /// Number of published builds.
+    pub(crate) num_builds: u64,
+    /// All-time favorites across all builds.
+    pub(crate) favorites: u64,
+    /// Weekly favorites across all builds.
+    pub(crate) weekly_favorites: u64,
+    /// Heroes the author published builds for. See more: <https://assets.deadlock-api.com/v2/heroes>
+    pub(crate) hero_ids: Vec<u32>,
+    /// Languages the author published builds in.
+    pub(crate) languages: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct BuildAuthor {
+    #[serde(flatten)]
+    pub(crate) stats: BuildAuthorStats,
+    /// Missing if the profile is unknown.
+    pub(crate) steam_profile: Option<SteamProfile>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct BuildAuthorProfile {
+    #[serde(flatten)]
+    pub(crate) author: BuildAuthor,
+    /// The latest version of every build of the author, most favorited first.
+    pub(crate) builds: Vec<Build>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildTagCount {
+    pub tag: u32,
+    /// See more: <https://assets.deadlock-api.com/v2/heroes>
+    pub hero_id: u32,
+    /// Number of builds of the hero with the tag.
+    pub num_builds: u64,
+}
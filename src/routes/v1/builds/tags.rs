@@ -0,0 +1,99 @@
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::Query;
+use serde::Deserialize;
+use sqlx::{QueryBuilder, Row};
+use tracing::debug;
+use utoipa::IntoParams;
+
+use crate::context::AppState;
+use crate::error::APIResult;
+use crate::routes::v1::builds::structs::BuildTagCount;
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub(super) struct BuildTagsQuery {
+    /// Only count builds of this hero. See more: <https://assets.deadlock-api.com/v2/heroes>
+    hero_id: Option<u32>,
+}
+
+/// Query of the number of builds per tag and hero, counting the latest version of each build.
+fn tags_query(query: &BuildTagsQuery) -> QueryBuilder<'static, sqlx::Postgres> {
+    let mut query_builder = QueryBuilder::new(
+        "WITH latest AS (SELECT DISTINCT ON (build_id) hero, data FROM hero_builds WHERE TRUE",
+    );
+    if let Some(hero_id) = query.hero_id {
+        query_builder.push(" AND hero = ");
+        query_builder.push_bind(hero_id.cast_signed());
+    }
+    query_builder.push(
+        " ORDER BY build_id, version DESC) SELECT tag::bigint AS tag, hero, count(*) AS num_builds \
+         FROM latest, jsonb_path_query(data, '$.hero_build.tags[*]') AS tag GROUP BY tag, hero \
+         ORDER BY hero, num_builds DESC, tag",
+    );
+    query_builder
+}
+
+#[utoipa::path(
+    get,
+    path = "/tags",
+    params(BuildTagsQuery),
+    responses(
+        (status = OK, body = [BuildTagCount]),
+        (status = BAD_REQUEST, description = "Provided parameters are invalid."),
+        (status = TOO_MANY_REQUESTS, description = "Rate limit exceeded"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal server error")
+    ),
+    tags = ["Builds"],
+    summary = "Tags",
+    description = "
+Lists the tags used by builds with the number of builds per hero, most used first. Only the latest
+version of each build is counted.
+
+### Rate Limits:
+| Type | Limit |
+| ---- | ----- |
+| IP | 100req/s |
+| Key | - |
+| Global | - |
+    "
+)]
+pub(super) async fn build_tags(
+    Query(query): Query<BuildTagsQuery>,
+    State(state): State<AppState>,
+) -> APIResult<impl IntoResponse> {
+    let mut query_builder = tags_query(&query);
+    debug!(query = query_builder.sql());
+    let tags = query_builder
+        .build()
+        .fetch_all(&state.pg_client)
+        .await?
+        .iter()
+        .filter_map(|row| {
+            Some(BuildTagCount {
+                tag: u32::try_from(row.get::<i64, _>("tag")).ok()?,
+                hero_id: row.get::<i32, _>("hero").cast_unsigned(),
+                num_builds: row.get::<i64, _>("num_builds").cast_unsigned(),
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(tags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_query() {
+        let query_builder = tags_query(&BuildTagsQuery { hero_id: Some(10) });
+        assert_eq!(
+            query_builder.sql(),
+            "WITH latest AS (SELECT DISTINCT ON (build_id) hero, data FROM hero_builds WHERE TRUE \
+             AND hero = $1 ORDER BY build_id, version DESC) SELECT tag::bigint AS tag, hero, \
+             count(*) AS num_builds FROM latest, jsonb_path_query(data, '$.hero_build.tags[*]') \
+             AS tag GROUP BY tag, hero ORDER BY hero, num_builds DESC, tag"
+        );
+    }
+}
@@ -3,7 +3,7 @@ use axum::response::IntoResponse;
 
 use crate::error::APIResult;
 
-const BIG_PATCH_DAYS: &[&str] = &[
+pub(crate) const BIG_PATCH_DAYS: &[&str] = &[
     "2025-08-18T20:43:52Z",
     "2025-07-29T22:22:52Z",
     "2025-07-04T20:03:43Z",
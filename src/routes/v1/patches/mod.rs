@@ -1,4 +1,4 @@
-mod big_patch_days;
+pub(super) mod big_patch_days;
 pub(super) mod feed;
 
 use core::time::Duration;
@@ -33,7 +33,7 @@ pub(super) struct SteamSearchQuery {
 
 #[derive(Debug, Clone, Row, Serialize, Deserialize, ToSchema)]
 pub(crate) struct SteamProfile {
-    pub(super) account_id: u32,
+    pub(crate) account_id: u32,
     pub(crate) personaname: String,
     pub(super) profileurl: String,
     pub(super) avatar: String,
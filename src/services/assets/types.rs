@@ -6,6 +6,10 @@ use serde::Deserialize;
 pub(crate) struct AssetsHero {
     pub(crate) id: u32,
     pub(crate) name: String,
+    /// Class names of the items of the hero by slot, the abilities are in the `signature1` to
+    /// `signature4` slots
+    #[serde(default)]
+    pub(crate) items: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -27,4 +31,9 @@ pub(crate) struct AssetsItem {
     pub(crate) item_type: Option<String>,
     #[serde(default)]
     pub(crate) slot: Option<String>,
+    #[serde(default)]
+    pub(crate) class_name: Option<String>,
+    /// Class names of the items this item is upgraded from
+    #[serde(default)]
+    pub(crate) component_items: Option<Vec<String>>,
 }
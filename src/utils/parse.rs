@@ -63,7 +63,7 @@ pub(crate) fn parse_steam_id_option<'de, D>(deserializer: D) -> Result<Option<u3
 where
     D: Deserializer<'de>,
 {
-    Option::<u64>::deserialize(deserializer)
+    parse_number_option::<D, u64>(deserializer)
         .map_err(serde::de::Error::custom)
         .and_then(|steam_id| {
             steam_id
@@ -175,6 +175,47 @@ where
     })
 }
 
+/// A number, or a string containing one.
+///
+/// Query parameters of a struct flattened with `#[serde(flatten)]` are buffered as strings, so
+/// numbers of flattened query structs have to be parsed from strings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum NumberOrString<T> {
+    Number(T),
+    String(String),
+}
+
+impl<T: FromStr> NumberOrString<T> {
+    fn parse<E: serde::de::Error>(self) -> Result<T, E> {
+        match self {
+            Self::Number(v) => Ok(v),
+            Self::String(s) => s
+                .trim()
+                .parse()
+                .map_err(|_| E::custom(format!("Invalid number: {s}"))),
+        }
+    }
+}
+
+pub(crate) fn parse_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+{
+    NumberOrString::<T>::deserialize(deserializer)?.parse()
+}
+
+pub(crate) fn parse_number_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+{
+    Option::<NumberOrString<T>>::deserialize(deserializer)?
+        .map(NumberOrString::parse)
+        .transpose()
+}
+
 pub(crate) fn default_last_month_timestamp() -> Option<i64> {
     let now = chrono::Utc::now().date_naive();
     let last_month = now - chrono::Duration::days(30);
@@ -339,6 +380,40 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[derive(Deserialize)]
+    struct NumberTestStruct {
+        #[serde(deserialize_with = "parse_number")]
+        number: i64,
+        #[serde(default, deserialize_with = "parse_number_option")]
+        number_option: Option<u8>,
+    }
+
+    #[rstest]
+    #[case("{\"number\": 1}", 1, None)]
+    #[case("{\"number\": \"-1\"}", -1, None)]
+    #[case("{\"number\": 1, \"number_option\": 2}", 1, Some(2))]
+    #[case("{\"number\": 1, \"number_option\": \" 2 \"}", 1, Some(2))]
+    #[case("{\"number\": 1, \"number_option\": null}", 1, None)]
+    fn test_parse_number(
+        #[case] json: &str,
+        #[case] expected: i64,
+        #[case] expected_option: Option<u8>,
+    ) {
+        let result: NumberTestStruct = serde_json::from_str(json).unwrap();
+        assert_eq!(result.number, expected);
+        assert_eq!(result.number_option, expected_option);
+    }
+
+    #[rstest]
+    #[case("{\"number\": \"a\"}")]
+    #[case("{\"number\": 1.5}")]
+    #[case("{\"number\": 1, \"number_option\": \"256\"}")]
+    #[case("{\"number\": 1, \"number_option\": -1}")]
+    fn test_parse_number_invalid(#[case] json: &str) {
+        let result = serde_json::from_str::<NumberTestStruct>(json);
+        assert!(result.is_err());
+    }
+
     #[derive(Deserialize)]
     struct DateTimeTestStruct {
         #[serde(deserialize_with = "parse_rfc2822_datetime")]
@@ -0,0 +1,18 @@
+DROP TABLE IF EXISTS build_drafts;
+
+create table build_drafts
+(
+    share_code    text                                   not null primary key,
+    hero_id       integer                                not null,
+    name          text,
+    item_ids      integer[]                default '{}'  not null,
+    notes         text,
+    filters       jsonb                    default '{}'  not null,
+    owner_api_key uuid,
+    edit_token    uuid,
+    created_at    timestamp with time zone default now() not null,
+    updated_at    timestamp with time zone default now() not null,
+    constraint build_drafts_owner_check check (owner_api_key is not null or edit_token is not null)
+);
+
+create index build_drafts_owner_api_key_index on build_drafts (owner_api_key);
@@ -21,6 +21,7 @@ create table hero_builds
 create index hero_builds_author_id_index on hero_builds (author_id);
 create index hero_builds_weekly_favorites_index on hero_builds (weekly_favorites desc);
 create index hero_builds_language_index on hero_builds (language);
+create index hero_builds_text_search_index on hero_builds using gin ((setweight(to_tsvector((CASE language WHEN 0 THEN 'english'::regconfig WHEN 1 THEN 'german'::regconfig WHEN 2 THEN 'french'::regconfig WHEN 3 THEN 'italian'::regconfig WHEN 4 THEN 'simple'::regconfig WHEN 5 THEN 'spanish'::regconfig WHEN 6 THEN 'simple'::regconfig WHEN 8 THEN 'russian'::regconfig WHEN 9 THEN 'simple'::regconfig WHEN 10 THEN 'simple'::regconfig WHEN 11 THEN 'portuguese'::regconfig WHEN 12 THEN 'simple'::regconfig WHEN 19 THEN 'simple'::regconfig WHEN 21 THEN 'turkish'::regconfig WHEN 22 THEN 'portuguese'::regconfig WHEN 25 THEN 'simple'::regconfig WHEN 26 THEN 'spanish'::regconfig WHEN 27 THEN 'simple'::regconfig ELSE 'simple'::regconfig END), coalesce(data->'hero_build'->>'name', '')), 'A') || setweight(to_tsvector((CASE language WHEN 0 THEN 'english'::regconfig WHEN 1 THEN 'german'::regconfig WHEN 2 THEN 'french'::regconfig WHEN 3 THEN 'italian'::regconfig WHEN 4 THEN 'simple'::regconfig WHEN 5 THEN 'spanish'::regconfig WHEN 6 THEN 'simple'::regconfig WHEN 8 THEN 'russian'::regconfig WHEN 9 THEN 'simple'::regconfig WHEN 10 THEN 'simple'::regconfig WHEN 11 THEN 'portuguese'::regconfig WHEN 12 THEN 'simple'::regconfig WHEN 19 THEN 'simple'::regconfig WHEN 21 THEN 'turkish'::regconfig WHEN 22 THEN 'portuguese'::regconfig WHEN 25 THEN 'simple'::regconfig WHEN 26 THEN 'spanish'::regconfig WHEN 27 THEN 'simple'::regconfig ELSE 'simple'::regconfig END), coalesce(data->'hero_build'->>'description', '')), 'B')));
 
 INSERT INTO public.hero_builds (hero, build_id, version, author_id, favorites, ignores, reports, updated_at, data,
                                 language, weekly_favorites, rollup_category, published_at)
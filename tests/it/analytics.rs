@@ -4,6 +4,7 @@ use deadlock_api_rust::routes::v1::analytics::ability_order_stats::AnalyticsAbil
 use deadlock_api_rust::routes::v1::analytics::build_item_stats::BuildItemStats;
 use deadlock_api_rust::routes::v1::analytics::hero_comb_stats::HeroCombStats;
 use deadlock_api_rust::routes::v1::analytics::hero_counters_stats::HeroCounterStats;
+use deadlock_api_rust::routes::v1::analytics::hero_powerspike::HeroPowerspike;
 use deadlock_api_rust::routes::v1::analytics::hero_stats::AnalyticsHeroStats;
 use deadlock_api_rust::routes::v1::analytics::hero_synergies_stats::HeroSynergyStats;
 use deadlock_api_rust::routes::v1::analytics::item_stats::ItemStats;
@@ -1330,3 +1331,88 @@ async fn test_player_performance_curve(
         assert!(point.net_worth_std >= 0.0);
     }
 }
+
+#[rstest]
+#[case(1, None, None, None, None)]
+#[case(1, Some(1741801678), Some(1742233678), Some(10), Some(5))]
+#[case(15, Some(1741801678), None, Some(100), None)]
+#[tokio::test]
+async fn test_hero_powerspike(
+    #[case] hero_id: u32,
+    #[case] min_unix_timestamp: Option<i64>,
+    #[case] max_unix_timestamp: Option<i64>,
+    #[case] min_window_matches: Option<u64>,
+    #[case] min_item_matches: Option<u64>,
+) {
+    let mut queries = vec![("hero_id", hero_id.to_string())];
+    if let Some(min_unix_timestamp) = min_unix_timestamp {
+        queries.push(("min_unix_timestamp", min_unix_timestamp.to_string()));
+    }
+    if let Some(max_unix_timestamp) = max_unix_timestamp {
+        queries.push(("max_unix_timestamp", max_unix_timestamp.to_string()));
+    }
+    if let Some(min_window_matches) = min_window_matches {
+        queries.push(("min_window_matches", min_window_matches.to_string()));
+    }
+    if let Some(min_item_matches) = min_item_matches {
+        queries.push(("min_item_matches", min_item_matches.to_string()));
+    }
+
+    let queries = queries
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .collect::<Vec<_>>();
+    let response = request_endpoint("/v1/analytics/hero-powerspike", queries).await;
+    let powerspike: HeroPowerspike = response.json().await.expect("Failed to parse response");
+    assert_eq!(powerspike.hero_id, hero_id);
+
+    // Verify windows and brackets are sorted and the match math adds up
+    assert!(powerspike.windows.is_sorted_by_key(|w| w.start_s));
+    assert!(
+        powerspike
+            .net_worth_brackets
+            .is_sorted_by_key(|b| b.min_net_worth)
+    );
+    for window in &powerspike.windows {
+        assert!(window.wins <= window.matches);
+    }
+    for bracket in &powerspike.net_worth_brackets {
+        assert!(bracket.wins <= bracket.matches);
+    }
+
+    // Verify the peak window is the best window with enough matches
+    let min_window_matches = min_window_matches.unwrap_or(1000);
+    if let Some(peak_window) = &powerspike.peak_window {
+        assert!(peak_window.matches >= min_window_matches);
+        assert!(powerspike.windows.contains(peak_window));
+        for window in &powerspike.windows {
+            if window.matches >= min_window_matches {
+                assert!(window.winrate <= peak_window.winrate);
+            }
+        }
+    } else {
+        assert!(powerspike.items.is_empty());
+    }
+    if let Some(peak_bracket) = &powerspike.peak_net_worth_bracket {
+        assert!(peak_bracket.matches >= min_window_matches);
+        assert!(powerspike.net_worth_brackets.contains(peak_bracket));
+    }
+
+    // Verify items are sorted by winrate and have enough matches
+    assert!(powerspike.items.is_sorted_by(|a, b| a.winrate >= b.winrate));
+    for item in &powerspike.items {
+        assert!(item.wins <= item.matches);
+        assert!(item.matches >= min_item_matches.unwrap_or(500));
+    }
+}
+
+#[tokio::test]
+async fn test_hero_powerspike_invalid_hero() {
+    let response = reqwest::Client::new()
+        .get("http://localhost:3000/v1/analytics/hero-powerspike?hero_id=999999")
+        .header("X-API-Key", "HEXE-fffd6bfd-2be9-4b7e-ab76-a9d1dca19b64")
+        .send()
+        .await
+        .expect("Failed to get response");
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,212 @@
+use itertools::Itertools;
+use reqwest::{Client, Response, StatusCode};
+use rstest::rstest;
+use serde_json::{Value, json};
+
+use crate::request_endpoint;
+
+const API_KEY: &str = "HEXE-fffd6bfd-2be9-4b7e-ab76-a9d1dca19b64";
+const DRAFTS_URL: &str = "http://localhost:3000/v1/build-creator/drafts";
+
+fn draft_request(name: &str) -> Value {
+    json!({
+        "hero_id": 10,
+        "name": name,
+        "item_ids": [1548066885, 968099481],
+        "notes": "Buy the magazine first",
+        "filters": {"min_matches": 50},
+    })
+}
+
+async fn create_draft(client: &Client, api_key: Option<&str>) -> Value {
+    let mut request = client.post(DRAFTS_URL).json(&draft_request("Draft"));
+    if let Some(api_key) = api_key {
+        request = request.header("X-API-Key", api_key);
+    }
+    let response = request.send().await.expect("Failed to get response");
+    assert_eq!(response.status(), StatusCode::CREATED);
+    response.json().await.expect("Failed to parse response")
+}
+
+async fn update_draft(
+    client: &Client,
+    share_code: &str,
+    api_key: Option<&str>,
+    edit_token: Option<&str>,
+) -> Response {
+    let mut request = client
+        .put(format!("{DRAFTS_URL}/{share_code}"))
+        .json(&draft_request("Updated Draft"));
+    if let Some(api_key) = api_key {
+        request = request.header("X-API-Key", api_key);
+    }
+    if let Some(edit_token) = edit_token {
+        request = request.header("X-Edit-Token", edit_token);
+    }
+    request.send().await.expect("Failed to get response")
+}
+
+async fn delete_draft(
+    client: &Client,
+    share_code: &str,
+    api_key: Option<&str>,
+    edit_token: Option<&str>,
+) -> Response {
+    let mut request = client.delete(format!("{DRAFTS_URL}/{share_code}"));
+    if let Some(api_key) = api_key {
+        request = request.header("X-API-Key", api_key);
+    }
+    if let Some(edit_token) = edit_token {
+        request = request.header("X-Edit-Token", edit_token);
+    }
+    request.send().await.expect("Failed to get response")
+}
+
+async fn get_draft(client: &Client, share_code: &str) -> Response {
+    client
+        .get(format!("{DRAFTS_URL}/{share_code}"))
+        .send()
+        .await
+        .expect("Failed to get response")
+}
+
+#[tokio::test]
+async fn test_draft_with_edit_token() {
+    let client = Client::new();
+    let draft = create_draft(&client, None).await;
+    let share_code = draft["share_code"].as_str().expect("Missing share code");
+    let edit_token = draft["edit_token"].as_str().expect("Missing edit token");
+    assert_eq!(share_code.len(), 8);
+    assert_eq!(draft["hero_id"], 10);
+    assert_eq!(draft["item_ids"], json!([1548066885, 968099481]));
+    assert_eq!(draft["filters"]["min_matches"], 50);
+
+    let response = get_draft(&client, share_code).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let fetched: Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(fetched["share_code"], share_code);
+    assert_eq!(fetched["name"], "Draft");
+    assert!(fetched.get("edit_token").is_none());
+
+    // Neither a missing or wrong edit token nor a foreign API key can modify the draft
+    for (api_key, token) in [
+        (None, None),
+        (None, Some("00000000-0000-0000-0000-000000000000")),
+        (Some(API_KEY), None),
+    ] {
+        let response = update_draft(&client, share_code, api_key, token).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = delete_draft(&client, share_code, api_key, token).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    let response = update_draft(&client, share_code, None, Some(edit_token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let updated: Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(updated["share_code"], share_code);
+    assert_eq!(updated["name"], "Updated Draft");
+    assert_eq!(updated["created_at"], draft["created_at"]);
+
+    let response = delete_draft(&client, share_code, None, Some(edit_token)).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = get_draft(&client, share_code).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_draft_with_api_key() {
+    let client = Client::new();
+    let draft = create_draft(&client, Some(API_KEY)).await;
+    let share_code = draft["share_code"].as_str().expect("Missing share code");
+    assert!(draft.get("edit_token").is_none());
+
+    let response = update_draft(&client, share_code, None, None).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = update_draft(&client, share_code, Some(API_KEY), None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let updated: Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(updated["name"], "Updated Draft");
+
+    let response = delete_draft(&client, share_code, None, None).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = delete_draft(&client, share_code, Some(API_KEY), None).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = get_draft(&client, share_code).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[rstest]
+#[case(None)]
+#[case(Some(API_KEY))]
+#[tokio::test]
+async fn test_missing_draft(#[case] api_key: Option<&str>) {
+    let client = Client::new();
+    let share_code = "AAAAAAAA";
+    assert_eq!(
+        get_draft(&client, share_code).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    let response = update_draft(&client, share_code, api_key, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = delete_draft(&client, share_code, api_key, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[rstest]
+#[case(json!({"hero_id": 10, "item_ids": [1548066885, 1548066885]}))]
+#[case(json!({"hero_id": 10, "item_ids": [1]}))]
+#[case(json!({"hero_id": 10, "name": "a".repeat(101)}))]
+#[tokio::test]
+async fn test_invalid_draft(#[case] request: Value) {
+    let response = Client::new()
+        .post(DRAFTS_URL)
+        .json(&request)
+        .send()
+        .await
+        .expect("Failed to get response");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[rstest]
+#[case(None, None)]
+#[case(Some("1548066885"), Some(1))]
+#[case(Some("1548066885,968099481"), Some(10))]
+#[tokio::test]
+async fn test_next_items(#[case] item_ids: Option<&str>, #[case] min_average_badge: Option<u8>) {
+    let min_average_badge = min_average_badge.map(|b| b.to_string());
+    let mut queries = vec![
+        ("hero_id", "15"),
+        ("min_matches", "1"),
+        ("min_unix_timestamp", "1741801678"),
+    ];
+    if let Some(item_ids) = item_ids {
+        queries.push(("item_ids", item_ids));
+    }
+    if let Some(min_average_badge) = &min_average_badge {
+        queries.push(("min_average_badge", min_average_badge));
+    }
+    let response = request_endpoint("/v1/build-creator/next-items", queries).await;
+    let next_items: Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(next_items["hero_id"], 15);
+    let items = next_items["items"].as_array().expect("Missing items");
+    for item in items {
+        let item_id = item["item_id"].as_u64().expect("Missing item id");
+        assert!(item_ids.is_none_or(|ids| ids.split(',').all(|id| id != item_id.to_string())));
+        assert!(item["matches"].as_u64().is_some_and(|m| m >= 1));
+    }
+}
+
+#[tokio::test]
+async fn test_next_items_too_many_items() {
+    let item_ids = (1..=65).join(",");
+    let response = Client::new()
+        .get(format!(
+            "http://localhost:3000/v1/build-creator/next-items?hero_id=15&item_ids={item_ids}"
+        ))
+        .header("X-API-Key", API_KEY)
+        .send()
+        .await
+        .expect("Failed to get response");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
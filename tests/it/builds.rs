@@ -1,10 +1,14 @@
 #![allow(clippy::too_many_arguments)]
 
 use deadlock_api_rust::routes::v1::builds::query::BuildsSearchQuerySortBy;
-use deadlock_api_rust::routes::v1::builds::structs::Build;
+use deadlock_api_rust::routes::v1::builds::structs::{
+    Build, BuildChange, BuildPerformance, BuildTagCount, BuildVersionHistory, BuildsSearchResponse,
+    SimilarBuild,
+};
 use deadlock_api_rust::utils::types::SortDirectionDesc;
 use itertools::Itertools;
 use rstest::rstest;
+use serde_json::Value;
 
 use crate::request_endpoint;
 
@@ -138,6 +142,7 @@ async fn test_builds(
             BuildsSearchQuerySortBy::PublishedAt => {
                 build.hero_build.publish_timestamp.map(|t| t as u32)
             }
+            BuildsSearchQuerySortBy::Relevance | BuildsSearchQuerySortBy::Performance => None,
         };
         key.unwrap_or_default()
     }
@@ -215,3 +220,311 @@ async fn test_builds(
         }
     }
 }
+
+#[rstest]
+#[case(None)]
+#[case(Some(true))]
+#[tokio::test]
+async fn test_builds_search_pages(#[case] only_latest: Option<bool>) {
+    let mut queries = vec![("hero_id", "10".to_string()), ("limit", "10".to_string())];
+    if let Some(only_latest) = only_latest {
+        queries.push(("only_latest", only_latest.to_string()));
+    }
+    let query_args = queries
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .collect::<Vec<_>>();
+    let response = request_endpoint("/v1/builds/search", query_args.clone()).await;
+    let first_page: BuildsSearchResponse = response.json().await.expect("Failed to parse response");
+    assert!(first_page.builds.len() <= 10);
+    let total = first_page.total.expect("First page has no total");
+    assert!(total >= first_page.builds.len() as u64);
+    assert_eq!(first_page.has_more, first_page.next_cursor.is_some());
+    assert_eq!(first_page.has_more, total > 10);
+
+    let Some(cursor) = first_page.next_cursor else {
+        return;
+    };
+    let response = request_endpoint(
+        "/v1/builds/search",
+        query_args.into_iter().chain([("cursor", cursor.as_str())]),
+    )
+    .await;
+    let second_page: BuildsSearchResponse =
+        response.json().await.expect("Failed to parse response");
+    assert!(second_page.total.is_none());
+    assert!(!second_page.builds.is_empty());
+    assert_eq!(second_page.has_more, second_page.next_cursor.is_some());
+
+    let first_versions = first_page
+        .builds
+        .iter()
+        .map(|b| (b.hero_build.hero_build_id, b.hero_build.version))
+        .collect::<Vec<_>>();
+    for build in &second_page.builds {
+        assert!(
+            !first_versions.contains(&(build.hero_build.hero_build_id, build.hero_build.version)),
+            "Build {} version {} is on both pages",
+            build.hero_build.hero_build_id,
+            build.hero_build.version
+        );
+    }
+}
+
+#[rstest]
+#[case(&[2010028405, 1998374645, 395867183], None, None)]
+#[case(&[2010028405, 1998374645, 395867183], Some(0.1), Some(5))]
+#[case(&[2010028405], Some(0.0), Some(100))]
+#[tokio::test]
+async fn test_similar_builds(
+    #[case] item_ids: &[u32],
+    #[case] min_similarity: Option<f64>,
+    #[case] limit: Option<u32>,
+) {
+    let mut queries = vec![
+        ("hero_id", "10".to_string()),
+        (
+            "item_ids",
+            item_ids.iter().map(ToString::to_string).join(","),
+        ),
+    ];
+    if let Some(min_similarity) = min_similarity {
+        queries.push(("min_similarity", min_similarity.to_string()));
+    }
+    if let Some(limit) = limit {
+        queries.push(("limit", limit.to_string()));
+    }
+    let queries = queries
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .collect::<Vec<_>>();
+    let response = request_endpoint("/v1/builds/similar", queries).await;
+    let builds: Vec<SimilarBuild> = response.json().await.expect("Failed to parse response");
+    assert!(!builds.is_empty());
+    if let Some(limit) = limit {
+        assert!(builds.len() <= limit as usize);
+    }
+    assert!(builds.is_sorted_by(|a, b| a.similarity >= b.similarity));
+    assert_eq!(
+        builds
+            .iter()
+            .unique_by(|b| b.build.hero_build.hero_build_id)
+            .count(),
+        builds.len()
+    );
+    for build in &builds {
+        assert_eq!(build.build.hero_build.hero_id, 10);
+        assert!(build.similarity > 0.0 && build.similarity <= 1.0);
+        assert!(build.similarity >= min_similarity.unwrap_or_default());
+        assert!(!build.matching_item_ids.is_empty());
+        for item_id in item_ids {
+            assert_ne!(
+                build.matching_item_ids.contains(item_id),
+                build.missing_item_ids.contains(item_id)
+            );
+        }
+    }
+}
+
+#[rstest]
+#[case(227099)]
+#[case(254091)]
+#[tokio::test]
+async fn test_build_history(#[case] build_id: u32) {
+    let response = request_endpoint(&format!("/v1/builds/{build_id}/history"), []).await;
+    let history: BuildVersionHistory = response.json().await.expect("Failed to parse response");
+    assert_eq!(history.hero_build_id, build_id);
+    assert_eq!(history.hero_id, 10);
+    assert!(!history.versions.is_empty());
+    assert!(
+        history
+            .versions
+            .is_sorted_by_key(|v| v.build.hero_build.version)
+    );
+    assert!(history.versions[0].diff.is_none());
+    for (previous, version) in history.versions.iter().tuple_windows() {
+        assert_eq!(version.build.hero_build.hero_build_id, build_id);
+        let diff = version.diff.as_ref().expect("Missing diff");
+        assert_eq!(diff.previous_version, previous.build.hero_build.version);
+    }
+}
+
+#[rstest]
+#[case(227099, None)]
+#[case(227099, Some(0.8))]
+#[case(254091, Some(0.3))]
+#[tokio::test]
+async fn test_build_performance(#[case] build_id: u32, #[case] min_overlap: Option<f64>) {
+    let mut queries = vec![];
+    if let Some(min_overlap) = min_overlap {
+        queries.push(("min_overlap", min_overlap.to_string()));
+    }
+    let queries = queries
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .collect::<Vec<_>>();
+    let response = request_endpoint(&format!("/v1/builds/{build_id}/performance"), queries).await;
+    let performance: BuildPerformance = response.json().await.expect("Failed to parse response");
+    assert_eq!(performance.hero_build_id, build_id);
+    assert_eq!(performance.hero_id, 10);
+    assert!((performance.min_overlap - min_overlap.unwrap_or(0.5)).abs() < f64::EPSILON);
+    assert!(performance.matches <= performance.hero_matches);
+    assert!((0.0..=1.0).contains(&performance.usage_rate));
+    assert!((0.0..=1.0).contains(&performance.wilson_lower_bound));
+    assert_eq!(performance.winrate.is_some(), performance.matches > 0);
+    if let Some(winrate) = performance.winrate {
+        assert!((0.0..=1.0).contains(&winrate));
+        assert!(performance.wilson_lower_bound <= winrate);
+    }
+}
+
+#[rstest]
+#[case(None, None, None)]
+#[case(Some(10), Some("favorites"), Some(5))]
+#[case(Some(10), Some("weekly_favorites"), None)]
+#[case(None, Some("num_builds"), Some(10))]
+#[tokio::test]
+async fn test_build_authors(
+    #[case] hero_id: Option<u32>,
+    #[case] sort_by: Option<&str>,
+    #[case] limit: Option<u32>,
+) {
+    let mut queries = vec![];
+    if let Some(hero_id) = hero_id {
+        queries.push(("hero_id", hero_id.to_string()));
+    }
+    if let Some(sort_by) = sort_by {
+        queries.push(("sort_by", sort_by.to_string()));
+    }
+    if let Some(limit) = limit {
+        queries.push(("limit", limit.to_string()));
+    }
+    let queries = queries
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .collect::<Vec<_>>();
+    let response = request_endpoint("/v1/builds/authors", queries).await;
+    let authors: Vec<Value> = response.json().await.expect("Failed to parse response");
+    assert!(!authors.is_empty());
+    assert!(authors.len() <= limit.unwrap_or(100) as usize);
+
+    let sort_by = sort_by.unwrap_or("favorites");
+    assert!(
+        authors
+            .iter()
+            .rev()
+            .is_sorted_by_key(|a| a[sort_by].as_u64().expect("Missing sort key")),
+        "Authors are not sorted by {sort_by}"
+    );
+    for author in &authors {
+        assert!(author["num_builds"].as_u64().is_some_and(|n| n > 0));
+        if let Some(hero_id) = hero_id {
+            assert_eq!(author["hero_ids"], serde_json::json!([hero_id]));
+        }
+    }
+}
+
+#[rstest]
+#[case(846183775)]
+#[tokio::test]
+async fn test_build_author(#[case] account_id: u32) {
+    let response = request_endpoint(&format!("/v1/builds/authors/{account_id}"), []).await;
+    let author: Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(author["account_id"], account_id);
+
+    let builds: Vec<Build> =
+        serde_json::from_value(author["builds"].clone()).expect("Failed to parse builds");
+    assert_eq!(author["num_builds"], builds.len());
+    assert_eq!(
+        builds
+            .iter()
+            .unique_by(|b| b.hero_build.hero_build_id)
+            .count(),
+        builds.len()
+    );
+    for build in &builds {
+        assert_eq!(build.hero_build.author_account_id, account_id);
+    }
+}
+
+#[rstest]
+#[case(None)]
+#[case(Some(10))]
+#[tokio::test]
+async fn test_build_tags(#[case] hero_id: Option<u32>) {
+    let mut queries = vec![];
+    if let Some(hero_id) = hero_id {
+        queries.push(("hero_id", hero_id.to_string()));
+    }
+    let queries = queries
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .collect::<Vec<_>>();
+    let response = request_endpoint("/v1/builds/tags", queries).await;
+    let tags: Vec<BuildTagCount> = response.json().await.expect("Failed to parse response");
+    assert!(!tags.is_empty());
+    assert_eq!(
+        tags.iter().unique_by(|t| (t.tag, t.hero_id)).count(),
+        tags.len()
+    );
+    for tag in &tags {
+        assert!(tag.num_builds > 0);
+        if let Some(hero_id) = hero_id {
+            assert_eq!(tag.hero_id, hero_id);
+        }
+    }
+}
+
+#[rstest]
+#[case(None)]
+#[case(Some(10))]
+#[tokio::test]
+async fn test_build_changes(#[case] hero_id: Option<u32>) {
+    let mut queries = vec![("since", "0".to_string())];
+    if let Some(hero_id) = hero_id {
+        queries.push(("hero_id", hero_id.to_string()));
+    }
+    let queries = queries
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .collect::<Vec<_>>();
+    let mut response = request_endpoint("/v1/builds/changes", queries).await;
+
+    // The feed never ends, read it until a few complete events arrived
+    let mut body = String::new();
+    let events = loop {
+        let events = body.rsplit_once("\n\n").map_or(vec![], |(complete, _)| {
+            complete
+                .split("\n\n")
+                .filter(|event| event.contains("event: build"))
+                .map(ToOwned::to_owned)
+                .collect::<Vec<_>>()
+        });
+        if events.len() >= 3 {
+            break events;
+        }
+        let chunk = response
+            .chunk()
+            .await
+            .expect("Failed to read the feed")
+            .expect("The feed ended");
+        body.push_str(&String::from_utf8_lossy(&chunk));
+    };
+    let changes = events
+        .iter()
+        .map(|event| {
+            let data = event
+                .lines()
+                .find_map(|line| line.strip_prefix("data:"))
+                .expect("Event has no data");
+            serde_json::from_str::<BuildChange>(data.trim()).expect("Failed to parse change")
+        })
+        .collect::<Vec<_>>();
+    assert!(changes.len() >= 3);
+    assert!(changes.is_sorted_by_key(|c| c.updated_at));
+    for change in &changes {
+        if let Some(hero_id) = hero_id {
+            assert_eq!(change.build.hero_build.hero_id, hero_id);
+        }
+    }
+}
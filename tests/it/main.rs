@@ -51,6 +51,7 @@ pub async fn request_endpoint(
 }
 
 mod analytics;
+mod build_creator;
 mod builds;
 mod info;
 mod patches;